@@ -1,110 +1,223 @@
 extern crate serde;
 extern crate serde_json;
 
-use std::fs::File;
-use std::error::Error;
+use std::fs::{self, File};
 use std::io::Read;
 use std::collections::BTreeMap;
+use self::serde_json::Value as Json;
 use syntax::parser::Parser;
 use syntax::symbol_table::SymbolTable;
+use syntax::type_check::TypeChecker;
+use syntax::value::Value;
 use generate::gen::Gen;
 use error::error_traits::Diagnostic;
+use error::gen_err::GenDiagnostic;
+use error::parse_err::ParseDiagnostic;
+use error::type_err::TypeDiagnostic;
+use error::tank_err::TankError;
+
+/// Name shown in the errors of a template rendered from a string.
+const STRING_FILENAME: &str = "<string>";
 
 pub struct Compiler {
     /// Unique parser for this compiler. Parsers and compilers are created
     /// for every file that needs to be fully compiled
     parser: Parser,
     /// Name of file to compile
-    filename: String
+    filename: String,
+    /// Contents of the file, used to show the source of any errors
+    source: String
 }
 
 impl Compiler {
-    pub fn new(m_file: &mut File, filename: &String) -> Compiler {
-        let mut file_contents = String::new();
-
-        match m_file.read_to_string(&mut file_contents) {
-            Err(error) => panic!("Failed to read {}: {}",
-                                 &filename,
-                                 Error::description(&error)),
-            Ok(_) => ()
-        }
+    /// Creates a new compiler for a template file. Fails if the file can't be read.
+    pub fn new(m_file: &mut File, filename: &String) -> Result<Compiler, TankError> {
+        let file_contents = read_file(m_file, filename)?;
 
         let sym_tab = SymbolTable::new();
-        let parser = Parser::new(file_contents, sym_tab);
+        let parser = Parser::new(file_contents.clone(), sym_tab);
 
-        Compiler {
-            parser: parser,
-            filename: filename.to_owned()
-        }
+        Ok(Compiler {
+            parser,
+            filename: filename.to_owned(),
+            source: file_contents
+        })
     }
 
     /// Create a new compiler using a JSON "config" file. This file is
     /// expected to contain other variables or information not declared
     /// within the template expected to be compiled. The scoping of these
     /// config vars will be global for the current file.
+    ///
+    /// Each json value becomes the matching typed value: whole numbers become ints, other
+    /// numbers floats, arrays lists and objects maps. Lists can be iterated over with a
+    /// for loop. Fails if either file can't be read, or the config isn't a json object.
     pub fn from_config_file(m_file: &mut File,
                             filename: &String,
-                            config_file: &mut File) -> Compiler {
-        let mut config_file_contents = String::new();
-        match config_file.read_to_string(&mut config_file_contents) {
-            Err(error) => panic!("Failed to read config file: {}",
-                                 Error::description(&error)),
-            Ok(_) => ()
-        }
+                            config_file: &mut File) -> Result<Compiler, TankError> {
+        let config_file_contents = read_file(config_file, "config file")?;
 
-        let input_map: BTreeMap<String, String> = serde_json::from_str(&config_file_contents)
-            .unwrap();
-        let sym_tab = SymbolTable::from_existing_map(&input_map);
-        let mut file_contents = String::new();
+        let input_map: BTreeMap<String, Json> = serde_json::from_str(&config_file_contents)
+            .map_err(|error| TankError::Config(error.to_string()))?;
 
-        match m_file.read_to_string(&mut file_contents) {
-            Err(error) => panic!("Failed to read {}: {}",
-                                 &filename,
-                                 Error::description(&error)),
-            Ok(_) => ()
-        }
+        let values: BTreeMap<String, Value> = input_map.iter()
+            .map(|(name, value)| (name.to_owned(), json_to_value(value)))
+            .collect();
 
-        let parser = Parser::new(file_contents, sym_tab);
+        let sym_tab = SymbolTable::from_existing_map(&values);
+        let file_contents = read_file(m_file, filename)?;
 
-        Compiler {
-            parser: parser,
-            filename: filename.to_owned()
-        }
+        let parser = Parser::new(file_contents.clone(), sym_tab);
+
+        Ok(Compiler {
+            parser,
+            filename: filename.to_owned(),
+            source: file_contents
+        })
     }
 
     /// Given a file and a parser created by the new functions,
     /// this function compiles a .tank file and writes the output
-    /// to the corresponding .html file.
-    pub fn compile(&mut self) -> &Compiler {
+    /// to the corresponding .html file. The template is type checked
+    /// once it is parsed, and the html is generated in memory before it
+    /// is written, so nothing is written if there are any errors.
+    pub fn compile(&mut self) -> Result<(), TankError> {
         println!("tank: Compiling '{}'...", &self.filename);
 
+        // Before parsing, the symbol table only holds the variables from the config file.
+        let mut checker = TypeChecker::new(self.parser.symbol_table.clone());
+
         self.parser.parse();
 
-        self.check_diag(&self.parser.diagnostic);
+        check_parse_diag(&self.parser.diagnostic, &self.filename, &self.source)?;
+
+        checker.check(&self.parser.root);
+
+        check_type_diag(&checker.diagnostic, &self.filename, &self.source)?;
 
         let ast = &self.parser.root;
         let sym = self.parser.symbol_table.clone();
-        let mut gen = Gen::new(&self.filename, sym);
+        let mut gen = Gen::from_writer(Vec::new(), sym);
 
         gen.output(ast);
 
-        self.check_diag(&gen.diagnostic);
+        check_gen_diag(&gen.diagnostic, &self.filename, &self.source)?;
+
+        // Only create the file once generation has succeeded, so that an error part way
+        // through doesn't leave a partial html file behind.
+        let out_filename = self.filename.to_owned() + ".html";
+        let html = gen.into_writer()
+            .map_err(|error| TankError::Io(out_filename.clone(), error))?;
+
+        fs::write(&out_filename, html)
+            .map_err(|error| TankError::Io(out_filename, error))?;
 
-        self
+        Ok(())
     }
 
-    /// Checks the appropriate Diagnostic struct belonging to either the
-    /// parser or the generator. Prints any available diagnostic messages
-    /// and then checks for fatal errors. If they exist, we will panic and
-    /// exit the program without generating anything.
-    fn check_diag(&self, diagnostic: &Diagnostic) {
-        if diagnostic.has_diag() {
-            diagnostic.print_diag();
-        }
+    /// Render a template to a string in memory, rather than to a file. The context holds
+    /// the variables available to the template, in the same way as a config file. Returns
+    /// the generated html, or the errors if the template couldn't be compiled.
+    pub fn render_to_string(source: &str,
+                            context: &BTreeMap<String, Value>) -> Result<String, TankError> {
+        let sym_tab = SymbolTable::from_existing_map(context);
+        let mut checker = TypeChecker::new(sym_tab.clone());
+        let mut parser = Parser::new(source.to_owned(), sym_tab);
+
+        parser.parse();
+
+        check_parse_diag(&parser.diagnostic, STRING_FILENAME, source)?;
+
+        checker.check(&parser.root);
+
+        check_type_diag(&checker.diagnostic, STRING_FILENAME, source)?;
+
+        let mut gen = Gen::from_writer(Vec::new(), parser.symbol_table.clone());
+
+        gen.output(&parser.root);
+
+        check_gen_diag(&gen.diagnostic, STRING_FILENAME, source)?;
+
+        let html = gen.into_writer()
+            .map_err(|error| TankError::Io("rendered html".to_owned(), error))?;
+
+        // Only strs are ever written, so the html is always valid utf-8.
+        Ok(String::from_utf8(html).expect("tank: Generated html is not valid utf-8"))
+    }
+}
+
+/// Read the whole of a template or config file into a string.
+fn read_file(file: &mut File, filename: &str) -> Result<String, TankError> {
+    let mut file_contents = String::new();
+
+    file.read_to_string(&mut file_contents)
+        .map_err(|error| TankError::Io(filename.to_owned(), error))?;
+
+    Ok(file_contents)
+}
+
+/// Checks the parser's diagnostics. Any warnings are printed, and any errors are
+/// returned so that nothing is generated.
+fn check_parse_diag(diagnostic: &ParseDiagnostic, filename: &str, source: &str) -> Result<(), TankError> {
+    if diagnostic.is_err() {
+        return Err(TankError::Parse(diagnostic.located_reports(filename, source)));
+    }
+
+    if diagnostic.has_diag() {
+        diagnostic.print_diag(filename, source);
+    }
+
+    Ok(())
+}
+
+/// Same as check_parse_diag(), for the type checker. Any errors are returned as type errors.
+fn check_type_diag(diagnostic: &TypeDiagnostic, filename: &str, source: &str) -> Result<(), TankError> {
+    if diagnostic.is_err() {
+        return Err(TankError::Type(diagnostic.located_reports(filename, source)));
+    }
+
+    if diagnostic.has_diag() {
+        diagnostic.print_diag(filename, source);
+    }
+
+    Ok(())
+}
+
+/// Same as check_parse_diag(), for the generator. If any values didn't match their
+/// declared types, the errors are returned as type errors.
+fn check_gen_diag(diagnostic: &GenDiagnostic, filename: &str, source: &str) -> Result<(), TankError> {
+    if diagnostic.has_type_err() {
+        return Err(TankError::Type(diagnostic.located_reports(filename, source)));
+    }
+
+    if diagnostic.is_err() {
+        return Err(TankError::Gen(diagnostic.located_reports(filename, source)));
+    }
+
+    if diagnostic.has_diag() {
+        diagnostic.print_diag(filename, source);
+    }
+
+    Ok(())
+}
 
-        if diagnostic.is_err() {
-            // TODO: remove possibly generated file here?
-            panic!("tank: Could not compile {}", &self.filename);
+/// Convert a value from the config file to the typed value stored in the symbol table.
+fn json_to_value(json: &Json) -> Value {
+    match *json {
+        Json::Null => Value::Null,
+        Json::Bool(b) => Value::Bool(b),
+        Json::Number(ref n) => {
+            match n.as_i64() {
+                Some(i) => Value::Int(i),
+                None => Value::Float(n.as_f64().unwrap_or_default())
+            }
+        },
+        Json::String(ref s) => Value::Str(s.to_owned()),
+        Json::Array(ref items) => Value::List(items.iter().map(json_to_value).collect()),
+        Json::Object(ref map) => {
+            Value::Map(map.iter()
+                .map(|(key, item)| (key.to_owned(), json_to_value(item)))
+                .collect())
         }
     }
 }
@@ -1,10 +1,60 @@
+use std::io::{self, IsTerminal};
+use error::report::{Level, Report};
+
 /// Trait for providing some simple error and warning messages for compiler errors.
 /// This is used by the generator and the parser for some simple diagnostic
 /// information when errors occur.
+///
+/// Implementations only need to store the reports. Checking and rendering them
+/// is shared by the default methods.
 pub trait Diagnostic {
-    fn is_err(&self) -> bool;
-    fn is_warn(&self) -> bool;
-    fn has_diag(&self) -> bool;
-    fn new_err(&mut self, err_message: &str);
-    fn print_diag(&self);
+    /// Add a report, and return it so that help and notes can be added to it.
+    fn report(&mut self, report: Report) -> &mut Report;
+    fn reports(&self) -> &[Report];
+
+    fn is_err(&self) -> bool {
+        self.reports().iter().any(|report| report.level == Level::Error)
+    }
+
+    fn is_warn(&self) -> bool {
+        self.reports().iter().any(|report| report.level == Level::Warning)
+    }
+
+    fn has_diag(&self) -> bool {
+        !self.reports().is_empty()
+    }
+
+    /// Report an error that doesn't refer to any particular source.
+    fn new_err(&mut self, err_message: &str) {
+        self.report(Report::error(err_message));
+    }
+
+    /// The messages of all of the errors reported, without their locations.
+    fn errors(&self) -> Vec<String> {
+        self.reports().iter()
+            .filter(|report| report.level == Level::Error)
+            .map(|report| report.message.clone())
+            .collect()
+    }
+
+    /// Returns a copy of all the reports, located in the given template so that they
+    /// can be rendered with the source they refer to.
+    fn located_reports(&self, filename: &str, source: &str) -> Vec<Report> {
+        self.reports().iter()
+            .cloned()
+            .map(|mut report| {
+                report.locate(filename, source);
+                report
+            })
+            .collect()
+    }
+
+    /// Print all of the reports to stderr, in colour if stderr is a terminal.
+    fn print_diag(&self, filename: &str, source: &str) {
+        let color = io::stderr().is_terminal();
+
+        for report in self.located_reports(filename, source) {
+            eprintln!("{}\n", report.render(color));
+        }
+    }
 }
@@ -1,10 +1,14 @@
 use std::default::Default;
 use error::error_traits::Diagnostic;
+use error::report::Report;
+use syntax::span::Span;
 
 #[derive(Default)]
 pub struct GenDiagnostic {
-    errors: Vec<String>,
-    warnings: Vec<String>
+    reports: Vec<Report>,
+    /// Set when a value doesn't match its declared type, so that the errors can
+    /// be reported as type errors.
+    has_type_err: bool
 }
 
 impl GenDiagnostic {
@@ -12,38 +16,30 @@ impl GenDiagnostic {
         Default::default()
     }
 
-    pub fn fatal(&self, fatal_message: &str) {
-        panic!("tank: FATAL ERROR - ".to_owned() + fatal_message);
-    }
-}
-
-impl Diagnostic for GenDiagnostic {
-    fn is_err(&self) -> bool {
-        self.errors.len() != 0
+    /// Report an error in the source at the given span. The report is returned, so
+    /// that help and notes can be added to it.
+    pub fn err_at(&mut self, err_message: &str, span: &Span) -> &mut Report {
+        self.report(Report::error(err_message).at(*span))
     }
 
-    fn is_warn(&self) -> bool {
-        self.warnings.len() != 0
+    /// Report a value that doesn't match the type it was declared with.
+    pub fn type_err(&mut self, err_message: &str, span: &Span) -> &mut Report {
+        self.has_type_err = true;
+        self.err_at(err_message, span)
     }
 
-    fn has_diag(&self) -> bool {
-        self.is_err() || self.is_warn()
+    pub fn has_type_err(&self) -> bool {
+        self.has_type_err
     }
+}
 
-    fn new_err(&mut self, err_message: &str) {
-        self.errors.push(err_message.to_owned());
+impl Diagnostic for GenDiagnostic {
+    fn report(&mut self, report: Report) -> &mut Report {
+        self.reports.push(report);
+        self.reports.last_mut().unwrap()
     }
 
-    fn print_diag(&self) {
-        for err in &self.errors {
-            println!("{}", err);
-        }
-
-        for warn in &self.warnings {
-            println!("{}", warn);
-        }
-
-        // An extra line here makes the messages a bit more readable before exiting.
-        print!("\n");
+    fn reports(&self) -> &[Report] {
+        &self.reports
     }
 }
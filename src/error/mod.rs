@@ -1,3 +1,6 @@
 pub mod parse_err;
 pub mod gen_err;
+pub mod type_err;
 pub mod error_traits;
+pub mod tank_err;
+pub mod report;
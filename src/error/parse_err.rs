@@ -1,56 +1,38 @@
 use error::error_traits::Diagnostic;
+use error::report::Report;
 use syntax::token::Token;
 
 pub struct ParseDiagnostic {
-    errors: Vec<String>,
-    warnings: Vec<String>
+    reports: Vec<Report>
+}
+
+impl Default for ParseDiagnostic {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ParseDiagnostic {
     pub fn new() -> ParseDiagnostic {
         ParseDiagnostic {
-            errors: Vec::new(),
-            warnings: Vec::new()
+            reports: Vec::new()
         }
     }
 
-    pub fn parse_err(&mut self, err: &String, token: &Token) {
-        let err_str = format!("tank: Parse error at line {}, pos {} - {}",
-                              token.line_num,
-                              token.char_pos,
-                              err);
-
-        self.errors.push(err_str.to_owned());
+    /// Report an error at the given token. The report is returned, so that help and
+    /// notes can be added to it.
+    pub fn parse_err(&mut self, err: &str, token: &Token) -> &mut Report {
+        self.report(Report::error(err).at(token.span))
     }
 }
 
 impl Diagnostic for ParseDiagnostic {
-    fn is_err(&self) -> bool {
-        self.errors.len() != 0
-    }
-
-    fn is_warn(&self) -> bool {
-        self.warnings.len() != 0
-    }
-
-    fn has_diag(&self) -> bool {
-        self.is_err() || self.is_warn()
-    }
-
-    fn new_err(&mut self, err_message: &str) {
-        self.errors.push(err_message.to_owned());
+    fn report(&mut self, report: Report) -> &mut Report {
+        self.reports.push(report);
+        self.reports.last_mut().unwrap()
     }
 
-    fn print_diag(&self) {
-        for err in &self.errors {
-            println!("{}", err);
-        }
-
-        for warn in &self.warnings {
-            println!("{}", warn);
-        }
-
-        // An extra line here makes the messages a bit more readable before exiting.
-        print!("\n");
+    fn reports(&self) -> &[Report] {
+        &self.reports
     }
 }
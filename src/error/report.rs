@@ -0,0 +1,237 @@
+use std::fmt;
+use syntax::span::Span;
+
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Level {
+    Error,
+    Warning
+}
+
+/// A single error or warning found in a template. Reports are created by the parser and
+/// the generator with the span of the source they refer to, and are located in their
+/// template once it is known, so that they can be rendered with the offending line:
+///
+/// ```text
+/// error: Expected RightParen, found Arrow
+///  --> index.tank:3:12
+///   |
+/// 3 | div (id: x -> hi
+///   |            ^^
+///   = help: close the attribute list with ')'
+/// ```
+#[derive(Debug, PartialEq, Clone)]
+pub struct Report {
+    pub level: Level,
+    pub message: String,
+    /// Location of the source the report refers to, if it refers to any
+    pub span: Option<Span>,
+    /// Suggestions for fixing the problem
+    pub help: Vec<String>,
+    /// Any other information about the problem
+    pub notes: Vec<String>,
+    /// Name of the template the report belongs to, set by locate()
+    pub filename: Option<String>,
+    /// Line of the template containing the start of the span, set by locate()
+    pub source_line: Option<String>
+}
+
+impl Report {
+    pub fn new(level: Level, message: &str) -> Report {
+        Report {
+            level,
+            message: message.to_owned(),
+            span: None,
+            help: Vec::new(),
+            notes: Vec::new(),
+            filename: None,
+            source_line: None
+        }
+    }
+
+    pub fn error(message: &str) -> Report {
+        Report::new(Level::Error, message)
+    }
+
+    pub fn warning(message: &str) -> Report {
+        Report::new(Level::Warning, message)
+    }
+
+    /// Set the location in the source that the report refers to.
+    pub fn at(mut self, span: Span) -> Report {
+        self.span = Some(span);
+        self
+    }
+
+    /// Add a suggestion for fixing the problem, shown as '= help: ...'.
+    pub fn with_help(&mut self, help: &str) -> &mut Report {
+        self.help.push(help.to_owned());
+        self
+    }
+
+    /// Add some more information about the problem, shown as '= note: ...'.
+    pub fn with_note(&mut self, note: &str) -> &mut Report {
+        self.notes.push(note.to_owned());
+        self
+    }
+
+    /// Record the template the report belongs to, and the line its span starts on.
+    /// A report that has already been located, such as one from an included template,
+    /// is left as it is.
+    pub fn locate(&mut self, filename: &str, source: &str) {
+        if self.filename.is_some() {
+            return;
+        }
+
+        self.filename = Some(filename.to_owned());
+        self.source_line = self.span
+            .and_then(|span| source.lines().nth(span.line.saturating_sub(1)))
+            .map(|line| line.to_owned());
+    }
+
+    /// Render the report, as shown in the docs for this struct. If color is true, the
+    /// output includes ansi colour codes for a terminal.
+    pub fn render(&self, color: bool) -> String {
+        let paint = |code: &str, text: &str| {
+            if color {
+                format!("{}{}{}", code, text, RESET)
+            } else {
+                text.to_owned()
+            }
+        };
+
+        let (label, label_color) = match self.level {
+            Level::Error => ("error", RED),
+            Level::Warning => ("warning", YELLOW)
+        };
+
+        let mut out = format!("{}{}", paint(label_color, label), paint(BOLD, &format!(": {}", self.message)));
+
+        let line_num = self.span.map(|span| span.line.to_string()).unwrap_or_default();
+        let gutter = " ".repeat(line_num.len());
+
+        if let Some(span) = self.span {
+            let filename = self.filename.as_ref().map_or("<template>", |name| name.as_ref());
+            out += &format!("\n{}{} {}:{}:{}", gutter, paint(BLUE, "-->"), filename, span.line, span.col);
+
+            if let Some(ref line) = self.source_line {
+                let bar = paint(BLUE, "|");
+                let (indent, width) = underline(line, &span);
+
+                out += &format!("\n{} {}", gutter, bar);
+                out += &format!("\n{} {} {}", paint(BLUE, &line_num), bar, line);
+                out += &format!("\n{} {} {}{}", gutter, bar, indent, paint(label_color, &"^".repeat(width)));
+            }
+        }
+
+        for (kind, lines) in &[("help", &self.help), ("note", &self.notes)] {
+            for line in lines.iter() {
+                out += &format!("\n{} {} {}: {}", gutter, paint(BLUE, "="), paint(BOLD, kind), line);
+            }
+        }
+
+        out
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.render(false))
+    }
+}
+
+/// Returns the indentation that lines up with the start of the span in the given source
+/// line, and the width of the underline beneath it. Tabs in the line are kept in the
+/// indentation so that it lines up however they are displayed. A span covering more than
+/// one line is underlined up to the end of its first line.
+///
+/// The column of a span counts chars, while its start and end are byte offsets, so the
+/// width is the number of chars in the line that fall inside the span's bytes.
+fn underline(line: &str, span: &Span) -> (String, usize) {
+    let start = span.col.saturating_sub(1);
+
+    let indent: String = line.chars()
+        .take(start)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+
+    let span_len = span.end.saturating_sub(span.start);
+    let mut bytes = 0;
+    let width = line.chars()
+        .skip(start)
+        .take_while(|c| {
+            let in_span = bytes < span_len;
+            bytes += c.len_utf8();
+            in_span
+        })
+        .count()
+        .max(1);
+
+    (indent, width)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_report() {
+        let source = "p () -> ok\ndiv (id: x -> hi\n";
+        let mut report = Report::error("Expected RightParen, found Arrow").at(Span::new(22, 24, 2, 12));
+        report.with_help("close the attribute list with ')'");
+        report.locate("index.tank", source);
+
+        assert_eq!(report.render(false), "error: Expected RightParen, found Arrow\n \
+                                          --> index.tank:2:12\n  \
+                                          |\n\
+                                          2 | div (id: x -> hi\n  \
+                                          |            ^^\n  \
+                                          = help: close the attribute list with ')'");
+    }
+
+    #[test]
+    fn test_render_report_without_span() {
+        let mut report = Report::warning("Nothing to generate");
+        report.with_note("the template is empty");
+
+        assert_eq!(report.render(false), "warning: Nothing to generate\n = note: the template is empty");
+    }
+
+    #[test]
+    fn test_render_report_with_color() {
+        let report = Report::error("Bad").at(Span::new(0, 1, 1, 1));
+
+        let rendered = report.render(true);
+        assert!(rendered.starts_with("\x1b[1;31merror\x1b[0m"));
+        assert!(rendered.contains("<template>:1:1"));
+    }
+
+    #[test]
+    fn test_underline_keeps_tabs() {
+        let (indent, width) = underline("\tp () -> x", &Span::new(1, 2, 1, 2));
+
+        assert_eq!(indent, "\t".to_owned());
+        assert_eq!(width, 1);
+    }
+
+    #[test]
+    fn test_underline_non_ascii() {
+        // 'café' is 4 chars but 5 bytes, and starts at the 9th char of the line.
+        let line = "p () -> café crème -> x";
+        let start = line.find("café").unwrap();
+        let (indent, width) = underline(line, &Span::new(start, start + "café".len(), 1, 9));
+
+        assert_eq!(indent, " ".repeat(8));
+        assert_eq!(width, 4);
+
+        // The span is cut off at the end of the line.
+        let start = line.find("crème").unwrap();
+        let (_, width) = underline(line, &Span::new(start, line.len() + 10, 1, 14));
+        assert_eq!(width, 10);
+    }
+}
@@ -0,0 +1,62 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use error::report::Report;
+
+/// Error returned by the public entry points of tank, such as compiling a template.
+/// Parse, type and generation errors hold every report for the template, located in
+/// its source, so that all of them can be shown at once.
+#[derive(Debug)]
+pub enum TankError {
+    /// A template or config file couldn't be read, or the html couldn't be written.
+    /// Holds the name of the file along with the underlying error.
+    Io(String, io::Error),
+    /// The config file isn't a valid json object
+    Config(String),
+    /// The template isn't valid tank syntax
+    Parse(Vec<Report>),
+    /// The template failed type checking, such as a value that doesn't match the type
+    /// it was declared with, or a variable that isn't defined
+    Type(Vec<Report>),
+    /// The template is valid, but its html couldn't be generated, such as when it
+    /// refers to a variable that isn't defined
+    Gen(Vec<Report>)
+}
+
+impl TankError {
+    /// All of the reports describing the error, one per problem found.
+    pub fn reports(&self) -> Vec<Report> {
+        match *self {
+            TankError::Io(ref filename, ref error) => {
+                vec![Report::error(&format!("Failed to access {} - {}", filename, error))]
+            },
+            TankError::Config(ref message) => vec![Report::error(&format!("Invalid config file - {}", message))],
+            TankError::Parse(ref reports)
+            | TankError::Type(ref reports)
+            | TankError::Gen(ref reports) => reports.clone()
+        }
+    }
+
+    /// Render every report, with ansi colour codes for a terminal if color is true.
+    pub fn render(&self, color: bool) -> String {
+        self.reports().iter()
+            .map(|report| report.render(color))
+            .collect::<Vec<String>>()
+            .join("\n\n")
+    }
+}
+
+impl fmt::Display for TankError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.render(false))
+    }
+}
+
+impl Error for TankError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            TankError::Io(_, ref error) => Some(error),
+            _ => None
+        }
+    }
+}
@@ -0,0 +1,32 @@
+use std::default::Default;
+use error::error_traits::Diagnostic;
+use error::report::Report;
+use syntax::span::Span;
+
+#[derive(Default)]
+pub struct TypeDiagnostic {
+    reports: Vec<Report>
+}
+
+impl TypeDiagnostic {
+    pub fn new() -> TypeDiagnostic {
+        Default::default()
+    }
+
+    /// Report an error found by the type checker at the given span. The report is
+    /// returned, so that help and notes can be added to it.
+    pub fn type_err(&mut self, err_message: &str, span: &Span) -> &mut Report {
+        self.report(Report::error(err_message).at(*span))
+    }
+}
+
+impl Diagnostic for TypeDiagnostic {
+    fn report(&mut self, report: Report) -> &mut Report {
+        self.reports.push(report);
+        self.reports.last_mut().unwrap()
+    }
+
+    fn reports(&self) -> &[Report] {
+        &self.reports
+    }
+}
@@ -1,34 +1,69 @@
-use std::fs::File;
-use std::io::{BufWriter, Write};
-use std::error::Error;
-
-const LEFT_ANGLE_BRACKET: &'static str = "<";
-const RIGHT_ANGLE_BRACKET: &'static str = ">";
-const CLOSING_TAG: &'static str = "</";
-const EQUALS: &'static str = "=";
-const NEWLINE: &'static str = "\n";
-const QUOTE: &'static str = "\"";
-
-pub struct Emitter {
-    /// Buffer used to write to a particular file. The file is expected to
-    /// be opened/read elsewhere (probably in the Compiler struct)
-    writer: BufWriter<File>
+use std::borrow::Cow;
+use std::io::{self, Write};
+
+const LEFT_ANGLE_BRACKET: &str = "<";
+const RIGHT_ANGLE_BRACKET: &str = ">";
+const SELF_CLOSING_BRACKET: &str = " />";
+const CLOSING_TAG: &str = "</";
+const EQUALS: &str = "=";
+const NEWLINE: &str = "\n";
+const QUOTE: &str = "\"";
+const COMMENT_OPEN: &str = "<!-- ";
+const COMMENT_CLOSE: &str = " -->";
+const DOCTYPE_OPEN: &str = "<!DOCTYPE ";
+
+/// The context a value is written into, which decides the chars that must be escaped
+/// so that the value can't change the structure of the page.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Escape {
+    /// Element contents. '&', '<' and '>' are escaped.
+    Text,
+    /// A quoted attribute value. Quotes are escaped, as well as the chars escaped in text.
+    Attribute,
+    /// Trusted html, written exactly as it is.
+    Raw
+}
+
+pub struct Emitter<W: Write> {
+    /// Where the html is written to. This is usually a buffered file, opened
+    /// elsewhere (probably in the Gen struct), but can be any writer, such as
+    /// a Vec<u8> when rendering to a string.
+    writer: W,
+    /// The first error from the writer. Once writing fails, nothing more is
+    /// written, and the error is returned by into_inner().
+    error: Option<io::Error>
 }
 
-impl Emitter {
-    pub fn new(w: BufWriter<File>) -> Emitter {
+impl<W: Write> Emitter<W> {
+    pub fn new(w: W) -> Emitter<W> {
         Emitter {
-            writer: w
+            writer: w,
+            error: None
         }
     }
 
+    /// Flush anything still buffered, and return the writer. Fails if any of the
+    /// writes failed.
+    pub fn into_inner(mut self) -> io::Result<W> {
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+
+        self.writer.flush()?;
+
+        Ok(self.writer)
+    }
+
     /// Writes a string to the current file (determined by which file the BufWriter)
     /// is attached to.
     pub fn emit(&mut self, output: &str) {
-        match write!(self.writer, "{}", output) {
-            Err(error) => panic!("tank: Failed to write -  {}", Error::description(&error)),
-            Ok(_) => ()
-        };
+        if self.error.is_some() {
+            return;
+        }
+
+        if let Err(error) = write!(self.writer, "{}", output) {
+            self.error = Some(error);
+        }
     }
 
     /// Write a '<' symbol to file.
@@ -41,6 +76,11 @@ impl Emitter {
         self.emit(RIGHT_ANGLE_BRACKET);
     }
 
+    /// Write ' />' to file, to end the tag of a self-closing element.
+    pub fn self_closing_bracket(&mut self) {
+        self.emit(SELF_CLOSING_BRACKET);
+    }
+
     /// Write '=' symbol to file.
     pub fn equals(&mut self) {
         self.emit(EQUALS);
@@ -50,9 +90,9 @@ impl Emitter {
     /// to be passed in, and then produces '</el_name>', followed by a new line.
     pub fn close_element(&mut self, tag_value: &str) {
         let mut tag = String::from(CLOSING_TAG);
-        tag = tag + tag_value;
-        tag = tag + RIGHT_ANGLE_BRACKET;
-        tag = tag + NEWLINE;
+        tag += tag_value;
+        tag += RIGHT_ANGLE_BRACKET;
+        tag += NEWLINE;
 
         self.emit(&tag);
     }
@@ -67,8 +107,8 @@ impl Emitter {
         let mut spaces = String::from("");
         let mut i = 0;
         while i < count {
-            spaces = spaces + " ";
-            i = i + 1;
+            spaces += " ";
+            i += 1;
         }
 
         self.emit(&spaces);
@@ -81,8 +121,129 @@ impl Emitter {
         self.emit(&val);
     }
 
+    /// Writes the provided text as an html comment, followed by a new line.
+    /// Eg. <!-- text -->
+    ///
+    /// Any '--' in the text is split with a space, so the text can't close the comment
+    /// early with '-->' and write the rest of it into the page.
+    pub fn comment(&mut self, text: &str) {
+        let val = COMMENT_OPEN.to_owned() + &comment_text(text) + COMMENT_CLOSE + NEWLINE;
+
+        self.emit(&val);
+    }
+
+    /// Writes a document type declaration, followed by a new line. Eg. <!DOCTYPE html>
+    pub fn doctype(&mut self, doc_type: &str) {
+        let val = DOCTYPE_OPEN.to_owned() + doc_type + RIGHT_ANGLE_BRACKET + NEWLINE;
+
+        self.emit(&val);
+    }
+
+    /// Writes the body of a raw block exactly as it was written, followed by a new line
+    /// if it doesn't already end with one.
+    pub fn raw(&mut self, html: &str) {
+        if html.is_empty() {
+            return;
+        }
+
+        self.emit(html);
+
+        if !html.ends_with(NEWLINE) {
+            self.newline();
+        }
+    }
+
     /// Writes a new line to file.
     pub fn newline(&mut self) {
         self.emit(NEWLINE);
     }
 }
+
+/// Escape a value for the context it will be written into. The value is only copied
+/// if there is something to escape.
+pub fn escape(val: &str, context: Escape) -> Cow<'_, str> {
+    let needs_escape = |c: char| match c {
+        '&' | '<' | '>' => context != Escape::Raw,
+        '"' | '\'' => context == Escape::Attribute,
+        _ => false
+    };
+
+    if !val.contains(needs_escape) {
+        return Cow::Borrowed(val);
+    }
+
+    let mut escaped = String::with_capacity(val.len() + 8);
+    for c in val.chars() {
+        if !needs_escape(c) {
+            escaped.push(c);
+            continue;
+        }
+
+        escaped.push_str(match c {
+            '&' => "&amp;",
+            '<' => "&lt;",
+            '>' => "&gt;",
+            '"' => "&quot;",
+            _ => "&#39;"
+        });
+    }
+
+    Cow::Owned(escaped)
+}
+
+/// Split every '--' in the text of a comment with a space, copying the text only if
+/// it contains one.
+fn comment_text(text: &str) -> Cow<'_, str> {
+    if !text.contains("--") {
+        return Cow::Borrowed(text);
+    }
+
+    let mut split = String::with_capacity(text.len() + 4);
+    for c in text.chars() {
+        if c == '-' && split.ends_with('-') {
+            split.push(' ');
+        }
+
+        split.push(c);
+    }
+
+    Cow::Owned(split)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UNSAFE: &str = "<script>alert(\"x\" + 'y')</script> & more";
+
+    #[test]
+    fn test_escape_text() {
+        assert_eq!(escape(UNSAFE, Escape::Text),
+                   "&lt;script&gt;alert(\"x\" + 'y')&lt;/script&gt; &amp; more");
+    }
+
+    #[test]
+    fn test_escape_attribute() {
+        assert_eq!(escape(UNSAFE, Escape::Attribute),
+                   "&lt;script&gt;alert(&quot;x&quot; + &#39;y&#39;)&lt;/script&gt; &amp; more");
+    }
+
+    #[test]
+    fn test_escape_raw() {
+        assert_eq!(escape(UNSAFE, Escape::Raw), UNSAFE);
+    }
+
+    #[test]
+    fn test_escape_borrows_safe_value() {
+        assert!(matches!(escape("nothing to escape", Escape::Attribute), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_comment_cannot_close_early() {
+        let mut emitter = Emitter::new(Vec::new());
+        emitter.comment("a --> <script>x()</script> <!-- b --- c");
+
+        let output = String::from_utf8(emitter.into_inner().unwrap()).unwrap();
+        assert_eq!(output, "<!-- a - -> <script>x()</script> <!- - b - - - c -->\n");
+    }
+}
@@ -1,5 +1,35 @@
-use syntax::ast::Ast;
+use std::cmp::Ordering;
+use std::fmt;
+use syntax::ast::{BinaryOp, Expr, Ident, Number, UnaryOp, VarType};
+use syntax::span::Span;
 use syntax::symbol_table::SymbolTable;
+use syntax::value::Value;
+
+/// Result of evaluating an expression.
+pub type EvalResult<T> = Result<T, EvalError>;
+
+/// An error from evaluating an expression, with a message saying what went wrong
+/// and the span of the expression it went wrong in.
+#[derive(Debug, PartialEq, Clone)]
+pub struct EvalError {
+    pub message: String,
+    pub span: Span
+}
+
+impl EvalError {
+    pub fn new(message: String, span: Span) -> EvalError {
+        EvalError {
+            message,
+            span
+        }
+    }
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at {}", self.message, self.span)
+    }
+}
 
 pub struct Evaluator {
     pub symbol_table: SymbolTable
@@ -12,212 +42,486 @@ impl Evaluator {
         }
     }
 
-    pub fn gt(&mut self, ast: &Box<Ast>) -> bool {
-        let val_pair = self.unwrap_ast(ast);
-
-        val_pair.0 > val_pair.1
+    /// Evaluate a condition, such as the one in an if statement. A condition is a tree
+    /// of comparisons and boolean values, joined by '&&', '||' and '!'. The right hand
+    /// side of '&&' and '||' is only evaluated if it is needed to decide the result.
+    pub fn eval_bool(&mut self, expr: &Expr) -> EvalResult<bool> {
+        match *expr {
+            Expr::Binary { op: BinaryOp::And, ref lhs, ref rhs, .. } => {
+                Ok(self.eval_bool(lhs)? && self.eval_bool(rhs)?)
+            },
+            Expr::Binary { op: BinaryOp::Or, ref lhs, ref rhs, .. } => {
+                Ok(self.eval_bool(lhs)? || self.eval_bool(rhs)?)
+            },
+            Expr::Unary { op: UnaryOp::Not, ref operand, .. } => Ok(!self.eval_bool(operand)?),
+            Expr::Binary { op, ref lhs, ref rhs, span } if op.is_comparison() => {
+                match op {
+                    BinaryOp::EqualsEquals => self.equals(lhs, rhs),
+                    BinaryOp::NotEquals => Ok(!self.equals(lhs, rhs)?),
+                    BinaryOp::Gt => Ok(self.compare(lhs, rhs, span)? == Ordering::Greater),
+                    BinaryOp::GtEquals => Ok(self.compare(lhs, rhs, span)? != Ordering::Less),
+                    BinaryOp::Lt => Ok(self.compare(lhs, rhs, span)? == Ordering::Less),
+                    _ => Ok(self.compare(lhs, rhs, span)? != Ordering::Greater)
+                }
+            },
+            _ => {
+                match self.eval_expr(expr)? {
+                    Value::Bool(b) => Ok(b),
+                    other => Err(EvalError::new(format!("Expected a bool, found {}", other.type_name()),
+                                                expr.span()))
+                }
+            }
+        }
     }
 
-    pub fn gt_equals(&mut self, ast: &Box<Ast>) -> bool {
-        let val_pair = self.unwrap_ast(ast);
+    /// Evaluate the value of a variable declaration, check it against the declared type
+    /// and store it in the symbol table. The variable itself is declared during parsing,
+    /// but the value is computed here so that it can be any expression, including ones
+    /// using other variables.
+    pub fn assign(&mut self, var: &Ident, var_type: VarType, value: &Expr) -> EvalResult<()> {
+        // A bare word can only name a variable declared before it in the template.
+        let start = value.span().start;
+        let bare_word = value.bare_word(|name| self.symbol_table.is_declared_before(name, start));
+
+        let val = match bare_word {
+            Some(word) => Value::Str(word.to_owned()),
+            None => self.eval_expr(value)?
+        };
+
+        if !val.matches_type(var_type) {
+            return Err(EvalError::new(format!("Type mismatch - '{}' is declared as {}, but its value is {}",
+                                              var.name,
+                                              var_type,
+                                              val.type_name()),
+                                      value.span()));
+        }
 
-        val_pair.0 >= val_pair.1
-    }
+        let val = match val {
+            Value::Int(i) if var_type == VarType::Float => Value::Float(i as f64),
+            _ => val
+        };
 
-    pub fn lt(&mut self, ast: &Box<Ast>) -> bool {
-        let val_pair = self.unwrap_ast(ast);
+        self.symbol_table.set(&var.name, val);
 
-        val_pair.0 < val_pair.1
+        Ok(())
     }
 
-    pub fn lt_equals(&mut self, ast: &Box<Ast>) -> bool {
-        let val_pair = self.unwrap_ast(ast);
-
-        val_pair.0 <= val_pair.1
+    /// Evaluate any expression tree to a value. Arithmetic on two ints gives an int,
+    /// and on any other numbers a float. Strings can be joined with '+', and conditions
+    /// evaluate to a bool.
+    pub fn eval_expr(&mut self, expr: &Expr) -> EvalResult<Value> {
+        match *expr {
+            Expr::Binary { op, .. } if op.is_comparison() || op == BinaryOp::And || op == BinaryOp::Or => {
+                Ok(Value::Bool(self.eval_bool(expr)?))
+            },
+            Expr::Binary { op, ref lhs, ref rhs, span } => self.arithmetic(op, lhs, rhs, span),
+            Expr::Unary { op: UnaryOp::Not, .. } => Ok(Value::Bool(self.eval_bool(expr)?)),
+            Expr::Unary { op: UnaryOp::Negate, ref operand, span } => {
+                match self.eval_expr(operand)? {
+                    Value::Int(i) => i.checked_neg()
+                        .map(Value::Int)
+                        .ok_or_else(|| EvalError::new("Integer overflow".to_owned(), span)),
+                    Value::Float(x) => Ok(Value::Float(-x)),
+                    other => Err(EvalError::new(format!("Cannot negate {}", other.type_name()), span))
+                }
+            },
+            Expr::Interpolation { expr: ref inner, .. } => self.eval_expr(inner),
+            Expr::Number { val: Number::Int(i), .. } => Ok(Value::Int(i)),
+            Expr::Number { val: Number::Float(x), .. } => Ok(Value::Float(x)),
+            Expr::Str { ref val, .. } => Ok(Value::Str(val.clone())),
+            Expr::InterpolatedString { ref parts, .. } => {
+                let mut value = String::new();
+                for part in parts {
+                    value += &self.eval_expr(part)?.to_string();
+                }
+
+                Ok(Value::Str(value))
+            },
+            Expr::Conditional { ref cond, ref then, ref else_, .. } => {
+                if self.eval_bool(cond)? {
+                    self.eval_expr(then)
+                } else {
+                    match *else_ {
+                        Some(ref else_) => self.eval_expr(else_),
+                        None => Ok(Value::Null)
+                    }
+                }
+            },
+            Expr::Ident(ref ident) => {
+                match ident.name.as_ref() {
+                    "true" => Ok(Value::Bool(true)),
+                    "false" => Ok(Value::Bool(false)),
+                    _ => self.symbol_val(ident)
+                }
+            },
+            Expr::Var(ref ident) => self.symbol_val(ident),
+            Expr::Raw { ref value, .. } => self.eval_expr(value),
+            Expr::Error { span } => Err(EvalError::new("Expected an expression".to_owned(), span))
+        }
     }
 
-    pub fn equals_equals(&mut self, ast: &Box<Ast>) -> bool {
-        let val_pair = self.unwrap_ast(ast);
+    /// Evaluate a binary arithmetic operation.
+    fn arithmetic(&mut self, op: BinaryOp, lhs: &Expr, rhs: &Expr, span: Span) -> EvalResult<Value> {
+        let left = self.eval_expr(lhs)?;
+        let right = self.eval_expr(rhs)?;
+
+        match (left, right) {
+            (Value::Int(l), Value::Int(r)) => {
+                let result = match op {
+                    BinaryOp::Plus => l.checked_add(r),
+                    BinaryOp::Minus => l.checked_sub(r),
+                    BinaryOp::Multiply => l.checked_mul(r),
+                    _ if r == 0 => return Err(EvalError::new("Division by zero".to_owned(), span)),
+                    BinaryOp::Divide => l.checked_div(r),
+                    _ => l.checked_rem(r)
+                };
+
+                result.map(Value::Int)
+                    .ok_or_else(|| EvalError::new("Integer overflow".to_owned(), span))
+            },
+            (Value::Str(l), Value::Str(r)) if op == BinaryOp::Plus => Ok(Value::Str(l + &r)),
+            (left, right) => {
+                match (as_float(&left), as_float(&right)) {
+                    (Some(l), Some(r)) => {
+                        let result = match op {
+                            BinaryOp::Plus => l + r,
+                            BinaryOp::Minus => l - r,
+                            BinaryOp::Multiply => l * r,
+                            BinaryOp::Divide => l / r,
+                            _ => l % r
+                        };
+
+                        Ok(Value::Float(result))
+                    },
+                    _ => Err(EvalError::new(format!("Cannot apply {:?} to {} and {}",
+                                                    op,
+                                                    left.type_name(),
+                                                    right.type_name()),
+                                            span))
+                }
+            }
+        }
+    }
 
-        val_pair.0 == val_pair.1
+    /// Evaluate both sides of a comparison, and compare them. Numbers can be compared
+    /// with each other, and strings are compared alphabetically.
+    fn compare(&mut self, lhs: &Expr, rhs: &Expr, span: Span) -> EvalResult<Ordering> {
+        let left = self.eval_expr(lhs)?;
+        let right = self.eval_expr(rhs)?;
+
+        let ordering = match (&left, &right) {
+            (&Value::Int(l), &Value::Int(r)) => Some(l.cmp(&r)),
+            (Value::Str(l), Value::Str(r)) => Some(l.cmp(r)),
+            _ => match (as_float(&left), as_float(&right)) {
+                (Some(l), Some(r)) => l.partial_cmp(&r),
+                _ => None
+            }
+        };
+
+        ordering.ok_or_else(|| EvalError::new(format!("Cannot compare {} with {}",
+                                                      left.type_name(),
+                                                      right.type_name()),
+                                              span))
     }
 
-    pub fn not_equals(&mut self, ast: &Box<Ast>) -> bool {
-        let val_pair = self.unwrap_ast(ast);
+    /// Evaluate both sides of an equality check. Values of different types are never
+    /// equal, apart from ints and floats with the same value.
+    fn equals(&mut self, lhs: &Expr, rhs: &Expr) -> EvalResult<bool> {
+        let left = self.eval_expr(lhs)?;
+        let right = self.eval_expr(rhs)?;
+
+        match (as_float(&left), as_float(&right)) {
+            (Some(l), Some(r)) if left.type_name() != right.type_name() => Ok(l == r),
+            _ => Ok(left == right)
+        }
+    }
 
-        val_pair.0 != val_pair.1
+    /// Look up the value of a variable, or a path into one such as 'user.name'.
+    fn symbol_val(&mut self, ident: &Ident) -> EvalResult<Value> {
+        match self.symbol_table.lookup(&ident.name) {
+            Ok(value) => Ok(value.clone()),
+            Err(err) => Err(EvalError::new(err, ident.span))
+        }
     }
+}
 
-    // TODO: support more complex expressions in if statements
-    fn unwrap_ast(&mut self, ast: &Box<Ast>) -> (i64, i64) {
-        self.validate_ast(ast);
+/// Returns the value of a number as a float, or None if the value isn't a number.
+fn as_float(value: &Value) -> Option<f64> {
+    match *value {
+        Value::Int(i) => Some(i as f64),
+        Value::Float(x) => Some(x),
+        _ => None
+    }
+}
 
-        let first_term = &ast.children[0];
-        let second_term = &ast.children[1];
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use syntax::symbol_table::SymbolTable;
 
-        let symbol = self.symbol_table.get(first_term.val.to_owned()).unwrap_or_else(|| {
-           panic!("tank: Invalid expression found, could not find identifier {}",
-                  first_term.val);
-        });
+    const IDENT_NAME: &str = "ident";
+    const IDENT_VAL: &str = "10";
 
-        let first_val: i64 = symbol.val
-            .clone()
-            .parse()
-            .ok()
-            .expect("tank: Expected an integer");
+    fn ident(name: &str) -> Ident {
+        Ident {
+            name: name.to_owned(),
+            span: Span::default()
+        }
+    }
 
-        let second_val: i64 = second_term.val
-            .clone()
-            .parse()
-            .ok()
-            .expect("tank: Expected an integer");
+    fn number(val: &str) -> Expr {
+        let val = if val.contains('.') {
+            Number::Float(val.parse().unwrap())
+        } else {
+            Number::Int(val.parse().unwrap())
+        };
 
-        (first_val, second_val)
+        Expr::Number {
+            val,
+            span: Span::default()
+        }
     }
 
-    fn validate_ast(&self, ast: &Box<Ast>) {
-        if ast.children.len() < 2 {
-            panic!("tank: Invalid expression ast found, not enough children");
+    fn string(val: &str) -> Expr {
+        Expr::Str {
+            val: val.to_owned(),
+            span: Span::default()
         }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use syntax::ast::Ast;
-    use syntax::ast::AstType;
-    use syntax::symbol_table::SymbolTable;
+    fn binary(op: BinaryOp, lhs: Expr, rhs: Expr) -> Expr {
+        Expr::Binary {
+            op,
+            lhs: Box::new(lhs),
+            rhs: Box::new(rhs),
+            span: Span::default()
+        }
+    }
 
-    const IDENT_NAME: &'static str = "ident";
-    const IDENT_VAL: &'static str = "10";
+    fn unary(op: UnaryOp, operand: Expr) -> Expr {
+        Expr::Unary {
+            op,
+            operand: Box::new(operand),
+            span: Span::default()
+        }
+    }
 
     fn setup() -> Evaluator {
         let mut table = SymbolTable::new();
-        let mut ident = Ast::new(AstType::AssignExpr);
+        table.insert(IDENT_NAME, "int", Span::default()).unwrap();
 
-        let mut var = Ast::new_from_value(AstType::Ident, IDENT_NAME);
-        var.var_type = Some(String::from("int"));
+        let mut eval = Evaluator::new(table);
+        eval.assign(&ident(IDENT_NAME), VarType::Int, &number(IDENT_VAL)).unwrap();
 
-        let var_val = Ast::new_from_value(AstType::Number, IDENT_VAL);
+        eval
+    }
 
-        ident.children.push(Box::new(var));
-        ident.children.push(Box::new(var_val));
+    /// Build an expression for 'ident <op> value'.
+    fn compare(op: BinaryOp, value: &str) -> Expr {
+        binary(op, Expr::Ident(ident(IDENT_NAME)), number(value))
+    }
 
-        table.insert(&Box::new(ident));
+    #[test]
+    fn test_eval_gt_false() {
+        let mut eval = setup();
 
-        Evaluator::new(table)
+        assert!(!eval.eval_bool(&compare(BinaryOp::Gt, "11")).unwrap());
     }
 
     #[test]
-    fn test_eval_gt_false() {
+    fn test_eval_gt_when_true() {
         let mut eval = setup();
 
-        let mut expr_ast = Ast::new(AstType::Gt);
-        let ident = Ast::new_from_value(AstType::Ident, IDENT_NAME);
-        let value = Ast::new_from_value(AstType::Number, "11");
+        assert!(eval.eval_bool(&compare(BinaryOp::Gt, "9")).unwrap());
+    }
 
-        expr_ast.children.push(Box::new(ident));
-        expr_ast.children.push(Box::new(value));
+    #[test]
+    fn test_eval_gt_equals() {
+        let mut eval = setup();
 
-        assert_eq!(eval.gt(&Box::new(expr_ast)), false);
+        assert!(!eval.eval_bool(&compare(BinaryOp::GtEquals, "11")).unwrap());
     }
 
     #[test]
-    fn test_eval_gt_when_true() {
+    fn test_eval_gt_equals_when_equal() {
+        let mut eval = setup();
+
+        assert!(eval.eval_bool(&compare(BinaryOp::GtEquals, "10")).unwrap());
+    }
+
+    #[test]
+    fn test_eval_lt() {
         let mut eval = setup();
 
-        let mut expr_ast = Ast::new(AstType::Gt);
-        let ident = Ast::new_from_value(AstType::Ident, IDENT_NAME);
-        let value = Ast::new_from_value(AstType::Number, "9");
+        assert!(eval.eval_bool(&compare(BinaryOp::Lt, "11")).unwrap());
+    }
 
-        expr_ast.children.push(Box::new(ident));
-        expr_ast.children.push(Box::new(value));
+    #[test]
+    fn test_eval_lt_equals() {
+        let mut eval = setup();
 
-        assert_eq!(eval.gt(&Box::new(expr_ast)), true);
+        assert!(!eval.eval_bool(&compare(BinaryOp::LtEquals, "9")).unwrap());
     }
 
     #[test]
-    fn test_eval_gt_equals() {
+    fn test_eval_equals_equals() {
+        let mut eval = setup();
+
+        assert!(!eval.eval_bool(&compare(BinaryOp::EqualsEquals, "11")).unwrap());
+    }
+
+    #[test]
+    fn test_eval_not_equals() {
         let mut eval = setup();
 
-        let mut expr_ast = Ast::new(AstType::GtEquals);
-        let ident = Ast::new_from_value(AstType::Ident, IDENT_NAME);
-        let value = Ast::new_from_value(AstType::Number, "11");
+        assert!(eval.eval_bool(&compare(BinaryOp::NotEquals, "11")).unwrap());
+    }
 
-        expr_ast.children.push(Box::new(ident));
-        expr_ast.children.push(Box::new(value));
+    #[test]
+    fn test_eval_and() {
+        let mut eval = setup();
+
+        let both = binary(BinaryOp::And, compare(BinaryOp::Gt, "9"), compare(BinaryOp::Lt, "11"));
+        let one = binary(BinaryOp::And, compare(BinaryOp::Gt, "9"), compare(BinaryOp::Lt, "10"));
 
-        assert_eq!(eval.gt_equals(&Box::new(expr_ast)), false);
+        assert!(eval.eval_bool(&both).unwrap());
+        assert!(!eval.eval_bool(&one).unwrap());
     }
 
     #[test]
-    fn test_eval_gt_equals_when_equal() {
+    fn test_eval_or() {
         let mut eval = setup();
 
-        let mut expr_ast = Ast::new(AstType::GtEquals);
-        let ident = Ast::new_from_value(AstType::Ident, IDENT_NAME);
-        let value = Ast::new_from_value(AstType::Number, "10");
+        let one = binary(BinaryOp::Or, compare(BinaryOp::Gt, "11"), compare(BinaryOp::Lt, "11"));
+        let neither = binary(BinaryOp::Or, compare(BinaryOp::Gt, "11"), compare(BinaryOp::Lt, "10"));
 
-        expr_ast.children.push(Box::new(ident));
-        expr_ast.children.push(Box::new(value));
+        assert!(eval.eval_bool(&one).unwrap());
+        assert!(!eval.eval_bool(&neither).unwrap());
+    }
+
+    #[test]
+    fn test_eval_not() {
+        let mut eval = setup();
 
-        assert_eq!(eval.gt_equals(&Box::new(expr_ast)), true);
+        let not_expr = unary(UnaryOp::Not, compare(BinaryOp::Gt, "11"));
+
+        assert!(eval.eval_bool(&not_expr).unwrap());
     }
 
     #[test]
-    fn test_eval_lt() {
+    fn test_eval_short_circuit() {
         let mut eval = setup();
 
-        let mut expr_ast = Ast::new(AstType::Lt);
-        let ident = Ast::new_from_value(AstType::Ident, IDENT_NAME);
-        let value = Ast::new_from_value(AstType::Number, "11");
+        // Evaluating the undeclared identifier would be an error.
+        let missing = binary(BinaryOp::Gt, Expr::Ident(ident("missing")), number("1"));
 
-        expr_ast.children.push(Box::new(ident));
-        expr_ast.children.push(Box::new(value));
+        let or_expr = binary(BinaryOp::Or, compare(BinaryOp::Gt, "9"), missing.clone());
+        let and_expr = binary(BinaryOp::And, compare(BinaryOp::Gt, "11"), missing);
 
-        assert_eq!(eval.lt(&Box::new(expr_ast)), true);
+        assert!(eval.eval_bool(&or_expr).unwrap());
+        assert!(!eval.eval_bool(&and_expr).unwrap());
     }
 
     #[test]
-    fn test_eval_lt_equals() {
+    fn test_eval_arithmetic() {
         let mut eval = setup();
 
-        let mut expr_ast = Ast::new(AstType::LtEquals);
-        let ident = Ast::new_from_value(AstType::Ident, IDENT_NAME);
-        let value = Ast::new_from_value(AstType::Number, "9");
+        // (ident * 3) % 7 - -4
+        let multiply = binary(BinaryOp::Multiply, Expr::Ident(ident(IDENT_NAME)), number("3"));
+        let modulo = binary(BinaryOp::Modulo, multiply, number("7"));
+        let minus = binary(BinaryOp::Minus, modulo, unary(UnaryOp::Negate, number("4")));
 
-        expr_ast.children.push(Box::new(ident));
-        expr_ast.children.push(Box::new(value));
+        assert_eq!(eval.eval_expr(&minus), Ok(Value::Int(6)));
+    }
+
+    #[test]
+    fn test_eval_float_literal() {
+        let mut eval = setup();
 
-        assert_eq!(eval.lt_equals(&Box::new(expr_ast)), false);
+        let multiply = binary(BinaryOp::Multiply, number("1.5"), number("3"));
+        assert_eq!(eval.eval_expr(&multiply), Ok(Value::Float(4.5)));
+
+        assign(&mut eval, "ratio", VarType::Float, number("0.25")).unwrap();
+        assert_eq!(eval.symbol_table.get("ratio".to_owned()).unwrap().val, Value::Float(0.25));
+
+        let err = assign(&mut eval, "count", VarType::Int, number("2.5")).unwrap_err();
+        assert!(err.message.contains("'count' is declared as int, but its value is float"));
     }
 
     #[test]
-    fn test_eval_equals_equals() {
+    fn test_eval_division_by_zero() {
         let mut eval = setup();
 
-        let mut expr_ast = Ast::new(AstType::EqualsEquals);
-        let ident = Ast::new_from_value(AstType::Ident, IDENT_NAME);
-        let value = Ast::new_from_value(AstType::Number, "11");
+        let divide = binary(BinaryOp::Divide, Expr::Ident(ident(IDENT_NAME)), number("0"));
 
-        expr_ast.children.push(Box::new(ident));
-        expr_ast.children.push(Box::new(value));
+        let err = eval.eval_expr(&divide).unwrap_err();
+        assert!(err.message.starts_with("Division by zero"));
+    }
 
-        assert_eq!(eval.equals_equals(&Box::new(expr_ast)), false);
+    /// Declare and assign 'let name: var_type = value'.
+    fn assign(eval: &mut Evaluator, name: &str, var_type: VarType, value: Expr) -> EvalResult<()> {
+        eval.symbol_table.insert(name, var_type.name(), Span::default()).unwrap();
+        eval.assign(&ident(name), var_type, &value)
     }
 
     #[test]
-    fn test_eval_not_equals() {
+    fn test_eval_assign_typed_values() {
+        let mut eval = setup();
+
+        assign(&mut eval, "count", VarType::Int, number("3")).unwrap();
+        assign(&mut eval, "ratio", VarType::Float, number("3")).unwrap();
+        assign(&mut eval, "flag", VarType::Bool, Expr::Ident(ident("true"))).unwrap();
+        assign(&mut eval, "name", VarType::Str, string("tank")).unwrap();
+
+        assert_eq!(eval.symbol_table.get("count".to_owned()).unwrap().val, Value::Int(3));
+        assert_eq!(eval.symbol_table.get("ratio".to_owned()).unwrap().val, Value::Float(3.0));
+        assert_eq!(eval.symbol_table.get("flag".to_owned()).unwrap().val, Value::Bool(true));
+        assert_eq!(eval.symbol_table.get("name".to_owned()).unwrap().val, Value::Str("tank".to_owned()));
+    }
+
+    #[test]
+    fn test_eval_assign_type_mismatch() {
+        let mut eval = setup();
+
+        let err = assign(&mut eval, "count", VarType::Int, string("ten")).unwrap_err();
+        assert!(err.message.contains("'count' is declared as int, but its value is string"));
+    }
+
+    #[test]
+    fn test_eval_mixed_types() {
+        let mut eval = setup();
+        eval.symbol_table.bind("half", "float", Value::Float(0.5));
+
+        let plus = binary(BinaryOp::Plus, Expr::Ident(ident(IDENT_NAME)), Expr::Ident(ident("half")));
+        assert_eq!(eval.eval_expr(&plus), Ok(Value::Float(10.5)));
+
+        let join = binary(BinaryOp::Plus, string("a"), string("b"));
+        assert_eq!(eval.eval_expr(&join), Ok(Value::Str("ab".to_owned())));
+
+        let mismatch = binary(BinaryOp::Gt, Expr::Ident(ident(IDENT_NAME)), string("a"));
+        assert!(eval.eval_bool(&mismatch).unwrap_err().message.starts_with("Cannot compare int with string"));
+    }
+
+    #[test]
+    fn test_eval_path_lookup() {
         let mut eval = setup();
 
-        let mut expr_ast = Ast::new(AstType::Gt);
-        let ident = Ast::new_from_value(AstType::Ident, IDENT_NAME);
-        let value = Ast::new_from_value(AstType::Number, "11");
+        let mut user = BTreeMap::new();
+        user.insert("name".to_owned(), Value::Str("Ann".to_owned()));
+        let items = vec![Value::Int(1), Value::Map(user.clone())];
+        eval.symbol_table.bind("user", "map", Value::Map(user)).bind("items", "list", Value::List(items));
+
+        let lookup = |eval: &mut Evaluator, path: &str| eval.eval_expr(&Expr::Ident(ident(path)));
 
-        expr_ast.children.push(Box::new(ident));
-        expr_ast.children.push(Box::new(value));
+        assert_eq!(lookup(&mut eval, "user.name"), Ok(Value::Str("Ann".to_owned())));
+        assert_eq!(lookup(&mut eval, "items[1].name"), Ok(Value::Str("Ann".to_owned())));
 
-        assert_eq!(eval.not_equals(&Box::new(expr_ast)), true);
+        assert!(lookup(&mut eval, "user.nmae").unwrap_err().message.ends_with("'user' has no field 'nmae'"));
+        assert!(lookup(&mut eval, "items[2]").unwrap_err()
+            .message.ends_with("index 2 is out of range for 'items', which has 2 items"));
+        assert!(lookup(&mut eval, "items[0].name").unwrap_err()
+            .message.ends_with("'items[0]' is of type int, so '.name' can't be looked up in it"));
+        assert!(lookup(&mut eval, "usr.name").unwrap_err().message.ends_with("'usr' is not defined"));
     }
 }
@@ -1,18 +1,27 @@
+use std::collections::BTreeMap;
 use std::fs::OpenOptions;
-use std::io::{BufWriter, Write, Read};
-use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
 use compile::compiler::Compiler;
-use syntax::ast::{Ast, AstType};
-use syntax::symbol_table::SymbolTable;
+use syntax::ast::{walk_node, Attr, Block, Else, Expr, Ident, Node, Template, TextPart, VarType, Visitor};
+use syntax::html::{is_void_element, keeps_bool_text};
+use syntax::span::Span;
+use syntax::symbol_table::{SymbolTable, LOOP};
+use syntax::value::Value;
 use error::error_traits::Diagnostic;
 use error::gen_err::GenDiagnostic;
-use generate::emit::Emitter;
+use generate::emit::{escape, Emitter, Escape};
 use generate::eval::Evaluator;
 
-const EXT: &'static str = ".html";
-const TANK_EXT: &'static str = ".tank";
+const EXT: &str = ".html";
+const TANK_EXT: &str = ".tank";
 const INDENTATION_COUNT: usize = 2;
 
+// Fields of the helper map bound inside the block of a for loop.
+const LOOP_INDEX: &str = "index";
+const LOOP_FIRST: &str = "first";
+const LOOP_LAST: &str = "last";
+
 #[derive(Clone, Debug)]
 struct Scope {
     /// Number of spaces to write before generation
@@ -21,9 +30,19 @@ struct Scope {
     val: String
 }
 
-pub struct Gen {
+/// How an attribute is written, once its value has been resolved.
+enum AttrValue {
+    /// Leave the attribute out, such as a false boolean attribute
+    Omit,
+    /// Write just the name of the attribute
+    Name,
+    /// Write the name and this value, already escaped
+    Value(String)
+}
+
+pub struct Gen<W: Write> {
     /// Emitter struct handle file writing operations
-    emitter: Emitter,
+    emitter: Emitter<W>,
     /// Evaluates expressions to determine if code needs to be generated
     eval: Evaluator,
     /// Stack of elements used to determine scope
@@ -32,23 +51,27 @@ pub struct Gen {
     pub diagnostic: GenDiagnostic
 }
 
-impl Gen {
+impl Gen<BufWriter<File>> {
     /// Creates a new Gen struct for a specific file. Will open/create
     /// the file that needs to be compiled, as well as set up the
-    /// proper writing buffer.
-    pub fn new(filename: &String, symbol_table: SymbolTable) -> Gen {
+    /// proper writing buffer. Fails if the file can't be created.
+    pub fn new(filename: &String, symbol_table: SymbolTable) -> io::Result<Gen<BufWriter<File>>> {
         let mut options = OpenOptions::new();
         options.write(true);
         options.create(true);
         options.truncate(true);
 
-        let file = match options.open(filename.to_owned() + EXT) {
-            Ok(file) => file,
-            Err(error) => panic!("tank: unable to open file: {}", error)
-        };
+        let file = options.open(filename.to_owned() + EXT)?;
+
+        Ok(Gen::from_writer(BufWriter::new(file), symbol_table))
+    }
+}
 
-        let buf_writer = BufWriter::new(file);
-        let m_emitter = Emitter::new(buf_writer);
+impl<W: Write> Gen<W> {
+    /// Creates a new Gen struct that writes the html to the given writer, rather
+    /// than to a file. Used to render a template in memory.
+    pub fn from_writer(writer: W, symbol_table: SymbolTable) -> Gen<W> {
+        let m_emitter = Emitter::new(writer);
         let m_el_stack = Vec::new();
 
         let m_eval = Evaluator::new(symbol_table);
@@ -61,194 +84,178 @@ impl Gen {
         }
     }
 
-    /// Generate the contents of an HTML template from the given ast. The contents are written
-    /// to the file provided when creating the generator.  This function will panic if the ast
-    /// does not contain a template, or if the ast is empty.
-    pub fn output(&mut self, template: &Ast) {
-        if template.ast_type != AstType::Template {
-            let err_str = format!("tank: Invalid ast provided to generator. Found {:?}, expected {:?}",
-                                  template.ast_type,
-                                  AstType::Template);
-
-            self.diagnostic.new_err(&err_str);
-        }
+    /// Finish generating, and return the writer the html was written to. Fails if
+    /// any of the html couldn't be written.
+    pub fn into_writer(self) -> io::Result<W> {
+        self.emitter.into_inner()
+    }
 
-        if template.children.is_empty() {
-            self.diagnostic.new_err(&"tank: Empty ast found, nothing to generate.");
+    /// Generate the contents of an HTML template from the given ast. The contents are written
+    /// to the file provided when creating the generator. An empty template is reported as
+    /// an error, since there is nothing to generate.
+    pub fn output(&mut self, template: &Template) {
+        if template.nodes.is_empty() {
+            self.diagnostic.new_err("tank: Empty ast found, nothing to generate.");
         }
 
-        for ast in &template.children {
-            // Clear out the element stack, in case the last un-nested element is left over.
-            self.el_stack.clear();
-            self.expr_or_element(&ast);
-        }
+        self.visit_template(template);
     }
 
-    /// Determines if we are currently generating an element or an expression. Simply
-    /// calls the approriate gen function based on the type of the ast.
-    fn expr_or_element(&mut self, ast: &Box<Ast>) -> &Gen {
-        match ast.ast_type {
-            AstType::Element => self.gen_element(ast),
-            AstType::IfExpr => self.gen_if(ast),
-            AstType::ForExpr => self.gen_for(ast),
-            AstType::Include => self.gen_include(ast),
-            _ => self.gen_empty()
+    /// Generate an element, along with its attributes and contents, and write it to file.
+    /// Each node in the body of the element is generated in order, nested one level deeper,
+    /// and then the element's own closing tag is written.
+    fn gen_element(&mut self, name: &Ident, attrs: &[Attr], body: &[Node]) -> &Self {
+        let el_scope = Scope {
+            indentation: self.el_stack.len() * INDENTATION_COUNT,
+            val: name.name.clone()
         };
 
-        self
-    }
+        self.gen_el_name(name);
+        self.gen_attr_list(attrs);
+        self.emitter.right_angle_bracket();
+        self.emitter.newline();
 
-    /// Given an Element ast, generate the html contents from it and write to file.
-    /// Expects the given ast to be of type Element, and contain at least 1 child.
-    /// This functions will then be recursively called if the contents of this element
-    /// contain another element.
-    fn gen_element(&mut self, ast: &Box<Ast>) -> &Gen {
-        if ast.ast_type != AstType::Element {
-            let err_str = format!("tank: Invalid ast provided to generator. Found {:?}, expected {:?}",
-                                  ast.ast_type,
-                                  AstType::Element);
-
-            self.diagnostic.new_err(&err_str);
-        }
+        self.el_stack.push(el_scope);
 
-        if ast.children.len() == 0 {
-            self.diagnostic.fatal("Invalid element found, no children present in ast");
+        for node in body {
+            self.visit_node(node);
         }
 
-        let first_child = &ast.children[0];
+        self.close_element();
 
-        // Nothing to generate if we are doing an assignment. Variable value is
-        // already in symbol table.
-        if first_child.ast_type == AstType::AssignExpr {
-            return self;
-        }
+        self
+    }
 
-        // We expect the first child to be ElementName with element name,
-        // second child is attribute list, third child is another element,
-        // containing either the contents or a nested element.
-        // We should be guaranteed to have at least 3 ast types in the children vector.
-        if ast.children.len() < 3 {
-            self.diagnostic.fatal("Invalid Element ast found, not enough children present");
+    /// Write an element that has no contents or closing tag. Html void elements, such
+    /// as 'br', are written as '<br>'. Any other element, such as an svg 'path', is
+    /// written as a self-closing tag: '<path />'.
+    fn gen_void_element(&mut self, name: &Ident, attrs: &[Attr]) -> &Self {
+        self.gen_el_name(name);
+        self.gen_attr_list(attrs);
+
+        if is_void_element(&name.name) {
+            self.emitter.right_angle_bracket();
+        } else {
+            self.emitter.self_closing_bracket();
         }
 
-        self.gen_el_name(&ast.children[0]);
-        self.gen_attr_list(&ast.children[1]);
+        self.emitter.newline();
 
-        match ast.children[2].ast_type {
-            AstType::Element => self.gen_element(&ast.children[2]),
-            AstType::Contents | AstType::VariableValue => self.gen_el_contents(&ast.children[2]),
-            AstType::Include => self.gen_include(&ast.children[2]),
-            AstType::Eof => self.gen_empty(),
-            _ => {
-                self.diagnostic.fatal("Unexpected ast type found");
-                self.gen_empty()
-            }
-        };
+        self
+    }
+
+    /// Nothing to generate for a variable declaration. The variable is already declared
+    /// in the symbol table, so we only need to compute its value.
+    fn gen_let(&mut self, var: &Ident, var_type: VarType, value: &Expr) -> &Self {
+        if let Err(err) = self.eval.assign(var, var_type, value) {
+            self.diagnostic.type_err(&err.message, &err.span);
+        }
 
         self
     }
 
     /// Evaluate and in statement and then generate the result. Evaluation of the
-    /// provided ast is performed and if the if-statement conditions are not met,
+    /// provided condition is performed and if the if-statement conditions are not met,
     /// we skip the generation phase so that the contents of the if-statement
-    /// are never written to file.
-    fn gen_if(&mut self, ast: &Box<Ast>) -> &Gen {
-        if ast.ast_type != AstType::IfExpr {
-            let err_str = format!("tank: Invalid ast provided to generator. Found {:?}, expected {:?}",
-                                  ast.ast_type,
-                                  AstType::IfExpr);
-
-            self.diagnostic.new_err(&err_str);
-        }
-
-        // Expect the first child to be the comparison operator in the if expression,
-        // which itself should have two children containing the left hand term and
-        // right hand term of the expression.
-        //
-        // Following this, we expect another element or expression which is contained
-        // inside the if block.
-        if ast.children.len() == 0 {
-            self.diagnostic.fatal("Invalid ast found, no children for if expression");
+    /// are never written to file. Instead, the else branch is generated if there is one.
+    fn gen_if(&mut self, cond: &Expr, then: &Block, else_: &Option<Box<Else>>) -> &Self {
+        if self.condition(cond) {
+            self.visit_block(then);
+        } else if let Some(ref else_branch) = *else_ {
+            self.gen_else(else_branch);
         }
 
-        let expr = &ast.children[0];
+        self
+    }
 
-        if expr.children.len() < 2 {
-            self.diagnostic.fatal("Invalid expression found, not enough children in if expression");
+    /// Generate an else branch. This is only called once the conditions of the
+    /// preceding if and else-if branches have all failed. Else-if branches have the
+    /// same form as an if statement, so are generated by gen_if().
+    fn gen_else(&mut self, else_branch: &Else) -> &Self {
+        match *else_branch {
+            Else::If { ref cond, ref then, ref else_, .. } => {
+                self.gen_if(cond, then, else_);
+            },
+            Else::Block { ref block, .. } => self.visit_block(block)
         }
 
-        let is_gen = match expr.ast_type {
-            AstType::Gt => self.eval.gt(expr),
-            AstType::GtEquals => self.eval.gt_equals(expr),
-            AstType::Lt => self.eval.lt(expr),
-            AstType::LtEquals => self.eval.lt_equals(expr),
-            AstType::EqualsEquals => self.eval.equals_equals(expr),
-            AstType::NotEquals => self.eval.not_equals(expr),
-            _ => false
+        self
+    }
+
+    fn gen_for(&mut self, loop_var: &Ident, loop_type: VarType, iter: &Ident, body: &Block) -> &Self {
+        // Ensure that the list is in the symbol table. We should have access to this
+        // variable from an inputted file, since we can't yet declare list literals
+        // inside tank files. It may also be a path to a list inside the config, such as
+        // 'site.posts'.
+        let items = match self.eval.symbol_table.lookup(&iter.name) {
+            Ok(Value::List(items)) => items.clone(),
+            Ok(value) => {
+                let err_str = format!("'{}' is not a list", iter.name);
+                let note = format!("its value is of type {}", value.type_name());
+                self.diagnostic.err_at(&err_str, &iter.span)
+                    .with_note(&note);
+                return self;
+            },
+            Err(err) => {
+                self.diagnostic.err_at(&err, &iter.span);
+                return self;
+            }
         };
 
-        if is_gen {
-            let element = &ast.children[1];
-            self.expr_or_element(element);
-        }
+        let last_index = items.len().saturating_sub(1);
+
+        // Generate the block once for each item. The loop variable and the loop helpers
+        // are bound in a new scope, so they can't be seen outside of the block.
+        for (index, item) in items.into_iter().enumerate() {
+            if !item.matches_type(loop_type) {
+                let err_str = format!("Type mismatch - '{}' is declared as {}, but item {} of {} is {}",
+                                      loop_var.name,
+                                      loop_type,
+                                      index,
+                                      iter.name,
+                                      item.type_name());
+                self.diagnostic.type_err(&err_str, &loop_var.span);
+                return self;
+            }
 
-        self
-    }
+            let mut helpers = BTreeMap::new();
+            helpers.insert(LOOP_INDEX.to_owned(), Value::Int(index as i64));
+            helpers.insert(LOOP_FIRST.to_owned(), Value::Bool(index == 0));
+            helpers.insert(LOOP_LAST.to_owned(), Value::Bool(index == last_index));
 
-    fn gen_for(&mut self, ast: &Box<Ast>) -> &Gen {
-        if ast.ast_type != AstType::ForExpr {
-            let err_str = format!("tank: Invalid ast provided to generator. Found {:?}, expected {:?}",
-                                  ast.ast_type,
-                                  AstType::ForExpr);
+            self.eval.symbol_table.push_scope();
+            self.eval.symbol_table
+                .bind(&loop_var.name, loop_type.name(), item)
+                .bind(LOOP, "map", Value::Map(helpers));
 
-            self.diagnostic.new_err(&err_str);
-        }
+            self.visit_block(body);
 
-        // Expect there to be at least 2 children, each identifier in the
-        // "for" "in" declaration.  Technically the for block can be empty, but normally
-        // we would also expect a third child which is the contents of the block.
-        if ast.children.len() < 2 {
-            self.diagnostic.fatal("Invalid ast found, not enough children found in for expression");
+            self.eval.symbol_table.pop_scope();
         }
 
-        let second_ident = &ast.children[1];
-
-        // Ensure that the second ident is in symbol table. We should have access to this
-        // variable from an inputted file, since we can't yet declare array types inside
-        // tank files.
-        match self.eval.symbol_table.get(second_ident.val.clone()) {
-            Some(_) => (),
-            None => {
-                let err_str = format!("tank: Error - variable {} is undefined.",
-                                      second_ident.val);
-                self.diagnostic.new_err(&err_str);
-            }
-        };
+        self
+    }
 
-        if ast.children.get(2).is_none() {
-            return self;
+    /// Insert the contents of an included file.
+    fn gen_include(&mut self, path: &str, span: Span) -> &Self {
+        if let Some(inserted_html) = self.read_include(path, &span) {
+            self.emitter.emit(&inserted_html);
         }
 
-        let containing_element = &ast.children[2];
-        // TODO: Insert elements for each loop in this for-in block. Will require
-        // passing in values from the data source and assigning them in the symbol table.
-        self.expr_or_element(containing_element);
-
         self
     }
 
     /// Try to open the file with a .html extension. If this file exists,
     /// we assume that we have html already so we can read it into a string
-    /// and insert it.
+    /// and return it, to be inserted by the caller.
     ///
     /// If the file doesn't exist, then we need to try and open the corresponding
     /// tank template, compile it, and then open the html file and write the contents
     /// to this file.
-    /// If we can't find the .tank file, then we panic.
-    fn gen_include(&mut self, ast: &Box<Ast>) -> &Gen {
-
+    /// If we can't find the .tank file, or it fails to compile, an error is reported.
+    fn read_include(&mut self, include_name: &str, span: &Span) -> Option<String> {
         let mut is_compile = false;
-        let filename = ast.val.to_owned();
+        let filename = include_name.to_owned();
         let html_filename = filename.to_owned() + EXT;
 
         let mut options = OpenOptions::new();
@@ -260,9 +267,11 @@ impl Gen {
                 let tank_filename = filename + TANK_EXT;
                 let tank_file = match options.open(&tank_filename) {
                     Ok(tank_file) => tank_file,
-                    Err(error) => panic!("tank: Unable to open file {}: {}",
-                                         tank_filename,
-                                         Error::description(&error))
+                    Err(error) => {
+                        let err_str = format!("Unable to open file {}: {}", tank_filename, error);
+                        self.diagnostic.err_at(&err_str, span);
+                        return None;
+                    }
                 };
                 is_compile = true;
 
@@ -273,167 +282,279 @@ impl Gen {
         if is_compile {
             // Create a new compiler struct and use it to compile
             // the referenced .tank file.
-            let tank_filename = ast.val.to_owned() + TANK_EXT;
-            let mut compiler = Compiler::new(&mut file, &tank_filename);
+            let tank_filename = include_name.to_owned() + TANK_EXT;
+            let result = Compiler::new(&mut file, &tank_filename)
+                .and_then(|mut compiler| compiler.compile());
 
-            compiler.compile();
+            if let Err(error) = result {
+                for report in error.reports() {
+                    self.diagnostic.report(report);
+                }
+            }
+
+            None
         } else {
-            // read html file to string and then insert its contents into this file.
+            // read html file to string, so its contents can be inserted into this file.
             let mut inserted_html = String::new();
 
-            match file.read_to_string(&mut inserted_html) {
-                Err(error) => panic!("Failed to read file: {}", Error::description(&error)),
-                Ok(_) => ()
+            if let Err(error) = file.read_to_string(&mut inserted_html) {
+                let err_str = format!("Failed to read file {}: {}", html_filename, error);
+                self.diagnostic.err_at(&err_str, span);
+                return None;
             }
 
-            // Generate the html from the referenced file and clear the element stack.
-            self.emitter.emit(&inserted_html);
-            self.clear_element_stack();
+            Some(inserted_html)
         }
+    }
+
+    /// Write a '#!' comment from the template to file as an html comment, indented
+    /// to the current element depth.
+    fn gen_html_comment(&mut self, text: &str) -> &Self {
+        let indentation = self.el_stack.len() * INDENTATION_COUNT;
+
+        self.emitter.space(indentation);
+        self.emitter.comment(text);
 
         self
     }
 
-    /// Write the name of an element to file, as well as pushes the name on to the
-    /// element stack. The stack is used to keep track of nested elements.
-    fn gen_el_name(&mut self, ast: &Box<Ast>) -> &Gen {
-        let indentation = self.el_stack.len() * INDENTATION_COUNT;
+    /// Write a document type declaration, such as '<!DOCTYPE html>', to file.
+    fn gen_doctype(&mut self, doc_type: &str) -> &Self {
+        self.emitter.doctype(doc_type);
 
-        let el_scope = Scope {
-            indentation: indentation,
-            val: ast.val.clone()
-        };
+        self
+    }
+
+    /// Write the body of a raw block to file. The body is written unchanged, so it isn't
+    /// indented to the depth of the element it is in.
+    fn gen_raw_html(&mut self, html: &str) -> &Self {
+        self.emitter.raw(html);
+
+        self
+    }
+
+    /// Write the start of an element's opening tag to file, indented to the depth of
+    /// the element.
+    fn gen_el_name(&mut self, name: &Ident) -> &Self {
+        let indentation = self.el_stack.len() * INDENTATION_COUNT;
 
-        self.el_stack.push(el_scope);
         self.emitter.space(indentation);
 
         self.emitter.left_angle_bracket();
-        self.emitter.emit(&ast.val);
+        self.emitter.emit(&name.name);
 
         self
     }
 
-    /// Write all the attribute name-value pairs to file, as well as additional
-    /// characters before the contents of the element. This function will panic
-    /// if non-identifier types are found in the attribute list.
-    fn gen_attr_list(&mut self, ast: &Box<Ast>) -> &Gen {
-        if !ast.children.is_empty() {
-            self.emitter.space(1);
-            let attributes = ast.children.clone();
-
-            // If we're here, we know we have x number of attribute pairs. We will
-            // always have an even number of elements in the attributes vec, otherwise
-            // there would have been a parse error. We split the vec into chunks of
-            // two and emit them as pairs of identifiers.
-            //
-            // We define a counter to count the number of pairs we emit. At the end
-            // of each pair, we must insert a space to separate the next pair from it.
-            // However, at the end of the last pair, we don't want to write a space
-            // because the next character inserted will be the closing tag '>'. we
-            // therefore only write a space if the counter is the same size as the
-            // size of the attributes list.
-            let mut counter = 0;
-
-            for attr_pair in attributes.chunks(2) {
-                let ref attr_key = attr_pair[0];
-                let ref attr_val = attr_pair[1];
-
-                if attr_key.ast_type != AstType::Ident {
-                    let err_str = format!("tank: Wrong ast type found, expected {:?}, found {:?}",
-                                          AstType::Ident,
-                                          attr_key.ast_type);
-                    self.diagnostic.fatal(&err_str);
-                }
+    /// Write all the attributes to file, each preceded by a space. The caller ends the tag.
+    ///
+    /// An attribute without a value is written as just its name. A value that evaluates to
+    /// a bool makes a boolean attribute, such as 'checked': it is written as just its name
+    /// when true, and left out entirely when false.
+    fn gen_attr_list(&mut self, attrs: &[Attr]) -> &Self {
+        for attr in attrs {
+            // Errors in the list have already been reported by the parser.
+            let (name, value) = match *attr {
+                Attr::Named { ref name, ref value } => (name, value),
+                Attr::Error { .. } => continue
+            };
 
-                if attr_val.ast_type != AstType::Ident {
-                    let err_str = format!("tank: Wrong ast type found, expected {:?}, found {:?}",
-                                          AstType::Ident,
-                                          attr_val.ast_type);
-                    self.diagnostic.fatal(&err_str);
-                }
+            let value = match self.attr_value(&name.name, value.as_ref()) {
+                AttrValue::Omit => continue,
+                AttrValue::Name => None,
+                AttrValue::Value(value) => Some(value)
+            };
 
-                self.emitter.emit(&attr_key.val);
-                self.emitter.equals();
-                self.emitter.string(&attr_val.val);
+            self.emitter.space(1);
+            self.emitter.emit(&name.name);
 
-                // We only write a space here if we are not at the end of the attr list.
-                // This space separates the attribute pairs.
-                counter = counter + 2;
-                if counter != attributes.len() {
-                    self.emitter.space(1);
-                }
+            if let Some(value) = value {
+                self.emitter.equals();
+                self.emitter.string(&value);
             }
         }
 
-        self.emitter.right_angle_bracket();
-        self.emitter.newline();
-
         self
     }
 
-    /// Write the contents of an element to file, and also write all applicable closing
-    /// tags. This is done by popping the values in the scope stack until the stack is
-    /// empty.
-    fn gen_el_contents(&mut self, ast: &Box<Ast>) -> &Gen {
+    /// Resolve the value of the attribute with the given name. Every value is escaped, so
+    /// that a quote in a literal such as 'Say "hi"' can't end the attribute early. A conditional
+    /// value resolves to the value of the branch that is taken, or is left out if its
+    /// condition is false and it has no else branch. The words 'true' and 'false' are bools,
+    /// like any other bool value.
+    fn attr_value(&mut self, name: &str, value: Option<&Expr>) -> AttrValue {
+        let value = match value {
+            Some(value) => value,
+            None => return AttrValue::Name
+        };
+
+        match *value {
+            Expr::Ident(Ident { name: ref val, .. }) if val != "true" && val != "false" => {
+                AttrValue::Value(escape(val, Escape::Attribute).into_owned())
+            },
+            Expr::Str { ref val, .. } => AttrValue::Value(escape(val, Escape::Attribute).into_owned()),
+            Expr::Number { val, .. } => AttrValue::Value(val.to_string()),
+            Expr::InterpolatedString { ref parts, .. } => {
+                AttrValue::Value(self.interpolated_string(parts, Escape::Attribute))
+            },
+            Expr::Conditional { ref cond, ref then, ref else_, .. } => {
+                let branch = if self.condition(cond) {
+                    Some(&**then)
+                } else {
+                    else_.as_ref().map(|else_| &**else_)
+                };
+
+                match branch {
+                    Some(branch) => self.attr_value(name, Some(branch)),
+                    None => AttrValue::Omit
+                }
+            },
+            _ => {
+                match self.value_of(value) {
+                    Value::Bool(true) if !keeps_bool_text(name) => AttrValue::Name,
+                    Value::Bool(false) if !keeps_bool_text(name) => AttrValue::Omit,
+                    val => {
+                        let context = match *value {
+                            Expr::Raw { .. } => Escape::Raw,
+                            _ => Escape::Attribute
+                        };
+
+                        AttrValue::Value(escape(&val.to_string(), context).into_owned())
+                    }
+                }
+            }
+        }
+    }
+
+    /// Write the text contents of an element to file, indented one level deeper than
+    /// the element they belong to.
+    ///
+    /// Text is written exactly as it appeared in the template, with variable values
+    /// and included files inserted in place.
+    fn gen_text(&mut self, parts: &[TextPart]) -> &Self {
         let mut contents_str = String::new();
 
-        for child in ast.children.to_owned() {
-            match child.ast_type {
-                AstType::Ident => {
-                    contents_str = contents_str + " " + &child.val;
+        for part in parts {
+            match *part {
+                TextPart::Text { ref text, .. } => {
+                    contents_str += text;
                 },
-                AstType::VariableValue => {
-                    let true_val = self.get_var_val(&child.val);
-                    contents_str = contents_str + " " + &true_val;
+                TextPart::Value(ref value) => {
+                    contents_str += &self.gen_value(value, Escape::Text);
                 },
-                _ => {
-                    let err_str = format!("tank: Unexpected ast type {:?} found in element contents",
-                                          child.ast_type);
-                    self.diagnostic.fatal(&err_str);
+                TextPart::Include { ref path, span } => {
+                    if let Some(inserted_html) = self.read_include(path, &span) {
+                        contents_str += inserted_html.trim_end();
+                    }
                 }
             };
         }
 
-        let indentation = self.el_stack.len() * INDENTATION_COUNT;
-        self.emitter.space(indentation + INDENTATION_COUNT);
-
-        self.emitter.emit(&contents_str.trim_left());
-        self.emitter.newline();
+        if !contents_str.is_empty() {
+            // The element being filled is already on the stack, so this indents the
+            // contents one level deeper than its tag.
+            let indentation = self.el_stack.len() * INDENTATION_COUNT;
+            self.emitter.space(indentation);
 
-        self.clear_element_stack();
+            self.emitter.emit(&contents_str);
+            self.emitter.newline();
+        }
 
         self
     }
 
-    /// Retrieve the value of a variable from the symbol table, and panic
-    /// if the var_name passed in does not exist in the symbol table.
-    fn get_var_val(&mut self, var_name: &String) -> String {
-        match self.eval.symbol_table.get(var_name.to_owned()) {
-            Some(symbol) => symbol.val.to_owned(),
-            None => {
-                let err_str = format!("tank: Invalid variable '{}' referenced", var_name);
-                self.diagnostic.new_err(&err_str);
-                String::new()
+    /// Get the value of a variable or interpolation as a string, escaped for the context
+    /// it will be written into. Values marked as raw with '%!' are written as they are.
+    /// String literals containing interpolations have only their interpolated values escaped.
+    fn gen_value(&mut self, value: &Expr, context: Escape) -> String {
+        match *value {
+            Expr::Raw { .. } => self.value_of(value).to_string(),
+            Expr::InterpolatedString { ref parts, .. } => self.interpolated_string(parts, context),
+            _ => escape(&self.value_of(value).to_string(), context).into_owned()
+        }
+    }
+
+    /// Get the value of a variable, such as '%posts[0].author', or of an interpolated
+    /// expression. If the value can't be found, the error is reported and the value is
+    /// Null, which is written as nothing.
+    fn value_of(&mut self, value: &Expr) -> Value {
+        match self.eval.eval_expr(value) {
+            Ok(value) => value,
+            Err(err) => {
+                self.diagnostic.err_at(&err.message, &err.span);
+                Value::Null
             }
         }
     }
 
-    /// Clears all the nested element scopes in the element stack. This function will
-    /// write the closing element tag of each scope found in the stack.
-    fn clear_element_stack(&mut self) -> &Gen {
-        let mut scope: Option<Scope> = self.el_stack.pop();
+    /// Build a string literal containing interpolations. The literal parts are kept as
+    /// they were written, and the interpolated values are escaped for the given context.
+    fn interpolated_string(&mut self, parts: &[Expr], context: Escape) -> String {
+        let mut value = String::new();
+
+        for part in parts {
+            match *part {
+                // Literal text is kept as written in contents, but in an attribute
+                // value it is escaped along with everything else.
+                Expr::Str { ref val, .. } if context == Escape::Attribute => {
+                    value += &escape(val, Escape::Attribute)
+                },
+                Expr::Str { ref val, .. } => value += val,
+                _ => value += &self.gen_value(part, context)
+            }
+        }
+
+        value
+    }
+
+    /// Evaluate the condition of an if statement or conditional attribute. An invalid
+    /// condition is reported, and treated as false.
+    fn condition(&mut self, cond: &Expr) -> bool {
+        match self.eval.eval_bool(cond) {
+            Ok(is_true) => is_true,
+            Err(err) => {
+                self.diagnostic.err_at(&err.message, &err.span);
+                false
+            }
+        }
+    }
 
-        while scope.is_some() {
-            let name = scope.unwrap();
-            self.emitter.space(name.indentation);
-            self.emitter.close_element(&name.val);
-            scope = self.el_stack.pop();
+    /// Write the closing tag of the innermost open element, and remove it from the
+    /// element stack.
+    fn close_element(&mut self) -> &Self {
+        if let Some(scope) = self.el_stack.pop() {
+            self.emitter.space(scope.indentation);
+            self.emitter.close_element(&scope.val);
         }
 
         self
     }
+}
 
-    fn gen_empty(&mut self) -> &Gen {
-        self
+impl<W: Write> Visitor for Gen<W> {
+    /// Calls the appropriate gen function based on the type of the node. Nodes are generated
+    /// in the order they are visited, so each node decides which of its children are
+    /// generated, and how many times, such as the branches of an if statement.
+    fn visit_node(&mut self, node: &Node) {
+        match *node {
+            Node::Element { ref name, ref attrs, ref body, .. } => self.gen_element(name, attrs, body),
+            Node::VoidElement { ref name, ref attrs, .. } => self.gen_void_element(name, attrs),
+            Node::Text { ref parts, .. } => self.gen_text(parts),
+            Node::Let { ref var, var_type, ref value, .. } => self.gen_let(var, var_type, value),
+            Node::If { ref cond, ref then, ref else_, .. } => self.gen_if(cond, then, else_),
+            Node::For { ref var, var_type, ref iter, ref body, .. } => {
+                self.gen_for(var, var_type, iter, body)
+            },
+            Node::Include { ref path, span } => self.gen_include(path, span),
+            Node::Comment { ref text, .. } => self.gen_html_comment(text),
+            Node::Doctype { ref doc_type, .. } => self.gen_doctype(doc_type),
+            Node::Raw { ref html, .. } => self.gen_raw_html(html),
+            // Errors have already been reported by the parser, so there is nothing to write.
+            Node::Error { .. } => self,
+            Node::Block(..) => {
+                walk_node(self, node);
+                self
+            }
+        };
     }
 }
@@ -3,90 +3,96 @@ extern crate tank;
 use std::env;
 use std::fs;
 use std::fs::File;
+use std::io::{self, IsTerminal};
 use std::path::Path;
-use std::error::Error;
+use std::process;
 use tank::compile::compiler::Compiler;
+use tank::error::tank_err::TankError;
 
 fn main() {
-    let filename = env::args().nth(1).unwrap_or_else(|| {
-        panic!("tank: Expected a file or directory name as the first arg.");
-    });
+    let filename = match env::args().nth(1) {
+        Some(filename) => filename,
+        None => {
+            eprintln!("tank: Expected a file or directory name as the first arg.");
+            process::exit(2);
+        }
+    };
 
     let config_filename = env::args().nth(2);
 
-    let path = Path::new(&filename);
-    let display = path.display();
-
-    let mut file = match File::open(&path) {
-        Err(error) => panic!("Failed to open {}: {}", display, Error::description(&error)),
-        Ok(file) => file
+    let errors = match run(&filename, config_filename) {
+        Ok(errors) => errors,
+        Err(error) => vec![error]
     };
 
-    let metadata = match file.metadata() {
-        Ok(data) => data,
-        Err(error) => panic!("Failed to get metadata for {}: {}",
-                             &filename,
-                             Error::description(&error))
-    };
+    let color = io::stderr().is_terminal();
+    for error in &errors {
+        eprintln!("{}\n", error.render(color));
+    }
+
+    if !errors.is_empty() {
+        process::exit(1);
+    }
+}
+
+/// Compile the given template, or every template in the given directory. Returns the
+/// errors from each template that failed to compile, so that one bad template doesn't
+/// stop the others from being compiled. Fails if the file or directory can't be read.
+fn run(filename: &String, config_filename: Option<String>) -> Result<Vec<TankError>, TankError> {
+    let path = Path::new(filename);
+
+    let file = open_file(path)?;
+
+    let metadata = file.metadata()
+        .map_err(|error| TankError::Io(filename.to_owned(), error))?;
+
+    if !metadata.is_dir() {
+        return Ok(compile_file(file, filename, &config_filename).err().into_iter().collect());
+    }
 
-    if metadata.is_dir() {
-        let files = match fs::read_dir(&filename) {
-            Err(error) => panic!("Failed to read directory {}: {}",
-                                 &filename,
-                                 Error::description(&error)),
-            Ok(list) => list
-        };
-
-        for entry in files {
-            let entry = match entry {
-                Ok(e) => e,
-                Err(error) => panic!("Failed to read file entry: {}", Error::description(&error))
-
-            };
-
-            let path = entry.path();
-            let mut file = match File::open(&path) {
-                Err(error) => panic!("Failed to open {}: {}", display, Error::description(&error)),
-                Ok(file) => file
-            };
-
-            let incl_filename = path.to_str().unwrap().to_owned();
-            let conf_filename_copy = config_filename.clone();
-
-            let mut compiler = if conf_filename_copy.is_some() {
-                get_compiler_from_conf(file, &filename, conf_filename_copy)
-            } else {
-                Compiler::new(&mut file, &incl_filename)
-            };
-
-            compiler.compile();
+    let files = fs::read_dir(filename)
+        .map_err(|error| TankError::Io(filename.to_owned(), error))?;
+
+    let mut errors = Vec::new();
+
+    for entry in files {
+        let entry = entry.map_err(|error| TankError::Io(filename.to_owned(), error))?;
+
+        let path = entry.path();
+        let file = open_file(&path)?;
+        let incl_filename = path.to_string_lossy().into_owned();
+
+        if let Err(error) = compile_file(file, &incl_filename, &config_filename) {
+            errors.push(error);
         }
-    } else {
-        let mut compiler = if config_filename.is_some() {
-            get_compiler_from_conf(file, &filename, config_filename)
-        } else {
-            Compiler::new(&mut file, &filename)
-        };
-
-        compiler.compile();
     }
+
+    Ok(errors)
+}
+
+/// Compile a single template, using the config file if one was given.
+fn compile_file(mut file: File,
+                filename: &String,
+                config_filename: &Option<String>) -> Result<(), TankError> {
+    let mut compiler = match *config_filename {
+        Some(ref conf_name) => get_compiler_from_conf(file, filename, conf_name)?,
+        None => Compiler::new(&mut file, filename)?
+    };
+
+    compiler.compile()
 }
 
 /// Create a new compiler from a "configuration" file. This file
 /// contains variables for use in templates in a global scope.
 fn get_compiler_from_conf(mut file: File,
                           filename: &String,
-                          conf_filename: Option<String>)
-                          -> Compiler {
-    let conf_name = conf_filename.unwrap();
-
-    let conf_path = Path::new(&conf_name);
-    let mut conf_file = match File::open(&conf_path) {
-        Err(error) => panic!("Failed to open {}: {}",
-                             conf_path.display(),
-                             Error::description(&error)),
-        Ok(file) => file
-    };
+                          conf_name: &str)
+                          -> Result<Compiler, TankError> {
+    let mut conf_file = open_file(Path::new(conf_name))?;
+
+    Compiler::from_config_file(&mut file, filename, &mut conf_file)
+}
 
-    Compiler::from_config_file(&mut file, &filename, &mut conf_file)
+fn open_file(path: &Path) -> Result<File, TankError> {
+    File::open(path).map_err(|error| TankError::Io(path.display().to_string(), error))
 }
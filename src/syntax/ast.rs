@@ -1,58 +1,689 @@
+use std::fmt;
+use syntax::span::Span;
+
+/// A parsed template, holding the nodes that aren't nested in any element, in the order
+/// they appear in the source.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Template {
+    pub nodes: Vec<Node>
+}
+
+/// Anything that can appear in the body of a template or an element.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Node {
+    /// An html element with its contents, such as 'p (class: "intro") -> Hello'
+    Element {
+        name: Ident,
+        attrs: Vec<Attr>,
+        body: Vec<Node>,
+        span: Span
+    },
+    /// An element without contents or a closing tag. This is either an html void element
+    /// such as 'br ()', or a self-closing element such as 'path (d: "...");'
+    VoidElement {
+        name: Ident,
+        attrs: Vec<Attr>,
+        span: Span
+    },
+    /// The text contents of an element, following its '->'
+    Text {
+        parts: Vec<TextPart>,
+        span: Span
+    },
+    /// A variable declaration, such as 'let x: int = 10'
+    Let {
+        var: Ident,
+        var_type: VarType,
+        value: Expr,
+        span: Span
+    },
+    /// An if statement, along with its else branch if it has one
+    If {
+        cond: Expr,
+        then: Block,
+        else_: Option<Box<Else>>,
+        span: Span
+    },
+    /// A for loop, such as 'for post: map in %posts { ... }'
+    For {
+        var: Ident,
+        var_type: VarType,
+        iter: Ident,
+        body: Block,
+        span: Span
+    },
+    /// A braced block of nodes
+    Block(Block),
+    /// An included template or html file, such as '&header'
+    Include {
+        path: String,
+        span: Span
+    },
+    /// A '#!' comment, written to the html as an html comment
+    Comment {
+        text: String,
+        span: Span
+    },
+    /// A document type declaration, such as 'doctype html'
+    Doctype {
+        doc_type: String,
+        span: Span
+    },
+    /// The body of a raw block, written to the html unchanged
+    Raw {
+        html: String,
+        span: Span
+    },
+    /// Source skipped by the parser while recovering from a syntax error
+    Error {
+        span: Span
+    }
+}
+
+/// The branch following the block of an if statement.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Else {
+    /// An 'else if' branch, which has the same form as an if statement and so can be chained
+    If {
+        cond: Expr,
+        then: Block,
+        else_: Option<Box<Else>>,
+        span: Span
+    },
+    /// The final 'else' branch
+    Block {
+        block: Block,
+        span: Span
+    }
+}
+
+/// A braced block of nodes, as used by if, else and for statements.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Block {
+    pub nodes: Vec<Node>,
+    pub span: Span
+}
+
+/// A name written in the template, such as the name of an element or a variable. The
+/// name of a value to look up may also be a path into it, such as 'site.posts'.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Ident {
+    pub name: String,
+    pub span: Span
+}
+
+/// An entry in the attribute list of an element.
 #[derive(Debug, PartialEq, Clone)]
-pub enum AstType {
-    Template,
-    Element,
-    Ident,
-    ElementName,
-    Contents,
-    Number,
-    AttrList,
-    IfExpr,
-    ForExpr,
-    AssignExpr,
-    VariableValue,
-    Include,
+pub enum Attr {
+    /// An attribute such as 'id: "main"'. An attribute written without a value, such
+    /// as 'disabled', has no value.
+    Named {
+        name: Ident,
+        value: Option<Expr>
+    },
+    /// Source skipped by the parser while recovering from a syntax error in the list
+    Error {
+        span: Span
+    }
+}
+
+/// One part of the text contents of an element.
+#[derive(Debug, PartialEq, Clone)]
+pub enum TextPart {
+    /// Text written exactly as it appeared in the template
+    Text {
+        text: String,
+        span: Span
+    },
+    /// A value inserted into the text, such as '%name' or '%{ x * 2 }'
+    Value(Expr),
+    /// An included template or html file, such as '&footer'
+    Include {
+        path: String,
+        span: Span
+    }
+}
+
+/// An expression, such as a condition, the value of a variable or of an attribute.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Expr {
+    /// A number, such as '10' or '1.5'
+    Number {
+        val: Number,
+        span: Span
+    },
+    /// A string literal, with its escape sequences already applied
+    Str {
+        val: String,
+        span: Span
+    },
+    /// A string literal containing interpolations, such as "/users/%{ id }". The parts
+    /// are Str literals and Interpolations, in the order they were written.
+    InterpolatedString {
+        parts: Vec<Expr>,
+        span: Span
+    },
+    /// A bare word, which is either 'true', 'false' or the name of a variable
+    Ident(Ident),
+    /// A reference to a variable, such as '%user.name'
+    Var(Ident),
+    /// An expression interpolation, such as '%{ x * 2 }'
+    Interpolation {
+        expr: Box<Expr>,
+        span: Span
+    },
+    /// A value marked with '%!' as trusted html, which isn't escaped
+    Raw {
+        value: Box<Expr>,
+        span: Span
+    },
+    /// An operator applied to a single operand, such as '!done' or '-x'
+    Unary {
+        op: UnaryOp,
+        operand: Box<Expr>,
+        span: Span
+    },
+    /// An operator applied to two operands, such as 'x + 1' or 'a && b'
+    Binary {
+        op: BinaryOp,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+        span: Span
+    },
+    /// A conditional value, such as 'if %active "on" else "off"'. It has no value if
+    /// the condition is false and there is no else branch.
+    Conditional {
+        cond: Box<Expr>,
+        then: Box<Expr>,
+        else_: Option<Box<Expr>>,
+        span: Span
+    },
+    /// An expression that couldn't be parsed
+    Error {
+        span: Span
+    }
+}
+
+/// The value of a number literal. A number written with a '.' is a float.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Number {
+    Int(i64),
+    Float(f64)
+}
+
+/// A type that can be declared for a variable, such as the 'int' in 'let x: int = 10'.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum VarType {
+    Int,
+    Float,
+    Bool,
+    Str,
+    List,
+    Map
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum UnaryOp {
+    Negate,
+    Not
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum BinaryOp {
     Plus,
     Minus,
+    Multiply,
+    Divide,
+    Modulo,
     EqualsEquals,
+    NotEquals,
     Gt,
     Lt,
     GtEquals,
     LtEquals,
-    NotEquals,
-    Empty,
-    Eof
+    And,
+    Or
 }
 
-#[derive(Debug, Clone)]
-pub struct Ast {
-    pub ast_type: AstType,
-    pub val: String,
-    pub var_type: Option<String>,
-    pub children: Vec<Box<Ast>>
+impl Node {
+    /// Location of the source text this node was parsed from.
+    pub fn span(&self) -> Span {
+        match *self {
+            Node::Element { span, .. }
+            | Node::VoidElement { span, .. }
+            | Node::Text { span, .. }
+            | Node::Let { span, .. }
+            | Node::If { span, .. }
+            | Node::For { span, .. }
+            | Node::Include { span, .. }
+            | Node::Comment { span, .. }
+            | Node::Doctype { span, .. }
+            | Node::Raw { span, .. }
+            | Node::Error { span } => span,
+            Node::Block(ref block) => block.span
+        }
+    }
 }
 
-impl Ast {
-    pub fn new(t: AstType) -> Ast {
-        let c = Vec::new();
-        Ast {
-            ast_type: t,
-            val: "".to_owned(),
-            var_type: None,
-            children: c
+impl Else {
+    /// Location of the source text this branch was parsed from, starting at its 'else'.
+    pub fn span(&self) -> Span {
+        match *self {
+            Else::If { span, .. } | Else::Block { span, .. } => span
         }
     }
+}
 
-    /// "Overloaded" new function, used to create a new ast node
-    /// but with a defined value instead of using an empty string,
-    /// as in the above new() method.
-    pub fn new_from_value(t: AstType, v: &str) -> Ast {
-        let c = Vec::new();
-        Ast {
-            ast_type: t,
-            val: v.to_owned(),
-            var_type: None,
-            children: c
+impl TextPart {
+    /// Location of the source text this part was parsed from.
+    pub fn span(&self) -> Span {
+        match *self {
+            TextPart::Text { span, .. } | TextPart::Include { span, .. } => span,
+            TextPart::Value(ref expr) => expr.span()
         }
     }
 }
+
+impl Expr {
+    /// Location of the source text this expression was parsed from.
+    pub fn span(&self) -> Span {
+        match *self {
+            Expr::Number { span, .. }
+            | Expr::Str { span, .. }
+            | Expr::InterpolatedString { span, .. }
+            | Expr::Interpolation { span, .. }
+            | Expr::Raw { span, .. }
+            | Expr::Unary { span, .. }
+            | Expr::Binary { span, .. }
+            | Expr::Conditional { span, .. }
+            | Expr::Error { span } => span,
+            Expr::Ident(ref ident) | Expr::Var(ref ident) => ident.span
+        }
+    }
+
+    /// The word, if this is a bare word that is a string rather than a variable, as in
+    /// 'let name: string = tank'. A single name is a string unless is_declared says that
+    /// a variable with that name is declared before it. Paths such as 'user.name' are
+    /// always variables, so that a typo in one is reported, and 'true' and 'false' are
+    /// always bools. Used for the values of declarations, by both the type checker and
+    /// the generator.
+    pub fn bare_word<F: FnOnce(&str) -> bool>(&self, is_declared: F) -> Option<&str> {
+        match *self {
+            Expr::Ident(ref ident) if ident.name != "true" && ident.name != "false" &&
+                !ident.name.contains(['.', '[']) && !is_declared(&ident.name) => Some(&ident.name),
+            _ => None
+        }
+    }
+}
+
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Number::Int(i) => write!(f, "{}", i),
+            Number::Float(x) => write!(f, "{}", x)
+        }
+    }
+}
+
+impl VarType {
+    /// Every type that can be declared.
+    pub const ALL: [VarType; 6] = [VarType::Int, VarType::Float, VarType::Bool, VarType::Str,
+                                   VarType::List, VarType::Map];
+
+    /// Find the type with the name it is declared by, such as 'int'.
+    pub fn from_name(name: &str) -> Option<VarType> {
+        VarType::ALL.iter().cloned().find(|var_type| var_type.name() == name)
+    }
+
+    /// Name of the type as it is declared, which is also the name of the type of the
+    /// values it holds.
+    pub fn name(self) -> &'static str {
+        match self {
+            VarType::Int => "int",
+            VarType::Float => "float",
+            VarType::Bool => "bool",
+            VarType::Str => "string",
+            VarType::List => "list",
+            VarType::Map => "map"
+        }
+    }
+}
+
+impl fmt::Display for VarType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl BinaryOp {
+    /// Whether the operator compares its operands, giving a bool.
+    pub fn is_comparison(self) -> bool {
+        matches!(self, BinaryOp::EqualsEquals | BinaryOp::NotEquals | BinaryOp::Gt |
+                       BinaryOp::Lt | BinaryOp::GtEquals | BinaryOp::LtEquals)
+    }
+}
+
+/// Walks a template without changing it, such as to lint or analyze it. Each method is
+/// called for every node of its kind, and by default carries on into the children of the
+/// node through the matching walk function. An implementation overrides the methods for
+/// the nodes it cares about, and calls the walk function from them to keep going deeper:
+///
+/// ```
+/// use tank::syntax::ast::{walk_expr, Expr, Visitor};
+///
+/// /// Collects the names of the variables used in a template.
+/// struct Vars(Vec<String>);
+///
+/// impl Visitor for Vars {
+///     fn visit_expr(&mut self, expr: &Expr) {
+///         if let Expr::Var(ref var) = *expr {
+///             self.0.push(var.name.clone());
+///         }
+///
+///         walk_expr(self, expr);
+///     }
+/// }
+/// ```
+pub trait Visitor {
+    fn visit_template(&mut self, template: &Template) {
+        walk_template(self, template);
+    }
+
+    fn visit_node(&mut self, node: &Node) {
+        walk_node(self, node);
+    }
+
+    fn visit_block(&mut self, block: &Block) {
+        walk_block(self, block);
+    }
+
+    fn visit_else(&mut self, else_branch: &Else) {
+        walk_else(self, else_branch);
+    }
+
+    fn visit_attr(&mut self, attr: &Attr) {
+        walk_attr(self, attr);
+    }
+
+    fn visit_text_part(&mut self, part: &TextPart) {
+        walk_text_part(self, part);
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        walk_expr(self, expr);
+    }
+
+    /// Called for the names of elements, variables and attributes, which have no children.
+    fn visit_ident(&mut self, _ident: &Ident) {}
+}
+
+pub fn walk_template<V: Visitor + ?Sized>(visitor: &mut V, template: &Template) {
+    for node in &template.nodes {
+        visitor.visit_node(node);
+    }
+}
+
+pub fn walk_node<V: Visitor + ?Sized>(visitor: &mut V, node: &Node) {
+    match *node {
+        Node::Element { ref name, ref attrs, ref body, .. } => {
+            visitor.visit_ident(name);
+
+            for attr in attrs {
+                visitor.visit_attr(attr);
+            }
+
+            for node in body {
+                visitor.visit_node(node);
+            }
+        },
+        Node::VoidElement { ref name, ref attrs, .. } => {
+            visitor.visit_ident(name);
+
+            for attr in attrs {
+                visitor.visit_attr(attr);
+            }
+        },
+        Node::Text { ref parts, .. } => {
+            for part in parts {
+                visitor.visit_text_part(part);
+            }
+        },
+        Node::Let { ref var, ref value, .. } => {
+            visitor.visit_ident(var);
+            visitor.visit_expr(value);
+        },
+        Node::If { ref cond, ref then, ref else_, .. } => {
+            visitor.visit_expr(cond);
+            visitor.visit_block(then);
+
+            if let Some(ref else_branch) = *else_ {
+                visitor.visit_else(else_branch);
+            }
+        },
+        Node::For { ref var, ref iter, ref body, .. } => {
+            visitor.visit_ident(var);
+            visitor.visit_ident(iter);
+            visitor.visit_block(body);
+        },
+        Node::Block(ref block) => visitor.visit_block(block),
+        Node::Include { .. } | Node::Comment { .. } | Node::Doctype { .. } | Node::Raw { .. }
+        | Node::Error { .. } => {}
+    }
+}
+
+pub fn walk_block<V: Visitor + ?Sized>(visitor: &mut V, block: &Block) {
+    for node in &block.nodes {
+        visitor.visit_node(node);
+    }
+}
+
+pub fn walk_else<V: Visitor + ?Sized>(visitor: &mut V, else_branch: &Else) {
+    match *else_branch {
+        Else::If { ref cond, ref then, ref else_, .. } => {
+            visitor.visit_expr(cond);
+            visitor.visit_block(then);
+
+            if let Some(ref else_branch) = *else_ {
+                visitor.visit_else(else_branch);
+            }
+        },
+        Else::Block { ref block, .. } => visitor.visit_block(block)
+    }
+}
+
+pub fn walk_attr<V: Visitor + ?Sized>(visitor: &mut V, attr: &Attr) {
+    if let Attr::Named { ref name, ref value } = *attr {
+        visitor.visit_ident(name);
+
+        if let Some(ref value) = *value {
+            visitor.visit_expr(value);
+        }
+    }
+}
+
+pub fn walk_text_part<V: Visitor + ?Sized>(visitor: &mut V, part: &TextPart) {
+    if let TextPart::Value(ref value) = *part {
+        visitor.visit_expr(value);
+    }
+}
+
+pub fn walk_expr<V: Visitor + ?Sized>(visitor: &mut V, expr: &Expr) {
+    match *expr {
+        Expr::InterpolatedString { ref parts, .. } => {
+            for part in parts {
+                visitor.visit_expr(part);
+            }
+        },
+        Expr::Ident(ref ident) | Expr::Var(ref ident) => visitor.visit_ident(ident),
+        Expr::Interpolation { expr: ref inner, .. } => visitor.visit_expr(inner),
+        Expr::Raw { ref value, .. } => visitor.visit_expr(value),
+        Expr::Unary { ref operand, .. } => visitor.visit_expr(operand),
+        Expr::Binary { ref lhs, ref rhs, .. } => {
+            visitor.visit_expr(lhs);
+            visitor.visit_expr(rhs);
+        },
+        Expr::Conditional { ref cond, ref then, ref else_, .. } => {
+            visitor.visit_expr(cond);
+            visitor.visit_expr(then);
+
+            if let Some(ref else_) = *else_ {
+                visitor.visit_expr(else_);
+            }
+        },
+        Expr::Number { .. } | Expr::Str { .. } | Expr::Error { .. } => {}
+    }
+}
+
+/// Walks a template and may change it in place, such as to minify or rewrite it. Works in
+/// the same way as Visitor, with the walk_*_mut functions carrying on into the children of
+/// each node. Nodes can be replaced outright by assigning to them, or removed by overriding
+/// the method for their parent and changing its list of children.
+pub trait MutVisitor {
+    fn visit_template_mut(&mut self, template: &mut Template) {
+        walk_template_mut(self, template);
+    }
+
+    fn visit_node_mut(&mut self, node: &mut Node) {
+        walk_node_mut(self, node);
+    }
+
+    fn visit_block_mut(&mut self, block: &mut Block) {
+        walk_block_mut(self, block);
+    }
+
+    fn visit_else_mut(&mut self, else_branch: &mut Else) {
+        walk_else_mut(self, else_branch);
+    }
+
+    fn visit_attr_mut(&mut self, attr: &mut Attr) {
+        walk_attr_mut(self, attr);
+    }
+
+    fn visit_text_part_mut(&mut self, part: &mut TextPart) {
+        walk_text_part_mut(self, part);
+    }
+
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        walk_expr_mut(self, expr);
+    }
+
+    /// Called for the names of elements, variables and attributes, which have no children.
+    fn visit_ident_mut(&mut self, _ident: &mut Ident) {}
+}
+
+pub fn walk_template_mut<V: MutVisitor + ?Sized>(visitor: &mut V, template: &mut Template) {
+    for node in &mut template.nodes {
+        visitor.visit_node_mut(node);
+    }
+}
+
+pub fn walk_node_mut<V: MutVisitor + ?Sized>(visitor: &mut V, node: &mut Node) {
+    match *node {
+        Node::Element { ref mut name, ref mut attrs, ref mut body, .. } => {
+            visitor.visit_ident_mut(name);
+
+            for attr in attrs {
+                visitor.visit_attr_mut(attr);
+            }
+
+            for node in body {
+                visitor.visit_node_mut(node);
+            }
+        },
+        Node::VoidElement { ref mut name, ref mut attrs, .. } => {
+            visitor.visit_ident_mut(name);
+
+            for attr in attrs {
+                visitor.visit_attr_mut(attr);
+            }
+        },
+        Node::Text { ref mut parts, .. } => {
+            for part in parts {
+                visitor.visit_text_part_mut(part);
+            }
+        },
+        Node::Let { ref mut var, ref mut value, .. } => {
+            visitor.visit_ident_mut(var);
+            visitor.visit_expr_mut(value);
+        },
+        Node::If { ref mut cond, ref mut then, ref mut else_, .. } => {
+            visitor.visit_expr_mut(cond);
+            visitor.visit_block_mut(then);
+
+            if let Some(ref mut else_branch) = *else_ {
+                visitor.visit_else_mut(else_branch);
+            }
+        },
+        Node::For { ref mut var, ref mut iter, ref mut body, .. } => {
+            visitor.visit_ident_mut(var);
+            visitor.visit_ident_mut(iter);
+            visitor.visit_block_mut(body);
+        },
+        Node::Block(ref mut block) => visitor.visit_block_mut(block),
+        Node::Include { .. } | Node::Comment { .. } | Node::Doctype { .. } | Node::Raw { .. }
+        | Node::Error { .. } => {}
+    }
+}
+
+pub fn walk_block_mut<V: MutVisitor + ?Sized>(visitor: &mut V, block: &mut Block) {
+    for node in &mut block.nodes {
+        visitor.visit_node_mut(node);
+    }
+}
+
+pub fn walk_else_mut<V: MutVisitor + ?Sized>(visitor: &mut V, else_branch: &mut Else) {
+    match *else_branch {
+        Else::If { ref mut cond, ref mut then, ref mut else_, .. } => {
+            visitor.visit_expr_mut(cond);
+            visitor.visit_block_mut(then);
+
+            if let Some(ref mut else_branch) = *else_ {
+                visitor.visit_else_mut(else_branch);
+            }
+        },
+        Else::Block { ref mut block, .. } => visitor.visit_block_mut(block)
+    }
+}
+
+pub fn walk_attr_mut<V: MutVisitor + ?Sized>(visitor: &mut V, attr: &mut Attr) {
+    if let Attr::Named { ref mut name, ref mut value } = *attr {
+        visitor.visit_ident_mut(name);
+
+        if let Some(ref mut value) = *value {
+            visitor.visit_expr_mut(value);
+        }
+    }
+}
+
+pub fn walk_text_part_mut<V: MutVisitor + ?Sized>(visitor: &mut V, part: &mut TextPart) {
+    if let TextPart::Value(ref mut value) = *part {
+        visitor.visit_expr_mut(value);
+    }
+}
+
+pub fn walk_expr_mut<V: MutVisitor + ?Sized>(visitor: &mut V, expr: &mut Expr) {
+    match *expr {
+        Expr::InterpolatedString { ref mut parts, .. } => {
+            for part in parts {
+                visitor.visit_expr_mut(part);
+            }
+        },
+        Expr::Ident(ref mut ident) | Expr::Var(ref mut ident) => visitor.visit_ident_mut(ident),
+        Expr::Interpolation { expr: ref mut inner, .. } => visitor.visit_expr_mut(inner),
+        Expr::Raw { ref mut value, .. } => visitor.visit_expr_mut(value),
+        Expr::Unary { ref mut operand, .. } => visitor.visit_expr_mut(operand),
+        Expr::Binary { ref mut lhs, ref mut rhs, .. } => {
+            visitor.visit_expr_mut(lhs);
+            visitor.visit_expr_mut(rhs);
+        },
+        Expr::Conditional { ref mut cond, ref mut then, ref mut else_, .. } => {
+            visitor.visit_expr_mut(cond);
+            visitor.visit_expr_mut(then);
+
+            if let Some(ref mut else_) = *else_ {
+                visitor.visit_expr_mut(else_);
+            }
+        },
+        Expr::Number { .. } | Expr::Str { .. } | Expr::Error { .. } => {}
+    }
+}
@@ -0,0 +1,19 @@
+/// Html elements that can never have contents, and so are written without a closing
+/// tag, eg. '<br>' or '<img src="...">'.
+pub const VOID_ELEMENTS: [&str; 13] = [
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source",
+    "track", "wbr"
+];
+
+/// Checks if an element name is one of the html void elements. Html element names
+/// aren't case sensitive, so neither is this check.
+pub fn is_void_element(name: &str) -> bool {
+    VOID_ELEMENTS.iter().any(|void| void.eq_ignore_ascii_case(name))
+}
+
+/// Checks if a bool value should be written as the text 'true' or 'false' for this
+/// attribute, rather than making it a boolean attribute that is either present or left
+/// out. Data and aria attributes use 'true' and 'false' as values.
+pub fn keeps_bool_text(attr_name: &str) -> bool {
+    attr_name.starts_with("data-") || attr_name.starts_with("aria-")
+}
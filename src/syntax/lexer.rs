@@ -1,5 +1,7 @@
+use std::str::Chars;
 use syntax::token::{Token, TokenType};
 use syntax::reserved::Reserved;
+use syntax::span::Span;
 
 // EOF isn't technically a char, but we can use this as a stand in when unwrapping things.
 const EOF: char = '\0';
@@ -8,18 +10,32 @@ const EOF: char = '\0';
 pub struct Lexer {
     /// Contents of input file
     input: String,
-    /// Numbering of current char in the input string
-    char_count: usize,
+    /// Byte offset of the current char in the input string. Lexing only ever
+    /// moves this forward, so each char is visited once.
+    pos: usize,
     /// Line number of input
     line_num: usize,
     /// Char count of current line
     line_char_num: usize,
+    /// Start of the token currently being lexed, set by mark()
+    tok_start: Span,
     /// Reserved word struct for checking if identifiers are valid
     pub reserved: Reserved,
     /// Last token to be consumed
     pub curr_tok: Option<Token>,
     /// Last char seen by the lexer
-    pub curr_char: Option<char>
+    pub curr_char: Option<char>,
+    /// Quotes of the string literals we are inside of, when lexing the expression of
+    /// a '%{ expr }' interpolation. The last one closes the innermost string.
+    string_quotes: Vec<char>,
+    /// Number of expressions we are inside of, such as the value of a let or a
+    /// '%{ expr }' interpolation. In an expression '-' is always an operator, so
+    /// 'x-1' is a subtraction rather than a name.
+    expr_depth: usize,
+    /// Byte offset of the last line scanned for attribute lists by at_element_start(),
+    /// and whether an attribute list starting at each byte offset from there to the end
+    /// of the line is closed by ') ->' or ');'.
+    attr_lists: (usize, Vec<bool>)
 }
 
 
@@ -29,61 +45,81 @@ impl Lexer {
     /// in the struct.
     pub fn new(file_contents: String) -> Lexer {
         let r = Reserved::new();
-        let c = file_contents.chars().nth(0);
+        let c = file_contents.chars().next();
 
         Lexer {
             input: file_contents,
-            char_count: 1,
+            pos: 0,
             line_num: 1,
             line_char_num: 1,
+            tok_start: Span::new(0, 0, 1, 1),
             reserved: r,
             curr_tok: None,
             curr_char: c,
+            string_quotes: Vec::new(),
+            expr_depth: 0,
+            attr_lists: (0, Vec::new())
         }
     }
 
-    /// Lex the input at the current point, represented by the char_count field.
+    /// Lex the input at the current point, represented by the pos field.
     /// Once lexing is complete, sets the curr_tok field to the correct token.
     ///
     /// After this function is called, the curr_tok field should never be None.
     /// If we cannot find a proper token during lexing, we will return the token
     /// for EOF.
+    ///
+    /// Whitespace, '//' line comments and '/* */' block comments are skipped
+    /// before the token is lexed.
     pub fn lex(&mut self) -> &mut Lexer {
-        if self.curr_char.is_none() {
-            self.curr_tok = Some(Token::new_from_empty());
-            return self;
+        loop {
+            match self.curr_char {
+                None => {
+                    self.mark();
+                    self.curr_tok = Some(Token::new(TokenType::Eof, self.span()));
+                    return self;
+                },
+                Some(c) if c.is_whitespace() => {
+                    self.get_char();
+                },
+                Some('/') if self.peek(0) == Some('/') => {
+                    self.skip_line_comment();
+                },
+                Some('/') if self.peek(0) == Some('*') => {
+                    self.mark();
+
+                    if !self.skip_block_comment() {
+                        self.curr_tok = Some(Token::new_from_value(TokenType::Error,
+                                                                   "Unterminated block comment",
+                                                                   self.span()));
+                        return self;
+                    }
+                },
+                Some(_) => break
+            }
         }
 
         // curr_char is guaranteed to be Some here.
-        let mut ch = self.curr_char.unwrap();
+        let ch = self.curr_char.unwrap();
+        self.mark();
 
         if ch == EOF {
-            self.curr_tok = Some(Token::new_from_empty());
+            self.curr_tok = Some(Token::new(TokenType::Eof, self.span()));
             return self;
         }
 
-        while ch.is_whitespace() {
-            if ch == '\n' {
-                self.line_num = self.line_num + 1;
-                self.line_char_num = 1;
-            }
-
-            self.get_char();
-            if self.curr_char.is_none() {
-                self.curr_tok = Some(Token::new_from_empty());
-                return self;
-            }
-
-            ch = self.curr_char.unwrap();
-        }
-
         match ch {
             '{' => self.curr_tok = self.get_token(TokenType::LeftBrace),
             '}' => self.curr_tok = self.get_token(TokenType::RightBrace),
             '(' => self.curr_tok = self.get_token(TokenType::LeftParen),
             ')' => self.curr_tok = self.get_token(TokenType::RightParen),
             ':' => self.curr_tok = self.get_token(TokenType::Colon),
+            ';' => self.curr_tok = self.get_token(TokenType::Semicolon),
             '+' => self.curr_tok = self.get_token(TokenType::Plus),
+            '*' => self.curr_tok = self.get_token(TokenType::Star),
+            '/' => self.curr_tok = self.get_token(TokenType::Slash),
+            '&' if self.peek(0) == Some('&') => self.curr_tok = self.lex_operator_logical(),
+            '|' => self.curr_tok = self.lex_operator_logical(),
             '&' => self.curr_tok = self.get_token(TokenType::Ampersand),
             '%' => self.curr_tok = self.get_token(TokenType::Percent),
             '=' => self.curr_tok = self.lex_operator_equals(),
@@ -91,6 +127,8 @@ impl Lexer {
             '>' => self.curr_tok = self.lex_operator_cmp(),
             '<' => self.curr_tok = self.lex_operator_cmp(),
             '-' => self.curr_tok = self.get_minus_or_arrow(),
+            '"' | '\'' => self.curr_tok = self.lex_string_literal(),
+            '#' if self.peek(0) == Some('!') => self.curr_tok = self.lex_html_comment(),
             _   => self.curr_tok = self.lex_word_or_number()
         }
 
@@ -101,150 +139,424 @@ impl Lexer {
     /// those characters. Used by the parser to determine some context
     /// about element declarations and their contents.
     ///
-    /// Currently, only checks if the current char is '(', so we can
+    /// Currently, only checks if the next non-whitespace char is '(', so we can
     /// decide the difference between an element name and its contents.
     pub fn peek_tok(&self) -> Token {
-        let tok = match self.curr_char.unwrap_or(EOF) {
-            '(' => Token::new(TokenType::LeftParen, self.line_char_num, self.line_num),
-            _ => Token::new_from_empty()
-        };
+        let mut line = self.line_num;
+        let mut col = self.line_char_num;
+
+        for (offset, ch) in self.input[self.pos..].char_indices() {
+            match ch {
+                '\n' => {
+                    line += 1;
+                    col = 1;
+                },
+                c if c.is_whitespace() => col += 1,
+                '(' => {
+                    let start = self.pos + offset;
+                    return Token::new(TokenType::LeftParen, Span::new(start, start + 1, line, col));
+                },
+                _ => break
+            }
+        }
 
-        tok
+        Token::new_from_empty()
     }
 
-    /// Returns the next available char from the file contents. If no
-    /// char is available (ie. at end of input, or when the char_count
-    /// field is greater than the number of chars in the file), then
-    /// None is returned.
+    /// Advances to the next available char in the file contents. If no
+    /// char is available (ie. at end of input), then curr_char is set to None.
+    ///
+    /// Line and column tracking happens here, so that any construct spanning
+    /// multiple lines (whitespace, string literals) keeps the position correct.
     fn get_char(&mut self) -> &mut Lexer {
-        //TODO: O(n)
-        match self.input.chars().nth(self.char_count) {
-            Some(c) => self.curr_char = Some(c),
-            None => self.curr_char = None
+        if let Some(c) = self.curr_char {
+            if c == '\n' {
+                self.line_num += 1;
+                self.line_char_num = 1;
+            } else {
+                self.line_char_num += 1;
+            }
+
+            self.pos += c.len_utf8();
         }
 
-        self.char_count = self.char_count + 1;
-        self.line_char_num = self.line_char_num + 1;
+        self.curr_char = self.input[self.pos..].chars().next();
 
         self
     }
 
+    /// Marks the current char as the start of the next token. The span of the
+    /// token is then completed by span(), once all of its chars are consumed.
+    fn mark(&mut self) {
+        self.tok_start = Span::new(self.pos, self.pos, self.line_num, self.line_char_num);
+    }
+
+    /// Returns the span from the last call to mark() up to the current char.
+    fn span(&self) -> Span {
+        Span::new(self.tok_start.start, self.pos, self.tok_start.line, self.tok_start.col)
+    }
+
     /// Retrieve a new token based on the current character, or None
     /// if no curr_char exists. This function is only used for tokens
     /// that have single character values.
     ///
     /// After a token is created, we advance the current char pointer.
     fn get_token(&mut self, token_type: TokenType) -> Option<Token> {
-        let tok = match self.curr_char {
-            Some(c) => Some(Token::new_from_value(token_type,
-                                                  &c.to_string(),
-                                                  self.line_char_num,
-                                                  self.line_num)),
-            None => Some(Token::new_from_empty())
+        let c = match self.curr_char {
+            Some(c) => c,
+            None => return Some(Token::new(TokenType::Eof, self.span()))
         };
 
         self.get_char();
 
-        tok
+        Some(Token::new_from_value(token_type, &c.to_string(), self.span()))
     }
 
     /// Called when a '-' characted is encountered. Checks for a following
     /// '>' character, then returns a token for either a minus sign or an arrow.
     fn get_minus_or_arrow(&mut self) -> Option<Token> {
         let ch = self.peek(0).unwrap_or(EOF);
-        let tok;
 
         if ch == '>' {
-            // Consume the '-' char here, the '>' is consumed below.
             self.get_char();
-            tok = Some(Token::new_from_value(TokenType::Arrow,
-                                             &"->",
-                                             self.line_char_num - 1,
-                                             self.line_num));
+            self.get_char();
+            Some(Token::new_from_value(TokenType::Arrow, "->", self.span()))
         } else {
-            tok = Some(Token::new_from_value(TokenType::Minus, &"-",
-                                             self.line_char_num,
-                                             self.line_num));
+            self.get_char();
+            Some(Token::new_from_value(TokenType::Minus, "-", self.span()))
         }
-
-        self.get_char();
-
-        tok
     }
 
     /// When we encounter a potential identifier, we continue lexing here
     /// to build the full string or number. The char pointer will be advanced
-    /// to the end of the word.
+    /// to the end of the word. Identifiers may contain letters, digits, '_' and '-',
+    /// so that names like 'data-id' can be used for attributes, and '.' between names.
     ///
     /// Also checks for reserved words if we have a valid identifier. Sets the
     /// proper token field to true if the word is reserved, then we can deal with
     /// it during parsing.
     fn lex_word_or_number(&mut self) -> Option<Token> {
-        let mut ch = self.curr_char.unwrap_or(EOF);
-        let mut ident = ch.to_string();
+        let ch = self.curr_char.unwrap_or(EOF);
+        let mut ident = String::new();
         let tok;
 
         if ch.is_alphabetic() {
+            self.lex_ident_rest(&mut ident);
+            tok = Some(self.ident_token(&ident));
+        } else if ch.is_ascii_digit() {
+            self.lex_digits(&mut ident);
+
+            // A float, such as '1.5', has digits on both sides of its '.'.
+            if self.curr_char == Some('.') && self.peek(0).is_some_and(|c| c.is_ascii_digit()) {
+                ident.push('.');
+                self.get_char();
+                self.lex_digits(&mut ident);
+            }
+
+            tok = Some(Token::new_from_value(TokenType::Number, &ident, self.span()));
+        } else {
+            // Consume the character, so that the parser can report it and carry on.
             self.get_char();
-            ch = self.curr_char.unwrap_or(EOF);
 
-            while self.alphanumeric_or_valid_punc(ch) {
-                let append = self.curr_char.unwrap_or(EOF);
-                if !self.is_valid_char_in_ident(append) {
-                    break;
+            let err = format!("Unexpected character '{}'", ch);
+            tok = Some(Token::new_from_value(TokenType::Error, &err, self.span()));
+        }
+
+        tok
+    }
+
+    /// Consume a run of digits, adding them to number.
+    fn lex_digits(&mut self, number: &mut String) {
+        while let Some(append) = self.curr_char {
+            if !append.is_ascii_digit() {
+                break;
+            }
+
+            number.push(append);
+            self.get_char();
+        }
+    }
+
+    /// Consume the rest of an identifier, adding its chars to ident.
+    fn lex_ident_rest(&mut self, ident: &mut String) {
+        while let Some(append) = self.curr_char {
+            // An index into a list, such as the '[2]' in 'items[2]', is kept as
+            // part of the identifier so that the whole path is looked up at once.
+            // The path only goes on after the ']' with another field or index.
+            if append == '[' && self.at_index() {
+                while let Some(index_char) = self.curr_char {
+                    ident.push(index_char);
+                    self.get_char();
+
+                    if index_char == ']' {
+                        break;
+                    }
                 }
 
-                if append.is_alphanumeric() {
-                    ident = ident + &append.to_string();
+                if self.curr_char != Some('.') && self.curr_char != Some('[') {
+                    break;
                 }
 
+                continue;
+            }
+
+            if !is_ident_char(append, self.peek(0)) || (append == '-' && self.expr_depth > 0) {
+                break;
+            }
+
+            ident.push(append);
+            self.get_char();
+        }
+    }
+
+    /// Build the token for an identifier, from the last call to mark() up to the current char.
+    fn ident_token(&self, ident: &str) -> Token {
+        let mut some_tok = Token::new_from_value(TokenType::Ident, ident, self.span());
+
+        // Match on reserved words
+        // TODO: way better reserved word handling is needed here.
+        // The is_reserved field is not useful.
+        match self.reserved.words.get(ident) {
+            Some(_) => some_tok.set_reserved(true),
+            None => some_tok.set_reserved(false)
+        };
+
+        some_tok
+    }
+
+    /// Start lexing an expression, where a '-' is never part of an identifier. Called
+    /// by the parser before the first token of the expression is lexed.
+    pub fn enter_expr(&mut self) -> &mut Lexer {
+        self.expr_depth += 1;
+
+        self
+    }
+
+    /// Stop lexing an expression. The token following the expression has already been
+    /// lexed by then, so if it is an identifier that was cut short at a '-', such as the
+    /// element name 'data-list', the rest of it is lexed now.
+    pub fn exit_expr(&mut self) -> &mut Lexer {
+        self.expr_depth -= 1;
+
+        if self.expr_depth > 0 || self.curr_char != Some('-') {
+            return self;
+        }
+
+        let mut ident = match self.curr_tok {
+            Some(ref tok) if tok.tok_type == TokenType::Ident => tok.val.clone(),
+            _ => return self
+        };
+
+        self.lex_ident_rest(&mut ident);
+        self.curr_tok = Some(self.ident_token(&ident));
+
+        self
+    }
+
+    /// Skip a '//' comment. Everything up to the end of the current line is
+    /// consumed, but the newline itself is left for the whitespace handling in lex().
+    fn skip_line_comment(&mut self) {
+        while let Some(c) = self.curr_char {
+            if c == '\n' {
+                break;
+            }
+
+            self.get_char();
+        }
+    }
+
+    /// Skip a '/* */' comment. Block comments may be nested, so we keep track of
+    /// the depth and only stop once the outermost comment is closed. Returns false
+    /// if the input ends before that happens.
+    fn skip_block_comment(&mut self) -> bool {
+        let mut depth = 0;
+
+        while let Some(c) = self.curr_char {
+            if c == '/' && self.peek(0) == Some('*') {
+                depth += 1;
+                self.get_char();
+            } else if c == '*' && self.peek(0) == Some('/') {
+                depth -= 1;
                 self.get_char();
-                ch = append;
             }
 
-            let mut some_tok = Token::new_from_value(TokenType::Ident,
-                                                     &ident,
-                                                     self.line_char_num - (ident.len() + 1),
-                                                     self.line_num);
-
-            // Match on reserved words
-            // TODO: way better reserved word handling is needed here.
-            // The is_reserved field is not useful.
-            match self.reserved.words.get(&ident) {
-                Some(_) => some_tok.set_reserved(true),
-                None => some_tok.set_reserved(false)
-            };
+            self.get_char();
+
+            if depth == 0 {
+                return true;
+            }
+        }
 
-            tok = Some(some_tok);
+        false
+    }
+
+    /// Called when '#!' is encountered. Unlike the other comment forms, these
+    /// comments are kept: the rest of the line becomes the value of an HtmlComment
+    /// token, which the generator writes out as an html comment.
+    fn lex_html_comment(&mut self) -> Option<Token> {
+        let mut value = String::new();
 
-        } else if ch.is_digit(10) {
+        // Consume the '#!'
+        self.get_char();
+        self.get_char();
+
+        while let Some(c) = self.curr_char {
+            if c == '\n' {
+                break;
+            }
+
+            value.push(c);
             self.get_char();
+        }
 
-            while ch.is_digit(10) {
-                let append = self.curr_char.unwrap_or(EOF);
-                if !append.is_whitespace() && append != EOF {
-                    ident = ident + &append.to_string();
+        Some(Token::new_from_value(TokenType::HtmlComment, value.trim(), self.span()))
+    }
+
+    /// Called when a '"' or '\'' character is encountered. Consumes everything up to
+    /// the matching closing quote and returns a StringLiteral token holding the
+    /// unescaped value. The value may span multiple lines.
+    ///
+    /// If the literal is never closed, or contains an invalid escape sequence, an
+    /// Error token is returned instead, with the error message as its value.
+    fn lex_string_literal(&mut self) -> Option<Token> {
+        let quote = self.curr_char.unwrap_or(EOF);
+
+        // Consume the opening quote.
+        self.get_char();
+        self.string_quotes.push(quote);
+
+        self.lex_string_body()
+    }
+
+    /// Continue lexing a string literal after the '}' closing one of its interpolations.
+    /// Returns the next part of the string, in the same way as lex_string_literal().
+    pub fn lex_string_rest(&mut self) -> &mut Lexer {
+        self.mark();
+
+        self.curr_tok = if self.string_quotes.is_empty() {
+            Some(Token::new_from_value(TokenType::Error, "Not inside a string literal", self.span()))
+        } else {
+            self.lex_string_body()
+        };
+
+        self
+    }
+
+    /// Lex the chars of a string literal up to its closing quote, or up to a '%{' that
+    /// starts an interpolation. In the first case the token is a StringLiteral, and in
+    /// the second it is a StringPart, with the '%{' consumed so that the expression
+    /// can be lexed as normal tokens. A literal '%{' can be written as '\\%{'.
+    fn lex_string_body(&mut self) -> Option<Token> {
+        let quote = self.string_quotes.last().cloned().unwrap_or(EOF);
+        let mut value = String::new();
+        let mut err: Option<String> = None;
+
+        loop {
+            let ch = match self.curr_char {
+                Some(c) => c,
+                None => {
+                    self.string_quotes.pop();
+                    return Some(Token::new_from_value(TokenType::Error,
+                                                      "Unterminated string literal",
+                                                      self.span()));
                 }
+            };
 
+            if ch == quote {
+                // Consume the closing quote.
                 self.get_char();
-                ch = append;
+                self.string_quotes.pop();
+                break;
             }
 
-            tok = Some(Token::new_from_value(TokenType::Number,
-                                             &ident,
-                                             self.line_char_num - (ident.len() + 1),
-                                             self.line_num));
-        } else {
-            tok = Some(Token::new_from_empty());
+            if ch == '%' && self.peek(0) == Some('{') && err.is_none() {
+                // Consume the '%', the '{' is left to be lexed as a token.
+                self.get_char();
+                return Some(Token::new_from_value(TokenType::StringPart, &value, self.span()));
+            }
+
+            if ch == '\\' {
+                // Consume the '\\', the escape is consumed by lex_escape.
+                self.get_char();
+                match self.lex_escape() {
+                    Ok(escaped) => value.push(escaped),
+                    Err(msg) => {
+                        // Keep the first error, but continue to the closing quote
+                        // so the rest of the literal isn't lexed as tokens.
+                        if err.is_none() {
+                            err = Some(msg);
+                        }
+                    }
+                }
+                continue;
+            }
+
+            value.push(ch);
+            self.get_char();
         }
 
-        tok
+        match err {
+            Some(msg) => Some(Token::new_from_value(TokenType::Error, &msg, self.span())),
+            None => Some(Token::new_from_value(TokenType::StringLiteral, &value, self.span()))
+        }
+    }
+
+    /// Lex the character following a '\\' in a string literal, and return the
+    /// character it represents. Supports \\n, \\t, \\r, \\", \\', \\%, \\\\ and unicode
+    /// escapes of the form \\u{1F600}. All characters of the escape are consumed.
+    fn lex_escape(&mut self) -> Result<char, String> {
+        let ch = match self.curr_char {
+            Some(c) => c,
+            None => return Err("Unterminated string literal".to_owned())
+        };
+
+        self.get_char();
+
+        match ch {
+            'n' => Ok('\n'),
+            't' => Ok('\t'),
+            'r' => Ok('\r'),
+            '"' => Ok('"'),
+            '\'' => Ok('\''),
+            '\\' => Ok('\\'),
+            '%' => Ok('%'),
+            'u' => {
+                if self.curr_char != Some('{') {
+                    return Err("Expected '{' in unicode escape".to_owned());
+                }
+                self.get_char();
+
+                let mut hex = String::new();
+                while let Some(c) = self.curr_char {
+                    if !c.is_ascii_hexdigit() {
+                        break;
+                    }
+                    hex.push(c);
+                    self.get_char();
+                }
+
+                if self.curr_char != Some('}') {
+                    return Err("Expected '}' in unicode escape".to_owned());
+                }
+                self.get_char();
+
+                if hex.is_empty() || hex.len() > 6 {
+                    return Err(format!("Invalid unicode escape '\\u{{{}}}'", hex));
+                }
+
+                // Fits in a u32, since there are at most 6 hex digits.
+                let code = u32::from_str_radix(&hex, 16).unwrap_or(0);
+                std::char::from_u32(code)
+                    .ok_or(format!("Invalid unicode escape '\\u{{{}}}'", hex))
+            },
+            _ => Err(format!("Unknown escape sequence '\\{}'", ch))
+        }
     }
 
     /// When we see an '=' or '!' character, we check the next character to determine
     /// what token to return. If the next token is an '=', then we have a two-character
     /// operator to use (either NotEquals or EqualsEquals). Otherwise, we can use a single
-    /// '=' character.
+    /// '=' or '!' character.
     fn lex_operator_equals(&mut self) -> Option<Token> {
         let ch = self.peek(0).unwrap_or(EOF);
         let tok;
@@ -253,35 +565,45 @@ impl Lexer {
             '=' => {
                 if ch == '=' {
                     self.get_char();
-                    tok = Some(Token::new_from_value(TokenType::EqualsEquals,
-                                                     &"==",
-                                                     self.line_char_num - 1,
-                                                     self.line_num));
+                    self.get_char();
+                    tok = Some(Token::new_from_value(TokenType::EqualsEquals, "==", self.span()));
                 } else {
-                    tok = Some(Token::new_from_value(TokenType::Equals,
-                                                     &"=",
-                                                     self.line_char_num,
-                                                     self.line_num));
+                    self.get_char();
+                    tok = Some(Token::new_from_value(TokenType::Equals, "=", self.span()));
                 }
             },
             '!' => {
                 if ch == '=' {
                     self.get_char();
-                    tok = Some(Token::new_from_value(TokenType::NotEquals,
-                                                     &"!=",
-                                                     self.line_char_num - 1,
-                                                     self.line_num));
+                    self.get_char();
+                    tok = Some(Token::new_from_value(TokenType::NotEquals, "!=", self.span()));
                 } else {
-                    // TODO: ! operator not supported yet
-                    tok = Some(Token::new_from_empty());
+                    self.get_char();
+                    tok = Some(Token::new_from_value(TokenType::Not, "!", self.span()));
                 }
             },
             _ => tok = Some(Token::new_from_empty())
         }
 
+        tok
+    }
+
+    /// Lex the '&&' and '||' operators. A single '&' is an include, and is handled
+    /// in lex(), but a single '|' has no meaning so an Error token is returned for it.
+    fn lex_operator_logical(&mut self) -> Option<Token> {
+        let ch = self.curr_char.unwrap_or(EOF);
         self.get_char();
 
-        tok
+        if self.curr_char != Some(ch) {
+            let err = format!("Unexpected character '{}'", ch);
+            return Some(Token::new_from_value(TokenType::Error, &err, self.span()));
+        }
+        self.get_char();
+
+        match ch {
+            '&' => Some(Token::new_from_value(TokenType::And, "&&", self.span())),
+            _ => Some(Token::new_from_value(TokenType::Or, "||", self.span()))
+        }
     }
 
     /// Determines if we have a two-character operator with the '>' and '<' characters. Checks
@@ -294,36 +616,26 @@ impl Lexer {
             '>' => {
                 if ch == '=' {
                     self.get_char();
-                    tok = Some(Token::new_from_value(TokenType::GtEquals,
-                                                     &">=",
-                                                     self.line_char_num - 1,
-                                                     self.line_num));
+                    self.get_char();
+                    tok = Some(Token::new_from_value(TokenType::GtEquals, ">=", self.span()));
                 } else {
-                    tok = Some(Token::new_from_value(TokenType::Gt,
-                                                     &">",
-                                                     self.line_char_num,
-                                                     self.line_num));
+                    self.get_char();
+                    tok = Some(Token::new_from_value(TokenType::Gt, ">", self.span()));
                 }
             },
             '<' => {
                 if ch == '=' {
                     self.get_char();
-                    tok = Some(Token::new_from_value(TokenType::LtEquals,
-                                                     &"<=",
-                                                     self.line_char_num - 1,
-                                                     self.line_num));
+                    self.get_char();
+                    tok = Some(Token::new_from_value(TokenType::LtEquals, "<=", self.span()));
                 } else {
-                    tok = Some(Token::new_from_value(TokenType::Lt,
-                                                     &"<",
-                                                     self.line_char_num,
-                                                     self.line_num));
+                    self.get_char();
+                    tok = Some(Token::new_from_value(TokenType::Lt, "<", self.span()));
                 }
             },
             _ => tok = Some(Token::new_from_empty())
         }
 
-        self.get_char();
-
         tok
     }
 
@@ -331,34 +643,362 @@ impl Lexer {
     /// that will be left to the get_char method. Allows for an offset value
     /// to be passed in, indicating how far to look ahead.
     fn peek(&self, offset: usize) -> Option<char> {
-        let next_ch = match self.input.chars().nth(self.char_count + offset) {
-            Some(c) => Some(c),
-            None => None
-        };
+        self.remaining().nth(offset + 1)
+    }
 
-        next_ch
+    /// Returns an iterator over the input, starting at the current char. Used
+    /// for lookahead of any length, without moving the lexer.
+    fn remaining(&self) -> Chars<'_> {
+        self.input[self.pos..].chars()
     }
 
-    /// Checks if an identifier contains an illegal character or not.
-    fn is_valid_char_in_ident(&self, ch: char) -> bool {
-        match ch {
-            ':' | '(' | ')' => false,
-            _ => true
+    /// Skip any whitespace at the current position, including newlines. Used by
+    /// the parser before deciding how to lex the contents of an element.
+    pub fn skip_whitespace(&mut self) {
+        while let Some(c) = self.curr_char {
+            if !c.is_whitespace() {
+                break;
+            }
+
+            self.get_char();
         }
     }
 
-    /// Determine if a char is a valid char in an identifier or in
-    /// the contents of an element.
-    fn alphanumeric_or_valid_punc(&self, ch: char) -> bool {
-        if ch.is_whitespace() || ch == EOF {
+    /// Looks ahead from the current char, without consuming anything, to see if an
+    /// element declaration starts here. That is, an identifier followed by an attribute
+    /// list and either an arrow, 'name (...) ->', or a ';' ending a self-closing element,
+    /// 'name (...);'.
+    ///
+    /// Used in text mode to tell the contents of an element apart from the start of
+    /// the next element, since text is otherwise free-form. Contents end at the end of
+    /// the line, so the lookahead never goes past a newline either. Text checks for an
+    /// element after every space, so the attribute lists on a line are only scanned the
+    /// first time, by scan_attr_lists(), and looked up after that.
+    pub fn at_element_start(&mut self) -> bool {
+        let mut chars = self.remaining();
+
+        match chars.next() {
+            Some(c) if c.is_alphabetic() => (),
+            _ => return false
+        }
+
+        // Element name
+        while let Some(c) = chars.clone().next() {
+            if !is_ident_char(c, chars.clone().nth(1)) {
+                break;
+            }
+            chars.next();
+        }
+
+        skip_inline_whitespace(&mut chars);
+        if chars.next() != Some('(') {
             return false;
         }
 
-        if ch.is_alphanumeric() {
-            return true;
+        let start = self.input.len() - chars.as_str().len();
+        let (line_start, ref closes) = self.attr_lists;
+        if start < line_start || start >= line_start + closes.len() {
+            self.attr_lists = (start, self.scan_attr_lists(start));
+        }
+
+        let (line_start, ref closes) = self.attr_lists;
+        closes.get(start - line_start).cloned().unwrap_or(false)
+    }
+
+    /// Works out, for every byte offset from the given one to the end of its line, if an
+    /// attribute list starting there is closed by a ')' followed by '->' or ';'. Parens
+    /// inside string literals are skipped. The line is scanned backwards, keeping track
+    /// of the answer both outside and inside of each kind of string, so that every char
+    /// is only looked at once.
+    fn scan_attr_lists(&self, start: usize) -> Vec<bool> {
+        let rest = &self.input[start..];
+        let line = &rest[..rest.find('\n').unwrap_or(rest.len())];
+        let mut closes = vec![false; line.len()];
+
+        // Whether the list is closed from the next char on, and from the char after it,
+        // when outside of a string, inside a '"' string and inside a '\'' string.
+        let mut next = [false; 3];
+        let mut after_next = [false; 3];
+        // Whether the chars after the current one, ignoring spaces, are '->' or ';'.
+        let mut ends_element = false;
+        let mut next_char = None;
+
+        for (i, c) in line.char_indices().rev() {
+            let in_string = |quote: char, state: usize| match c {
+                '\\' => after_next[state],
+                c if c == quote => next[0],
+                _ => next[state]
+            };
+
+            let outside = match c {
+                ')' => ends_element,
+                '"' => next[1],
+                '\'' => next[2],
+                _ => next[0]
+            };
+
+            let curr = [outside, in_string('"', 1), in_string('\'', 2)];
+            closes[i] = outside;
+            after_next = next;
+            next = curr;
+
+            if !c.is_whitespace() {
+                ends_element = c == ';' || (c == '-' && next_char == Some('>'));
+            }
+            next_char = Some(c);
+        }
+
+        closes
+    }
+
+    /// Lex the contents of an element in text mode. Sets curr_tok to one of:
+    ///
+    /// - A Text token with the raw source text, up to the next interpolation, include,
+    ///   end of line, unescaped '}' or the start of another element. Whitespace and
+    ///   punctuation are kept exactly as written, apart from trailing whitespace at the
+    ///   end of the contents.
+    /// - A Percent token, if a variable interpolation such as '%myVar' or an expression
+    ///   interpolation such as '%{ x * 2 }' starts here.
+    /// - An Ampersand token, if an include such as '&header' starts here.
+    /// - An Eof token once the contents have ended. The char that ended the contents
+    ///   is not consumed, so normal lexing can carry on from there.
+    ///
+    /// A '\\' can be used to escape '%', '&', '{', '}' and '\\' in text.
+    pub fn lex_text(&mut self) -> &mut Lexer {
+        self.mark();
+        let mut text = String::new();
+        let mut at_end = self.at_element_start();
+        // Byte offset after the last non-whitespace char, so the span of the text
+        // doesn't include any trailing whitespace that is trimmed below.
+        let mut text_end = self.pos;
+
+        while !at_end {
+            let ch = match self.curr_char {
+                Some(c) => c,
+                None => break
+            };
+
+            match ch {
+                '\n' | '}' => {
+                    at_end = true;
+                    break;
+                },
+                '%' if self.at_value_start() => break,
+                '&' if self.at_include_start() => break,
+                '\\' if matches!(self.peek(0), Some('%') | Some('&') | Some('{') | Some('}') | Some('\\')) => {
+                    self.get_char();
+                    text.push(self.curr_char.unwrap_or(EOF));
+                    self.get_char();
+                    text_end = self.pos;
+                    continue;
+                },
+                c if c.is_whitespace() => {
+                    text.push(c);
+                    self.get_char();
+
+                    if self.at_element_start() {
+                        at_end = true;
+                        break;
+                    }
+
+                    continue;
+                },
+                _ => ()
+            }
+
+            text.push(ch);
+            self.get_char();
+            text_end = self.pos;
+        }
+
+        if self.curr_char.is_none() {
+            at_end = true;
+        }
+
+        let mut span = self.span();
+        if at_end {
+            let trimmed_len = text.trim_end().len();
+            text.truncate(trimmed_len);
+            span.end = text_end;
         }
 
-        self.is_valid_char_in_ident(ch)
+        if !text.is_empty() {
+            self.curr_tok = Some(Token::new_from_value(TokenType::Text, &text, span));
+        } else if at_end {
+            self.curr_tok = Some(Token::new(TokenType::Eof, self.span()));
+        } else {
+            // We stopped at the start of an interpolation or include.
+            let tok_type = match self.curr_char {
+                Some('%') => TokenType::Percent,
+                _ => TokenType::Ampersand
+            };
+            self.curr_tok = self.get_token(tok_type);
+        }
+
+        self
+    }
+
+    /// Lex the body of a raw block, such as 'raw { <svg>...</svg> }', once the 'raw' keyword
+    /// has been lexed. The body is taken verbatim, so it can hold any html. By default the
+    /// braces inside the body must be balanced. If the body contains unbalanced braces, a
+    /// custom delimiter can be used by putting one or more '#' before the opening brace:
+    /// 'raw #{ ... }#' ends at the first '}' followed by the same number of '#'.
+    ///
+    /// A line break directly after the opening brace, and the indentation of the closing
+    /// brace, are not part of the body. Sets the current token to a RawText token, or an
+    /// Error token if the block is never closed.
+    pub fn lex_raw_block(&mut self) -> &mut Lexer {
+        while let Some(c) = self.curr_char {
+            if c == '\n' || !c.is_whitespace() {
+                break;
+            }
+
+            self.get_char();
+        }
+
+        self.mark();
+
+        let mut hashes = 0;
+        while self.curr_char == Some('#') {
+            hashes += 1;
+            self.get_char();
+        }
+
+        if self.curr_char != Some('{') {
+            self.curr_tok = Some(Token::new_from_value(TokenType::Error,
+                                                       "Expected '{' to start a raw block",
+                                                       self.span()));
+            return self;
+        }
+
+        // Consume the '{', and the line break following it.
+        self.get_char();
+        if self.curr_char == Some('\r') && self.peek(0) == Some('\n') {
+            self.get_char();
+        }
+        if self.curr_char == Some('\n') {
+            self.get_char();
+        }
+
+        let body_start = self.pos;
+        let mut depth = 0;
+
+        loop {
+            let ch = match self.curr_char {
+                Some(c) => c,
+                None => {
+                    self.curr_tok = Some(Token::new_from_value(TokenType::Error,
+                                                               "Unterminated raw block",
+                                                               self.span()));
+                    return self;
+                }
+            };
+
+            if ch == '{' && hashes == 0 {
+                depth += 1;
+            } else if ch == '}' && hashes == 0 && depth > 0 {
+                depth -= 1;
+            } else if ch == '}' && self.remaining().skip(1).take_while(|&c| c == '#').count() >= hashes {
+                break;
+            }
+
+            self.get_char();
+        }
+
+        let body = self.input[body_start..self.pos].to_owned();
+
+        // Consume the closing '}' and its '#'s.
+        for _ in 0..hashes + 1 {
+            self.get_char();
+        }
+
+        // Drop the indentation of the closing brace, if it is on its own line.
+        let body = match body.rfind('\n') {
+            Some(i) if body[i + 1..].trim().is_empty() => body[..i + 1].to_owned(),
+            _ => body
+        };
+
+        self.curr_tok = Some(Token::new_from_value(TokenType::RawText, &body, self.span()));
+
+        self
+    }
+
+    /// Checks if the '%' at the current char starts a value in text, such as '%myVar',
+    /// '%{ x * 2 }', or either of those marked as raw html with a '!', eg. '%!myVar'.
+    fn at_value_start(&self) -> bool {
+        let starts_value = |c: Option<char>| c.is_some_and(|c| c.is_alphabetic() || c == '{');
+
+        match self.peek(0) {
+            Some('!') => starts_value(self.peek(1)),
+            next => starts_value(next)
+        }
+    }
+
+    /// Checks if the '[' at the current char starts a list index, ie. it is followed
+    /// by one or more digits and a ']'.
+    fn at_index(&self) -> bool {
+        let mut chars = self.remaining();
+        chars.next();
+
+        let mut has_digits = false;
+        for c in chars {
+            match c {
+                ']' => return has_digits,
+                c if c.is_ascii_digit() => has_digits = true,
+                _ => return false
+            }
+        }
+
+        false
+    }
+
+    /// Checks if the '&' at the current char starts an include. A name followed by
+    /// a ';' is an html entity (eg. '&amp;'), and is left alone as text.
+    fn at_include_start(&self) -> bool {
+        let mut chars = self.remaining();
+        chars.next();
+
+        match chars.next() {
+            Some(c) if c.is_alphabetic() => (),
+            _ => return false
+        }
+
+        for c in chars {
+            if c == ';' {
+                return false;
+            }
+
+            if !c.is_alphanumeric() {
+                break;
+            }
+        }
+
+        true
+    }
+}
+
+/// Checks if a char can be part of an identifier. Letters, digits, '_' and '-'
+/// are all allowed, unless the '-' is the start of an arrow. A '.' is allowed
+/// between two names, as in 'loop.index', but not at the end of one.
+fn is_ident_char(ch: char, next: Option<char>) -> bool {
+    if ch == '-' {
+        return next != Some('>');
+    }
+
+    if ch == '.' {
+        return next.is_some_and(|c| c.is_alphabetic());
+    }
+
+    ch.is_alphanumeric() || ch == '_'
+}
+
+/// Advance an iterator past any spaces and tabs, but not past newlines.
+fn skip_inline_whitespace(chars: &mut Chars) {
+    while let Some(c) = chars.clone().next() {
+        if c == '\n' || !c.is_whitespace() {
+            break;
+        }
+        chars.next();
     }
 }
 
@@ -374,18 +1014,97 @@ mod tests {
         left_brace_lex.lex();
 
         let curr_tok = left_brace_lex.curr_tok.unwrap();
-        let expected = Token::new_from_value(TokenType::LeftBrace, &"{", 1, 1);
+        let expected = Token::new_from_value(TokenType::LeftBrace, "{", Span::new(0, 1, 1, 1));
+
+        assert_eq!(curr_tok, expected);
+    }
+
+    #[test]
+    fn test_lex_semicolon() {
+        let mut semi_lex = Lexer::new("br ();".to_string());
+        semi_lex.lex();
+        semi_lex.lex();
+        semi_lex.lex();
+        semi_lex.lex();
+
+        let curr_tok = semi_lex.curr_tok.unwrap();
+        let expected = Token::new_from_value(TokenType::Semicolon, ";", Span::new(5, 6, 1, 6));
 
         assert_eq!(curr_tok, expected);
     }
 
+    #[test]
+    fn test_lex_interpolated_string() {
+        let mut string_lex = Lexer::new("\"a %{ x } b\"".to_string());
+        string_lex.lex();
+
+        let curr_tok = string_lex.curr_tok.clone().unwrap();
+        assert_eq!(curr_tok, Token::new_from_value(TokenType::StringPart, "a ", Span::new(0, 4, 1, 1)));
+
+        string_lex.lex();
+        assert_eq!(string_lex.curr_tok.clone().unwrap().tok_type, TokenType::LeftBrace);
+        string_lex.lex();
+        assert_eq!(string_lex.curr_tok.clone().unwrap().val, "x".to_owned());
+        string_lex.lex();
+        assert_eq!(string_lex.curr_tok.clone().unwrap().tok_type, TokenType::RightBrace);
+
+        string_lex.lex_string_rest();
+        let curr_tok = string_lex.curr_tok.clone().unwrap();
+        assert_eq!(curr_tok, Token::new_from_value(TokenType::StringLiteral, " b", Span::new(9, 12, 1, 10)));
+    }
+
+    #[test]
+    fn test_lex_raw_block() {
+        let mut raw_lex = Lexer::new("raw {\n<svg><g>{}</g></svg>\n  }\np".to_string());
+        raw_lex.lex();
+        raw_lex.lex_raw_block();
+
+        let curr_tok = raw_lex.curr_tok.clone().unwrap();
+        assert_eq!(curr_tok.tok_type, TokenType::RawText);
+        assert_eq!(curr_tok.val, "<svg><g>{}</g></svg>\n".to_owned());
+
+        raw_lex.lex();
+        assert_eq!(raw_lex.curr_tok.clone().unwrap().val, "p".to_owned());
+    }
+
+    #[test]
+    fn test_lex_raw_block_with_delimiter() {
+        let mut raw_lex = Lexer::new("raw ##{ if (a) { }#}##".to_string());
+        raw_lex.lex();
+        raw_lex.lex_raw_block();
+
+        let curr_tok = raw_lex.curr_tok.clone().unwrap();
+        assert_eq!(curr_tok, Token::new_from_value(TokenType::RawText, " if (a) { }#", Span::new(4, 22, 1, 5)));
+    }
+
+    #[test]
+    fn test_lex_raw_block_unterminated() {
+        let mut raw_lex = Lexer::new("raw { <b>{</b> }".to_string());
+        raw_lex.lex();
+        raw_lex.lex_raw_block();
+
+        let curr_tok = raw_lex.curr_tok.clone().unwrap();
+        assert_eq!(curr_tok.tok_type, TokenType::Error);
+        assert_eq!(curr_tok.val, "Unterminated raw block".to_owned());
+    }
+
+    #[test]
+    fn test_lex_text_stops_at_self_closing_element() {
+        let mut text_lex = Lexer::new("text  img (src: x);".to_string());
+        text_lex.lex_text();
+
+        let curr_tok = text_lex.curr_tok.clone().unwrap();
+        assert_eq!(curr_tok.val, "text".to_owned());
+        assert!(text_lex.at_element_start());
+    }
+
     #[test]
     fn test_lex_arrow() {
         let mut arrow_lex = Lexer::new("->".to_string());
         arrow_lex.lex();
 
         let curr_tok = arrow_lex.curr_tok.unwrap();
-        let expected = Token::new_from_value(TokenType::Arrow, &"->", 1, 1);
+        let expected = Token::new_from_value(TokenType::Arrow, "->", Span::new(0, 2, 1, 1));
 
         assert_eq!(curr_tok, expected);
     }
@@ -396,7 +1115,7 @@ mod tests {
         minus_lex.lex();
 
         let curr_tok_minus = minus_lex.curr_tok.unwrap();
-        let expected_minus = Token::new_from_value(TokenType::Minus, &"-", 1, 1);
+        let expected_minus = Token::new_from_value(TokenType::Minus, "-", Span::new(0, 1, 1, 1));
 
         assert_eq!(curr_tok_minus, expected_minus);
     }
@@ -407,7 +1126,7 @@ mod tests {
         ident_lex.lex();
 
         let curr_tok = ident_lex.curr_tok.unwrap();
-        let expected = Token::new_from_value(TokenType::Ident, &"testIdentifier", 1, 1);
+        let expected = Token::new_from_value(TokenType::Ident, "testIdentifier", Span::new(0, 14, 1, 1));
 
         assert_eq!(curr_tok, expected);
     }
@@ -418,7 +1137,7 @@ mod tests {
         ident_lex.lex();
 
         let curr_tok = ident_lex.curr_tok.unwrap();
-        let mut expected = Token::new_from_value(TokenType::Ident, &"int", 1, 1);
+        let mut expected = Token::new_from_value(TokenType::Ident, "int", Span::new(0, 3, 1, 1));
         expected.set_reserved(true);
 
         assert_eq!(curr_tok, expected);
@@ -431,9 +1150,362 @@ mod tests {
         num_lex.lex();
 
         let curr_tok = num_lex.curr_tok.unwrap();
-        let expected = Token::new_from_value(TokenType::Number, &"8080", 1, 1);
+        let expected = Token::new_from_value(TokenType::Number, "8080", Span::new(0, 4, 1, 1));
+
+        assert_eq!(curr_tok, expected);
+    }
+
+    #[test]
+    fn test_lex_float() {
+        let mut num_lex = Lexer::new("1.25 2.".to_string());
+        num_lex.lex();
+
+        let curr_tok = num_lex.curr_tok.clone().unwrap();
+        let expected = Token::new_from_value(TokenType::Number, "1.25", Span::new(0, 4, 1, 1));
+        assert_eq!(curr_tok, expected);
+
+        // A '.' without digits after it isn't part of the number.
+        num_lex.lex();
+        assert_eq!(num_lex.curr_tok.unwrap().val, "2".to_owned());
+    }
+
+    #[test]
+    fn test_lex_string_literal() {
+        let mut str_lex = Lexer::new("\"styled-div\"".to_string());
+        str_lex.lex();
+
+        let curr_tok = str_lex.curr_tok.unwrap();
+        let expected = Token::new_from_value(TokenType::StringLiteral, "styled-div", Span::new(0, 12, 1, 1));
+
+        assert_eq!(curr_tok, expected);
+    }
+
+    #[test]
+    fn test_lex_string_literal_single_quotes() {
+        let mut str_lex = Lexer::new("'say \"hi\"'".to_string());
+        str_lex.lex();
+
+        let curr_tok = str_lex.curr_tok.unwrap();
+        let expected = Token::new_from_value(TokenType::StringLiteral, "say \"hi\"", Span::new(0, 10, 1, 1));
+
+        assert_eq!(curr_tok, expected);
+    }
+
+    #[test]
+    fn test_lex_string_literal_escapes() {
+        let mut str_lex = Lexer::new(r#""a\nb \"c\" \\ \u{e9}\u{1F600}""#.to_string());
+        str_lex.lex();
+
+        let curr_tok = str_lex.curr_tok.unwrap();
+        let expected = Token::new_from_value(TokenType::StringLiteral,
+                                             "a\nb \"c\" \\ \u{e9}\u{1F600}",
+                                             Span::new(0, 31, 1, 1));
+
+        assert_eq!(curr_tok, expected);
+    }
+
+    #[test]
+    fn test_lex_string_literal_tracks_lines() {
+        let mut str_lex = Lexer::new("\"first\nsecond\" next".to_string());
+        str_lex.lex();
+        str_lex.lex();
+
+        let curr_tok = str_lex.curr_tok.unwrap();
+        let expected = Token::new_from_value(TokenType::Ident, "next", Span::new(15, 19, 2, 9));
+
+        assert_eq!(curr_tok, expected);
+    }
+
+    #[test]
+    fn test_lex_string_literal_unterminated() {
+        let mut str_lex = Lexer::new("\"no end".to_string());
+        str_lex.lex();
+
+        let curr_tok = str_lex.curr_tok.unwrap();
+
+        assert_eq!(curr_tok.tok_type, TokenType::Error);
+    }
+
+    #[test]
+    fn test_lex_string_literal_invalid_escape() {
+        let mut str_lex = Lexer::new(r#""bad \q" next"#.to_string());
+        str_lex.lex();
+
+        let curr_tok = str_lex.curr_tok.clone().unwrap();
+        assert_eq!(curr_tok.tok_type, TokenType::Error);
 
+        // The rest of the literal is consumed, so lexing resumes after it.
+        str_lex.lex();
+        assert_eq!(str_lex.curr_tok.unwrap().val, "next".to_owned());
+    }
+
+    #[test]
+    fn test_lex_skips_line_comment() {
+        let mut comment_lex = Lexer::new("// a note (with parens)\n  div".to_string());
+        comment_lex.lex();
+
+        let curr_tok = comment_lex.curr_tok.unwrap();
+        let expected = Token::new_from_value(TokenType::Ident, "div", Span::new(26, 29, 2, 3));
+
+        assert_eq!(curr_tok, expected);
+    }
+
+    #[test]
+    fn test_lex_skips_nested_block_comment() {
+        let mut comment_lex = Lexer::new("/* outer\n /* inner */\n still outer */ div".to_string());
+        comment_lex.lex();
+
+        let curr_tok = comment_lex.curr_tok.unwrap();
+        let expected = Token::new_from_value(TokenType::Ident, "div", Span::new(38, 41, 3, 17));
+
+        assert_eq!(curr_tok, expected);
+    }
+
+    #[test]
+    fn test_lex_unterminated_block_comment() {
+        let mut comment_lex = Lexer::new("/* outer /* inner */ div".to_string());
+        comment_lex.lex();
+
+        let curr_tok = comment_lex.curr_tok.unwrap();
+
+        assert_eq!(curr_tok.tok_type, TokenType::Error);
+        assert_eq!(curr_tok.span, Span::new(0, 24, 1, 1));
+    }
+
+    #[test]
+    fn test_lex_html_comment() {
+        let mut comment_lex = Lexer::new("#! Shown in the output \ndiv".to_string());
+        comment_lex.lex();
+
+        let curr_tok = comment_lex.curr_tok.clone().unwrap();
+        let expected = Token::new_from_value(TokenType::HtmlComment, "Shown in the output", Span::new(0, 23, 1, 1));
+        assert_eq!(curr_tok, expected);
+
+        comment_lex.lex();
+        let next_tok = comment_lex.curr_tok.unwrap();
+        let expected_next = Token::new_from_value(TokenType::Ident, "div", Span::new(24, 27, 2, 1));
+        assert_eq!(next_tok, expected_next);
+    }
+
+    #[test]
+    fn test_lex_operator_span() {
+        let mut op_lex = Lexer::new("if x\n  >= 10".to_string());
+        op_lex.lex();
+        op_lex.lex();
+        op_lex.lex();
+
+        let curr_tok = op_lex.curr_tok.unwrap();
+        let expected = Token::new_from_value(TokenType::GtEquals, ">=", Span::new(7, 9, 2, 3));
+
+        assert_eq!(curr_tok, expected);
+    }
+
+    #[test]
+    fn test_lex_arithmetic_operators() {
+        let mut op_lex = Lexer::new("1 + 2 * 3 / 4 % 5 - 6".to_string());
+        let expected = [TokenType::Number, TokenType::Plus, TokenType::Number,
+                        TokenType::Star, TokenType::Number, TokenType::Slash,
+                        TokenType::Number, TokenType::Percent, TokenType::Number,
+                        TokenType::Minus, TokenType::Number];
+
+        for tok_type in expected.iter() {
+            op_lex.lex();
+            assert_eq!(&op_lex.curr_tok.clone().unwrap().tok_type, tok_type);
+        }
+    }
+
+    #[test]
+    fn test_lex_logical_operators() {
+        let mut op_lex = Lexer::new("!a && b || c".to_string());
+        let expected = [(TokenType::Not, "!"),
+                        (TokenType::Ident, "a"),
+                        (TokenType::And, "&&"),
+                        (TokenType::Ident, "b"),
+                        (TokenType::Or, "||"),
+                        (TokenType::Ident, "c")];
+
+        for (tok_type, val) in expected.iter() {
+            op_lex.lex();
+            let curr_tok = op_lex.curr_tok.clone().unwrap();
+
+            assert_eq!(&curr_tok.tok_type, tok_type);
+            assert_eq!(curr_tok.val, val.to_owned());
+        }
+    }
+
+    #[test]
+    fn test_lex_single_pipe() {
+        let mut op_lex = Lexer::new("a | b".to_string());
+        op_lex.lex();
+        op_lex.lex();
+
+        let curr_tok = op_lex.curr_tok.unwrap();
+
+        assert_eq!(curr_tok.tok_type, TokenType::Error);
+        assert_eq!(curr_tok.span, Span::new(2, 3, 1, 3));
+    }
+
+    #[test]
+    fn test_lex_ident_with_punctuation() {
+        let mut ident_lex = Lexer::new("data-id_2: x".to_string());
+        ident_lex.lex();
+
+        let curr_tok = ident_lex.curr_tok.unwrap();
+        let expected = Token::new_from_value(TokenType::Ident, "data-id_2", Span::new(0, 9, 1, 1));
+
+        assert_eq!(curr_tok, expected);
+    }
+
+    #[test]
+    fn test_lex_dotted_ident() {
+        let mut ident_lex = Lexer::new("loop.index.".to_string());
+        ident_lex.lex();
+
+        let curr_tok = ident_lex.curr_tok.unwrap();
+        let expected = Token::new_from_value(TokenType::Ident, "loop.index", Span::new(0, 10, 1, 1));
+
+        assert_eq!(curr_tok, expected);
+    }
+
+    #[test]
+    fn test_lex_indexed_ident() {
+        let mut ident_lex = Lexer::new("posts[0].author[x]".to_string());
+        ident_lex.lex();
+
+        let curr_tok = ident_lex.curr_tok.unwrap();
+        let expected = Token::new_from_value(TokenType::Ident, "posts[0].author", Span::new(0, 15, 1, 1));
+
+        assert_eq!(curr_tok, expected);
+    }
+
+    #[test]
+    fn test_lex_ident_ends_after_index() {
+        for source in &["a[0]x", "a[0]\u{e9}"] {
+            let mut ident_lex = Lexer::new(source.to_string());
+            ident_lex.lex();
+
+            let curr_tok = ident_lex.curr_tok.unwrap();
+            let expected = Token::new_from_value(TokenType::Ident, "a[0]", Span::new(0, 4, 1, 1));
+
+            assert_eq!(curr_tok, expected);
+        }
+    }
+
+    #[test]
+    fn test_lex_unexpected_char() {
+        let mut char_lex = Lexer::new("$ div".to_string());
+        char_lex.lex();
+
+        let curr_tok = char_lex.curr_tok.clone().unwrap();
+        assert_eq!(curr_tok.tok_type, TokenType::Error);
+
+        char_lex.lex();
+        assert_eq!(char_lex.curr_tok.unwrap().val, "div".to_owned());
+    }
+
+    #[test]
+    fn test_lex_text_keeps_source() {
+        let mut text_lex = Lexer::new("Hello,   world! Don't — café  \nnext".to_string());
+        text_lex.lex_text();
+
+        let curr_tok = text_lex.curr_tok.clone().unwrap();
+        let expected = Token::new_from_value(TokenType::Text, "Hello,   world! Don't — café", Span::new(0, 31, 1, 1));
+        assert_eq!(curr_tok, expected);
+
+        // The newline ends the text, and is left for normal lexing.
+        text_lex.lex_text();
+        assert_eq!(text_lex.curr_tok.clone().unwrap().tok_type, TokenType::Eof);
+        assert_eq!(text_lex.curr_char, Some('\n'));
+    }
+
+    #[test]
+    fn test_lex_text_interpolation() {
+        let mut text_lex = Lexer::new("50% off for %name".to_string());
+        text_lex.lex_text();
+
+        let curr_tok = text_lex.curr_tok.clone().unwrap();
+        let expected = Token::new_from_value(TokenType::Text, "50% off for ", Span::new(0, 12, 1, 1));
         assert_eq!(curr_tok, expected);
+
+        text_lex.lex_text();
+        let percent_tok = text_lex.curr_tok.clone().unwrap();
+        let expected_percent = Token::new_from_value(TokenType::Percent, "%", Span::new(12, 13, 1, 13));
+        assert_eq!(percent_tok, expected_percent);
+    }
+
+    #[test]
+    fn test_lex_text_raw_interpolation() {
+        let mut text_lex = Lexer::new("Wow%! %!html".to_string());
+        text_lex.lex_text();
+
+        let curr_tok = text_lex.curr_tok.clone().unwrap();
+        let expected = Token::new_from_value(TokenType::Text, "Wow%! ", Span::new(0, 6, 1, 1));
+        assert_eq!(curr_tok, expected);
+
+        text_lex.lex_text();
+        assert_eq!(text_lex.curr_tok.clone().unwrap().tok_type, TokenType::Percent);
+
+        text_lex.lex();
+        assert_eq!(text_lex.curr_tok.clone().unwrap().tok_type, TokenType::Not);
+    }
+
+    #[test]
+    fn test_lex_text_expression_interpolation() {
+        let mut text_lex = Lexer::new("Total: %{ 2 * 3 }".to_string());
+        text_lex.lex_text();
+        assert_eq!(text_lex.curr_tok.clone().unwrap().val, "Total: ".to_owned());
+
+        text_lex.lex_text();
+        assert_eq!(text_lex.curr_tok.clone().unwrap().tok_type, TokenType::Percent);
+
+        // The expression itself is lexed normally.
+        text_lex.lex();
+        assert_eq!(text_lex.curr_tok.clone().unwrap().tok_type, TokenType::LeftBrace);
+    }
+
+    #[test]
+    fn test_lex_text_include_and_entities() {
+        let mut text_lex = Lexer::new("Tom & Jerry &amp; \\&co &header".to_string());
+        text_lex.lex_text();
+
+        let curr_tok = text_lex.curr_tok.clone().unwrap();
+        assert_eq!(curr_tok.tok_type, TokenType::Text);
+        assert_eq!(curr_tok.val, "Tom & Jerry &amp; &co ".to_owned());
+
+        text_lex.lex_text();
+        assert_eq!(text_lex.curr_tok.clone().unwrap().tok_type, TokenType::Ampersand);
+    }
+
+    #[test]
+    fn test_lex_text_stops_at_element_start() {
+        let mut text_lex = Lexer::new("a (b) c  p (class: \")\") -> d".to_string());
+        text_lex.lex_text();
+
+        let curr_tok = text_lex.curr_tok.clone().unwrap();
+        assert_eq!(curr_tok.val, "a (b) c".to_owned());
+        assert!(text_lex.at_element_start());
+    }
+
+    #[test]
+    fn test_lex_text_unclosed_paren() {
+        let mut text_lex = Lexer::new("call me (maybe\nh1 () -> x".to_string());
+        text_lex.lex_text();
+
+        // The '(' is never closed on this line, so 'me (' doesn't start an element.
+        let curr_tok = text_lex.curr_tok.clone().unwrap();
+        assert_eq!(curr_tok.val, "call me (maybe".to_owned());
+    }
+
+    #[test]
+    fn test_lex_text_element_start_after_string() {
+        // The '"' opens a string that is never closed, so 'a (' doesn't start an
+        // element, but the later 'b (' does.
+        let mut text_lex = Lexer::new("say a (\"x) ->  b (y: \")\") -> z".to_string());
+        text_lex.lex_text();
+
+        let curr_tok = text_lex.curr_tok.clone().unwrap();
+        assert_eq!(curr_tok.val, "say a (\"x) ->".to_owned());
+        assert!(text_lex.at_element_start());
     }
 
     #[test]
@@ -442,7 +1514,7 @@ mod tests {
         empty_lex.lex();
 
         let curr_tok = empty_lex.curr_tok.unwrap();
-        let expected = Token::new_from_empty();
+        let expected = Token::new(TokenType::Eof, Span::new(0, 0, 1, 1));
 
         assert_eq!(curr_tok, expected);
     }
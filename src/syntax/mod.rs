@@ -1,6 +1,10 @@
 pub mod ast;
 pub mod token;
+pub mod html;
 pub mod lexer;
 pub mod parser;
 pub mod reserved;
+pub mod span;
 pub mod symbol_table;
+pub mod type_check;
+pub mod value;
@@ -1,9 +1,13 @@
 use syntax::lexer::Lexer;
 use syntax::token::{Token, TokenType};
-use syntax::ast::{Ast, AstType};
+use syntax::ast::{Attr, BinaryOp, Block, Else, Expr, Ident, Node, Number, Template, TextPart, UnaryOp,
+                  VarType};
+use syntax::html::is_void_element;
+use syntax::span::Span;
 use syntax::symbol_table::SymbolTable;
 use error::error_traits::Diagnostic;
 use error::parse_err::ParseDiagnostic;
+use error::report::Report;
 
 pub struct Parser {
     /// Lexer struct called repeatedly to get tokens
@@ -11,10 +15,17 @@ pub struct Parser {
     /// Current token, held here when a symbol is lexed.
     /// The value and the type of the current symbol can be accessed here
     curr_tok: Token,
+    /// Span of the last token consumed, used to find where a multi-token node ends
+    prev_span: Span,
+    /// Number of braced blocks the current token is nested in
+    depth: usize,
+    /// Set after a syntax error until the parser has skipped to a point where it can
+    /// carry on, so that one mistake doesn't cause a cascade of errors
+    panicking: bool,
     /// Our current symbol table containing variable declarations
     pub symbol_table: SymbolTable,
     /// Current ast (initially empty)
-    pub root: Ast,
+    pub root: Template,
     /// Error and warning message information
     pub diagnostic: ParseDiagnostic
 }
@@ -33,329 +44,1011 @@ impl Parser {
 
         Parser {
             lexer: m_lexer,
-            symbol_table: symbol_table,
+            symbol_table,
             curr_tok: tok.unwrap_or(Token::new_from_empty()),
-            root: Ast::new(AstType::Template),
+            prev_span: Span::default(),
+            depth: 0,
+            panicking: false,
+            root: Template::default(),
             diagnostic: ParseDiagnostic::new()
         }
     }
 
-    /// Initiate recursive parsing process. Ast will take the from of Template -> [Element]
-    /// here. Template is the top level ast, and should contain any elements that are not
-    /// nested in other elements. The parsing process will continually call the lex() method
-    /// from the struct's lexer object until EOF is reached.
+    /// Initiate the parsing process. The template holds any nodes that are not nested in
+    /// other elements, in the order they appear in the source. The nodes are parsed one
+    /// after another in a loop, which continually calls the lex() method from the struct's
+    /// lexer object until EOF is reached.
     pub fn parse(&mut self) -> &mut Parser {
         if self.curr_tok.tok_type == TokenType::Eof {
             self.diagnostic.new_err("End of input reached, nothing to parse!");
         }
 
-        let el = self.element();
-        self.root.children.insert(0, el);
+        self.root = Template {
+            nodes: self.elements()
+        };
 
         self
     }
 
-    /// Parse and add an Element ast type to the tree. This method is
-    /// recursive in all cases, and will call itself until no input remains.
-    /// An element ast in tank can contain an html element, a variable assignment,
-    /// an if statement or a for-in statement. In the case that we have no elements
-    /// left to parse, we will append an EOF to the ast indicating the end of input.
-    fn element(&mut self) -> Box<Ast> {
-        let mut el_ast = Ast::new(AstType::Element);
-        match self.curr_tok.tok_type {
-            TokenType::Ident => {
+    /// Parse a list of sibling nodes, until the end of the input or the '}' closing the
+    /// block they are in.
+    ///
+    /// After a syntax error, tokens are skipped up to the next point where an element
+    /// could start, and an Error node covering them is added in their place. This way
+    /// every independent error in the template is reported by a single parse.
+    fn elements(&mut self) -> Vec<Node> {
+        let mut nodes = Vec::new();
+
+        loop {
+            let node = self.element();
+            let skip_start = self.curr_tok.span;
+
+            match node {
+                Some(node) => nodes.push(node),
+                None if self.curr_tok.tok_type == TokenType::Eof
+                    || (self.curr_tok.tok_type == TokenType::RightBrace && self.depth > 0) => break,
+                None => {
+                    // Nothing can start an element here, such as an unmatched ')' or '}',
+                    // so skip the token to make sure the parser moves on. The lexer has
+                    // already described what is wrong with an Error token.
+                    let err = match self.curr_tok.tok_type {
+                        TokenType::Error => self.curr_tok.val.clone(),
+                        _ => format!("Unexpected token {:?} found", self.curr_tok.val)
+                    };
+                    self.syntax_err(&err);
+                    self.get_next_tok();
+                }
+            }
 
-                match self.curr_tok.val.as_ref() {
-                    "if" => {
-                        // Consume "if"
-                        self.get_next_tok();
-                        el_ast = Ast::new(AstType::IfExpr);
-                        el_ast.children.push(self.expr());
+            if self.panicking {
+                nodes.push(Node::Error {
+                    span: self.synchronize(skip_start)
+                });
+                self.panicking = false;
+            }
+        }
+
+        nodes
+    }
 
-                        // Consume "{"
-                        self.expect(TokenType::LeftBrace);
+    /// Parse a single node, along with everything nested inside it. A node in tank can be
+    /// an html element, a variable assignment, an if statement, a for-in statement, a
+    /// braced block, an include, an html comment, a doctype or a raw html block. In the
+    /// case that no node starts at the current token, None is returned and nothing is
+    /// consumed.
+    fn element(&mut self) -> Option<Node> {
+        let start = self.curr_tok.span;
 
-                        el_ast.children.push(self.element());
+        let node = match self.curr_tok.tok_type {
+            TokenType::Ident => {
 
-                        // Consume "}"
-                        self.expect(TokenType::RightBrace);
+                match self.curr_tok.val.as_ref() {
+                    "if" => {
+                        let (cond, then, else_) = self.if_branch();
 
-                        let next = self.element();
-                        self.root.children.insert(0, next);
+                        Node::If {
+                            cond,
+                            then,
+                            else_,
+                            span: self.span_from(start)
+                        }
+                    },
+                    "else" => {
+                        self.syntax_err("Found 'else' without a matching 'if'");
+                        return None;
                     },
                     "for" => {
                         // Consume "for"
                         self.get_next_tok();
-                        el_ast = Ast::new(AstType::ForExpr);
-                        let mut first_ident_ast = self.term();
+                        let var = self.ident();
 
                         self.expect(TokenType::Colon);
 
-                        first_ident_ast.var_type = Some(self.curr_tok.val.clone());
-                        // Add to symbol table
-                        // TODO: Will eventually be unnecessary I think
-                        self.symbol_table.insert_for_id(&first_ident_ast);
-
-                        el_ast.children.push(first_ident_ast);
-                        self.get_next_tok();
+                        // The loop variable is bound once per item by the generator,
+                        // so it isn't added to the symbol table here.
+                        let var_type = self.var_type(&var);
 
                         if self.curr_tok.val != "in" {
-                            self.diagnostic.new_err("Expected 'in' at for loop");
+                            if let Some(report) = self.syntax_err("Expected 'in' at for loop") {
+                                report.with_help("write loops as 'for item: type in items'");
+                            }
                         } else {
                             self.get_next_tok();
                         }
 
-                        el_ast.children.push(self.term());
-                        el_ast.children.push(self.element());
-                    },
-                    "let" => {
-                        // Consume "let"
-                        self.get_next_tok();
-                        let assign_el = self.expr();
+                        // The list is either a variable, such as '%posts', or a bare name.
+                        let iter = if self.curr_tok.tok_type == TokenType::Percent {
+                            let iter_start = self.curr_tok.span;
 
-                        // Add this variable to the symbol table, and panic
-                        // if we already tried to declare it before.
-                        self.symbol_table.insert(&assign_el);
+                            // Consume "%"
+                            self.get_next_tok();
+                            let ident = self.ident();
 
-                        el_ast.children.push(assign_el);
-                        let next = self.element();
-                        self.root.children.insert(0, next);
+                            Ident {
+                                name: ident.name,
+                                span: iter_start.to(&ident.span)
+                            }
+                        } else {
+                            self.ident()
+                        };
+
+                        let body = self.block();
+                        let span = self.span_from(start);
+
+                        // A loop without a valid type can't be checked or generated.
+                        match var_type {
+                            Some(var_type) => Node::For {
+                                var,
+                                var_type,
+                                iter,
+                                body,
+                                span
+                            },
+                            None => Node::Error {
+                                span
+                            }
+                        }
                     },
-                    _ => {
-                        el_ast.children.push(self.term());
+                    "doctype" if self.peek() != TokenType::LeftParen => {
+                        // Consume "doctype"
+                        self.get_next_tok();
 
-                        if self.curr_tok.tok_type == TokenType::LeftParen {
-                            el_ast.children.push(self.attr_list());
-                        }
+                        let doc_type = if self.curr_tok.tok_type == TokenType::Ident {
+                            let doc_type = self.curr_tok.val.clone();
 
-                        // Look ahead and see if we have another element
-                        if self.peek() == TokenType::LeftParen {
-                            el_ast.children.push(self.element());
+                            // Consume the document type
+                            self.get_next_tok();
+                            doc_type
                         } else {
-                            el_ast.children.push(self.contents());
+                            let err = format!("Expected a document type after 'doctype', found {:?}",
+                                              self.curr_tok.tok_type);
+                            self.syntax_err(&err);
+                            String::new()
+                        };
+
+                        Node::Doctype {
+                            doc_type,
+                            span: self.span_from(start)
                         }
+                    },
+                    "raw" if self.peek() != TokenType::LeftParen => {
+                        // The body of the block is lexed verbatim, straight after "raw".
+                        self.get_next_raw_tok();
 
-                        let next = self.element();
-                        self.root.children.insert(0, next);
-                    }
-                };
-            },
-            TokenType::LeftBrace => {
-                // Consume "{"
-                self.get_next_tok();
+                        let html = if self.curr_tok.tok_type == TokenType::RawText {
+                            self.curr_tok.val.clone()
+                        } else {
+                            let err = self.curr_tok.val.clone();
+                            self.syntax_err(&err);
+                            String::new()
+                        };
+
+                        // Consume the body
+                        self.get_next_tok();
 
-                el_ast.children.push(self.element());
+                        Node::Raw {
+                            html,
+                            span: self.span_from(start)
+                        }
+                    },
+                    "let" => self.declaration(start),
+                    _ => {
+                        let name = self.ident();
+                        let attrs = self.attr_list();
+                        let is_void = is_void_element(&name.name);
+                        let mut body = Vec::new();
+
+                        match self.curr_tok.tok_type {
+                            // A self-closing element, such as 'path (d: "...");'
+                            TokenType::Semicolon => {
+                                // Consume ";"
+                                self.get_next_tok();
+
+                                return Some(Node::VoidElement {
+                                    name,
+                                    attrs,
+                                    span: self.span_from(start)
+                                });
+                            },
+                            // Don't consume the arrow here, contents() needs the lexer
+                            // to still be positioned directly after it.
+                            TokenType::Arrow => {
+                                if is_void {
+                                    let err = format!("Void element '{}' can't have contents", name.name);
+                                    self.diagnostic.parse_err(&err, &self.curr_tok)
+                                        .with_help("void elements such as 'br' and 'img' can only have attributes");
+                                }
+
+                                self.contents(&mut body);
+                            },
+                            // Void elements, such as 'br ()', never have contents so
+                            // don't need to be closed.
+                            _ if is_void => {
+                                return Some(Node::VoidElement {
+                                    name,
+                                    attrs,
+                                    span: self.span_from(start)
+                                });
+                            },
+                            _ => self.expect(TokenType::Arrow)
+                        }
 
-                // Consume "}"
-                self.expect(TokenType::RightBrace);
+                        Node::Element {
+                            name,
+                            attrs,
+                            body,
+                            span: self.span_from(start)
+                        }
+                    }
+                }
             },
+            TokenType::LeftBrace => Node::Block(self.block()),
             TokenType::Ampersand => {
                 // Consume "&"
                 self.get_next_tok();
 
-                el_ast = Ast::new_from_value(AstType::Include, &self.curr_tok.val);
+                let path = self.curr_tok.val.clone();
 
                 // Consume filename
                 self.get_next_tok();
 
-                let next = self.element();
-                self.root.children.insert(0, next);
+                Node::Include {
+                    path,
+                    span: self.span_from(start)
+                }
             },
-            _ => {
-                el_ast = Ast::new(AstType::Eof);
+            TokenType::HtmlComment => {
+                let text = self.curr_tok.val.clone();
+
+                // Consume the comment
+                self.get_next_tok();
+
+                Node::Comment {
+                    text,
+                    span: start
+                }
+            },
+            _ => return None
+        };
+
+        Some(node)
+    }
+
+    /// Parse a variable declaration, such as 'let x: int = 10', starting at its "let"
+    /// keyword. The variable is added to the symbol table, and its value is computed
+    /// during generation.
+    fn declaration(&mut self, start: Span) -> Node {
+        // Consume "let"
+        self.get_next_tok();
+
+        if self.curr_tok.tok_type != TokenType::Ident {
+            self.syntax_err("Expected a variable declaration, such as 'let x: int = 10'");
+        }
+
+        let var = self.ident();
+
+        let var_type = if self.curr_tok.tok_type == TokenType::Colon {
+            // Consume ":"
+            self.get_next_tok();
+            self.var_type(&var)
+        } else {
+            let err = format!("Variable '{}' declared without a type", var.name);
+            self.diagnostic.report(Report::error(&err).at(var.span));
+            None
+        };
+
+        self.enter_expr();
+        self.expect(TokenType::Equals);
+        let value = self.logic_or();
+        self.exit_expr();
+
+        let span = self.span_from(start);
+
+        // A declaration without a valid type can't be checked or generated.
+        let var_type = match var_type {
+            Some(var_type) => var_type,
+            None => return Node::Error {
+                span
+            }
+        };
+
+        // Add this variable to the symbol table, which fails if we already tried to
+        // declare it before.
+        if let Err(err) = self.symbol_table.insert(&var.name, var_type.name(), span) {
+            self.diagnostic.report(Report::error(&err).at(var.span));
+        }
+
+        Node::Let {
+            var,
+            var_type,
+            value,
+            span
+        }
+    }
+
+    /// Parse the type declared for a variable, following its ':'. Returns None if the
+    /// type is missing or isn't one of the types that can be declared, which is reported.
+    fn var_type(&mut self, var: &Ident) -> Option<VarType> {
+        if self.curr_tok.tok_type != TokenType::Ident {
+            let err = format!("Expected the type of '{}', such as 'int'", var.name);
+            self.syntax_err(&err);
+            return None;
+        }
+
+        let var_type = VarType::from_name(&self.curr_tok.val);
+
+        if var_type.is_none() {
+            let err = format!("Unknown type '{}' declared for '{}'", self.curr_tok.val, var.name);
+            let types: Vec<&str> = VarType::ALL.iter().map(|var_type| var_type.name()).collect();
+            self.diagnostic.parse_err(&err, &self.curr_tok)
+                .with_help(&format!("the types are {}", types.join(", ")));
+        }
+
+        // Consume the type
+        self.get_next_tok();
+        var_type
+    }
+
+    /// Parse an if statement, starting at its "if" keyword, and return its condition,
+    /// block and else branch. If the block is followed by "else", the else branch is
+    /// either another if statement, which can be chained in the same way, or the final
+    /// block.
+    fn if_branch(&mut self) -> (Expr, Block, Option<Box<Else>>) {
+        // Consume "if"
+        self.enter_expr();
+        self.get_next_tok();
+        let cond = self.logic_or();
+        self.exit_expr();
+        let then = self.block();
+
+        if self.curr_tok.tok_type != TokenType::Ident || self.curr_tok.val != "else" {
+            return (cond, then, None);
+        }
+
+        let else_start = self.curr_tok.span;
+
+        // Consume "else"
+        self.get_next_tok();
+
+        let else_branch = if self.curr_tok.tok_type == TokenType::Ident && self.curr_tok.val == "if" {
+            let (cond, then, else_) = self.if_branch();
+
+            Else::If {
+                cond,
+                then,
+                else_,
+                span: self.span_from(else_start)
+            }
+        } else {
+            Else::Block {
+                block: self.block(),
+                span: self.span_from(else_start)
             }
+        };
+
+        (cond, then, Some(Box::new(else_branch)))
+    }
+
+    /// Parse a braced block, as used by if, else and for statements. The nodes inside it
+    /// are kept in the order they were written.
+    fn block(&mut self) -> Block {
+        let start = self.curr_tok.span;
+
+        // Without its '{', the nodes of the block can't be told apart from what follows
+        // it, so they are left to be skipped over while recovering from the error.
+        if self.curr_tok.tok_type != TokenType::LeftBrace {
+            self.expect(TokenType::LeftBrace);
+
+            return Block {
+                nodes: Vec::new(),
+                span: Span::new(start.start, start.start, start.line, start.col)
+            };
         }
 
-        Box::new(el_ast)
+        // Consume "{"
+        self.get_next_tok();
+
+        self.depth += 1;
+        let nodes = self.elements();
+        self.depth -= 1;
+
+        // Consume "}"
+        self.expect(TokenType::RightBrace);
+
+        Block {
+            nodes,
+            span: self.span_from(start)
+        }
     }
 
     /// Parse an attribute list for an html element. An attribute list can contain any number
     /// of desired html attributes, which do not need to be separated by commas (a space is fine).
-    /// This method will consume all required punctuation as well.
-    fn attr_list(&mut self) -> Box<Ast> {
-        let mut attr_ast = Ast::new(AstType::AttrList);
+    /// This method will consume the parens, but leaves the following arrow to the caller.
+    ///
+    /// An attribute written without a value, such as 'disabled', has no value. After a syntax
+    /// error the list skips ahead to its ')', or to the next attribute on a new line, and an
+    /// Error attribute is added in place of what was skipped.
+    fn attr_list(&mut self) -> Vec<Attr> {
+        let mut attrs = Vec::new();
 
         self.expect(TokenType::LeftParen);
 
-        while self.curr_tok.tok_type != TokenType::RightParen {
-            attr_ast.children.push(self.term());
+        while self.curr_tok.tok_type != TokenType::RightParen && !self.panicking {
+            if self.curr_tok.tok_type != TokenType::Ident {
+                let err = format!("Expected an attribute name, found {:?}",
+                                  self.curr_tok.tok_type);
+                self.syntax_err(&err);
+
+                // Skip the token, unless it closes the block the element is in.
+                let skip_start = self.curr_tok.span;
+                if self.curr_tok.tok_type != TokenType::RightBrace {
+                    self.get_next_tok();
+                }
+
+                self.recover_attr_list(&mut attrs, skip_start);
+                continue;
+            }
 
-            self.expect(TokenType::Colon);
+            let name = self.ident();
 
-            attr_ast.children.push(self.term());
+            let value = if self.curr_tok.tok_type == TokenType::Colon {
+                // Consume ":"
+                self.get_next_tok();
+                Some(self.attr_value())
+            } else {
+                None
+            };
 
-            if self.diagnostic.is_err() {
-                break;
+            attrs.push(Attr::Named {
+                name,
+                value
+            });
+
+            if self.panicking {
+                let skip_start = self.curr_tok.span;
+                self.recover_attr_list(&mut attrs, skip_start);
             }
         }
 
         self.expect(TokenType::RightParen);
 
-        self.expect(TokenType::Arrow);
+        attrs
+    }
 
-        Box::new(attr_ast)
+    /// Skip ahead after a syntax error in an attribute list. If the list carries on, with
+    /// its ')' or another attribute on a new line, the parser has recovered. Otherwise the
+    /// list was left unclosed, such as when the next line starts a new element, and the
+    /// parser carries on recovering from the element.
+    fn recover_attr_list(&mut self, attrs: &mut Vec<Attr>, start: Span) {
+        attrs.push(Attr::Error {
+            span: self.synchronize(start)
+        });
+
+        let at_attr = self.curr_tok.tok_type == TokenType::Ident
+            && !self.at_keyword()
+            && self.peek() != TokenType::LeftParen;
+
+        if self.curr_tok.tok_type == TokenType::RightParen || at_attr {
+            self.panicking = false;
+        }
     }
 
-    /// Parse an intial test inside an expression.
-    fn expr(&mut self) -> Box<Ast> {
-        let mut test_ast = self.op();
-        let curr_ast_type = match self.curr_tok.tok_type {
-            TokenType::Gt => AstType::Gt,
-            TokenType::Lt => AstType::Lt,
-            TokenType::GtEquals => AstType::GtEquals,
-            TokenType::LtEquals => AstType::LtEquals,
-            TokenType::NotEquals => AstType::NotEquals,
-            TokenType::EqualsEquals => AstType::EqualsEquals,
-            TokenType::Colon => {
-                self.get_next_tok();
-                test_ast.var_type = Some(self.curr_tok.val.clone());
-                // Consume the type.
-                self.get_next_tok();
+    /// Parse the value of an attribute, following its ':'. This is a term, such as a string
+    /// literal or a '%var' reference, which may be negated with '!' or compared with another
+    /// term, or a conditional value written as 'if %active "on" else "off"'. The else branch
+    /// is optional, and when it is missing and the condition is false the attribute is left
+    /// out.
+    fn attr_value(&mut self) -> Expr {
+        if self.curr_tok.tok_type != TokenType::Ident || self.curr_tok.val != "if" {
+            return self.logic_not();
+        }
 
-                AstType::AssignExpr
-            },
-            TokenType::Equals => {
-                self.expect(TokenType::Ident);
-                AstType::Empty
-            },
-            _ => test_ast.ast_type.clone()
+        let start = self.curr_tok.span;
+
+        // Consume "if"
+        self.enter_expr();
+        self.get_next_tok();
+        let cond = self.logic_or();
+        self.exit_expr();
+        let then = self.attr_value();
+
+        let else_ = if self.curr_tok.tok_type == TokenType::Ident && self.curr_tok.val == "else" {
+            // Consume "else"
+            self.get_next_tok();
+            Some(Box::new(self.attr_value()))
+        } else {
+            None
         };
 
-        let test_ast_next = test_ast;
-        test_ast = Box::new(Ast::new(curr_ast_type));
+        Expr::Conditional {
+            cond: Box::new(cond),
+            then: Box::new(then),
+            else_,
+            span: self.span_from(start)
+        }
+    }
+
+    /// Parse any number of conditions joined by '||'. This binds the loosest of all
+    /// the operators, so 'a || b && c' is parsed as 'a || (b && c)'.
+    fn logic_or(&mut self) -> Expr {
+        let start = self.curr_tok.span;
+        let mut or_expr = self.logic_and();
+
+        while self.curr_tok.tok_type == TokenType::Or {
+            self.get_next_tok();
+            let rhs = self.logic_and();
+            or_expr = self.binary(BinaryOp::Or, or_expr, rhs, start);
+        }
+
+        or_expr
+    }
+
+    /// Parse any number of conditions joined by '&&'.
+    fn logic_and(&mut self) -> Expr {
+        let start = self.curr_tok.span;
+        let mut and_expr = self.logic_not();
+
+        while self.curr_tok.tok_type == TokenType::And {
+            self.get_next_tok();
+            let rhs = self.logic_not();
+            and_expr = self.binary(BinaryOp::And, and_expr, rhs, start);
+        }
+
+        and_expr
+    }
+
+    /// Parse a condition, which may be negated with any number of '!'.
+    fn logic_not(&mut self) -> Expr {
+        if self.curr_tok.tok_type != TokenType::Not {
+            return self.comparison();
+        }
+
+        let start = self.curr_tok.span;
+
+        // Consume "!"
         self.get_next_tok();
+        let operand = self.logic_not();
+
+        Expr::Unary {
+            op: UnaryOp::Not,
+            operand: Box::new(operand),
+            span: self.span_from(start)
+        }
+    }
 
-        test_ast.children.push(test_ast_next);
-        test_ast.children.push(self.op());
+    /// Parse a single comparison between two operations, or just an operation on
+    /// its own if no comparison operator follows it.
+    fn comparison(&mut self) -> Expr {
+        let start = self.curr_tok.span;
+        let lhs = self.op();
+        let op = match self.curr_tok.tok_type {
+            TokenType::Gt => BinaryOp::Gt,
+            TokenType::Lt => BinaryOp::Lt,
+            TokenType::GtEquals => BinaryOp::GtEquals,
+            TokenType::LtEquals => BinaryOp::LtEquals,
+            TokenType::NotEquals => BinaryOp::NotEquals,
+            TokenType::EqualsEquals => BinaryOp::EqualsEquals,
+            _ => return lhs
+        };
+
+        self.get_next_tok();
+        let rhs = self.op();
 
-        test_ast
+        self.binary(op, lhs, rhs, start)
     }
 
-    /// Parse an operation inside an expression.
-    fn op(&mut self) -> Box<Ast> {
-        let mut op_ast = self.term();
+    /// Parse an operation inside an expression, made up of any number of factors
+    /// joined by '+' and '-'.
+    fn op(&mut self) -> Expr {
+        let start = self.curr_tok.span;
+        let mut op_expr = self.factor();
+
+        loop {
+            let op = match self.curr_tok.tok_type {
+                TokenType::Plus => BinaryOp::Plus,
+                TokenType::Minus => BinaryOp::Minus,
+                _ => break
+            };
 
-        while self.curr_tok.tok_type == TokenType::Plus || self.curr_tok.tok_type == TokenType::Minus {
-            let op_ast_next = op_ast;
+            self.get_next_tok();
+            let rhs = self.factor();
+            op_expr = self.binary(op, op_expr, rhs, start);
+        }
+
+        op_expr
+    }
 
-            //TODO: Currently, only supporting plus and minus
-            let curr_ast_type = match self.curr_tok.tok_type {
-                TokenType::Plus => AstType::Plus,
-                TokenType::Minus => AstType::Minus,
-                _ => AstType::Empty
+    /// Parse any number of terms joined by '*', '/' and '%'. These bind tighter
+    /// than '+' and '-', so 'a + b * c' is parsed as 'a + (b * c)'.
+    fn factor(&mut self) -> Expr {
+        let start = self.curr_tok.span;
+        let mut factor_expr = self.unary();
+
+        loop {
+            let op = match self.curr_tok.tok_type {
+                TokenType::Star => BinaryOp::Multiply,
+                TokenType::Slash => BinaryOp::Divide,
+                TokenType::Percent => BinaryOp::Modulo,
+                _ => break
             };
 
-            op_ast = Box::new(Ast::new(curr_ast_type));
             self.get_next_tok();
-            op_ast.children.push(op_ast_next);
-            op_ast.children.push(self.term());
+            let rhs = self.unary();
+            factor_expr = self.binary(op, factor_expr, rhs, start);
         }
 
-        op_ast
+        factor_expr
     }
 
-    /// Method will parse a term in an expression. This can be a constant identifier
-    /// or number, or could also contain another expression.
-    fn term(&mut self) -> Box<Ast> {
-        let term_ast;
+    /// Parse a term, which may be negated with any number of '-'.
+    fn unary(&mut self) -> Expr {
+        if self.curr_tok.tok_type != TokenType::Minus {
+            return self.term();
+        }
+
+        let start = self.curr_tok.span;
+
+        // Consume "-"
+        self.get_next_tok();
+        let operand = self.unary();
+
+        Expr::Unary {
+            op: UnaryOp::Negate,
+            operand: Box::new(operand),
+            span: self.span_from(start)
+        }
+    }
+
+    /// Method will parse a term in an expression. This can be a constant identifier,
+    /// number or string, a variable reference such as '%user.name', or could also contain
+    /// another expression grouped in parens.
+    fn term(&mut self) -> Expr {
+        let start = self.curr_tok.span;
+
         match self.curr_tok.tok_type {
-            TokenType::Ident => {
-                // If we find a left paren next, we are declaring an element.
-                let m_type = match self.peek() {
-                    TokenType::LeftParen => AstType::ElementName,
-                    _ => AstType::Ident
-                };
-                term_ast = Box::new(Ast::new_from_value(m_type, &self.curr_tok.val));
+            TokenType::Ident => Expr::Ident(self.ident()),
+            TokenType::Number => {
+                let val = self.number();
+
+                // Consume the number
+                self.get_next_tok();
+
+                match val {
+                    Some(val) => Expr::Number {
+                        val,
+                        span: start
+                    },
+                    None => Expr::Error {
+                        span: start
+                    }
+                }
+            },
+            TokenType::StringLiteral => {
+                let val = self.curr_tok.val.clone();
+                self.get_next_tok();
+
+                Expr::Str {
+                    val,
+                    span: start
+                }
+            },
+            TokenType::StringPart => {
+                let string_expr = self.interpolated_string();
+
+                // Consume the last part of the string
                 self.get_next_tok();
+                string_expr
             },
-            TokenType::Number => {
-                term_ast = Box::new(Ast::new_from_value(AstType::Number, &self.curr_tok.val));
+            TokenType::Error => {
+                let err = self.curr_tok.val.clone();
+                self.syntax_err(&err);
                 self.get_next_tok();
+
+                Expr::Error {
+                    span: start
+                }
             },
             TokenType::Eof => {
-                term_ast = Box::new(Ast::new(AstType::Eof));
+                self.syntax_err("Expected an expression, found the end of the template");
+
+                Expr::Error {
+                    span: start
+                }
+            },
+            TokenType::LeftParen => {
+                // Consume "("
+                self.get_next_tok();
+                let expr = self.logic_or();
+
+                // Consume ")"
+                self.expect(TokenType::RightParen);
+                expr
+            },
+            TokenType::Percent => {
+                // Consume "%"
+                self.get_next_tok();
+                let value_expr = self.value_ref(start);
+
+                // Consume the identifier or "}"
+                self.get_next_tok();
+                value_expr
             },
-            TokenType::Arrow => {
+            _ => {
                 let err = format!("Unexpected token {:?} found",
                                   self.curr_tok.val);
-                self.diagnostic.new_err(&err);
-                term_ast = Box::new(Ast::new(AstType::Eof));
+                self.syntax_err(&err);
+
+                Expr::Error {
+                    span: start
+                }
+            }
+        }
+    }
+
+    /// Parse the contents of an element, following its '->', and add them to the body of
+    /// the element. The current token is the arrow, and the lexer is still positioned
+    /// directly after it, so we can decide how to lex what follows: a single nested
+    /// element, a braced block of any number of nodes, or text.
+    ///
+    /// Text is lexed in text mode, which keeps the source exactly as written up to the
+    /// end of the line. Only '%var' and '%{ expr }' interpolations and '&file' includes
+    /// are recognised inside it. Contents written as a single string literal are also accepted.
+    fn contents(&mut self, body: &mut Vec<Node>) {
+        self.lexer.skip_whitespace();
+
+        if self.lexer.at_element_start() {
+            self.get_next_tok();
+            body.extend(self.element());
+            return;
+        }
+
+        if self.lexer.curr_char == Some('{') {
+            self.get_next_tok();
+            body.append(&mut self.block().nodes);
+            return;
+        }
+
+        let mut parts = Vec::new();
+
+        if matches!(self.lexer.curr_char, Some('"') | Some('\'')) {
+            self.get_next_tok();
+
+            match self.curr_tok.tok_type {
+                TokenType::StringLiteral => {
+                    parts.push(TextPart::Text {
+                        text: self.curr_tok.val.clone(),
+                        span: self.curr_tok.span
+                    });
+                },
+                TokenType::StringPart => {
+                    parts.push(TextPart::Value(self.interpolated_string()));
+                },
+                _ => {
+                    let err = self.curr_tok.val.clone();
+                    self.syntax_err(&err);
+                }
+            }
+        }
+
+        loop {
+            self.get_next_text_tok();
+            let part_start = self.curr_tok.span;
+
+            let part = match self.curr_tok.tok_type {
+                TokenType::Text => {
+                    TextPart::Text {
+                        text: self.curr_tok.val.clone(),
+                        span: part_start
+                    }
+                },
+                TokenType::Percent => {
+                    // Consume "%", the identifier or expression is lexed normally.
+                    self.get_next_tok();
+                    TextPart::Value(self.value_ref(part_start))
+                },
+                TokenType::Ampersand => {
+                    // Consume "&", the filename is lexed normally.
+                    self.get_next_tok();
+
+                    TextPart::Include {
+                        path: self.curr_tok.val.clone(),
+                        span: part_start.to(&self.curr_tok.span)
+                    }
+                },
+                _ => break
+            };
+
+            parts.push(part);
+        }
+
+        let span = match (parts.first(), parts.last()) {
+            (Some(first), Some(last)) => first.span().to(&last.span()),
+            _ => Span::default()
+        };
+
+        // Text mode is over, so lex the token following the contents.
+        self.get_next_tok();
+
+        body.push(Node::Text {
+            parts,
+            span
+        });
+    }
+
+    /// Parse a reference to a value once its '%' has been consumed: either a variable
+    /// such as '%user.name', or an expression interpolation such as '%{ x * 2 }'. A '!'
+    /// after the '%' marks the value as trusted html, so it won't be escaped.
+    ///
+    /// The last token of the reference, the identifier or the closing '}', is left as
+    /// the current token, since in text mode the contents carry on directly after it.
+    fn value_ref(&mut self, start: Span) -> Expr {
+        let is_raw = self.curr_tok.tok_type == TokenType::Not;
+        if is_raw {
+            // Consume "!"
+            self.get_next_tok();
+        }
+
+        let value_expr = match self.curr_tok.tok_type {
+            TokenType::LeftBrace => self.interpolation(start),
+            TokenType::Ident => {
+                Expr::Var(Ident {
+                    name: self.curr_tok.val.clone(),
+                    span: start.to(&self.curr_tok.span)
+                })
             },
             _ => {
-                term_ast = self.expr();
+                let err = format!("Expected a variable or {:?} after '%', found {:?}",
+                                  TokenType::LeftBrace,
+                                  self.curr_tok.tok_type);
+                self.syntax_err(&err);
+
+                Expr::Error {
+                    span: start.to(&self.curr_tok.span)
+                }
             }
+        };
+
+        if !is_raw {
+            return value_expr;
         }
 
-        term_ast
+        Expr::Raw {
+            span: value_expr.span(),
+            value: Box::new(value_expr)
+        }
     }
 
-    /// Generates the contents of an element by joining together many identifiers
-    /// separated by spaces.  Also will consume references to other files within
-    /// the element contents and interpolate variables.
-    fn contents(&mut self) -> Box<Ast> {
-        if self.curr_tok.tok_type == TokenType::Arrow {
-            let err = format!("Unexpected token {:?} found",
-                              self.curr_tok.val);
-            self.diagnostic.new_err(&err);
+    /// Read the value of the current Number token. A number with a '.' is a float, and
+    /// anything else an int. Returns None if the number doesn't fit in an int, which is
+    /// reported.
+    fn number(&mut self) -> Option<Number> {
+        let val = &self.curr_tok.val;
+
+        if val.contains('.') {
+            return val.parse().ok().map(Number::Float);
         }
 
-        match self.curr_tok.tok_type {
-            TokenType::Ident => {
-                let mut contents_ast = Ast::new(AstType::Contents);
+        match val.parse() {
+            Ok(i) => Some(Number::Int(i)),
+            Err(_) => {
+                let err = format!("Integer {} is too large", val);
+                self.diagnostic.parse_err(&err, &self.curr_tok)
+                    .with_help(&format!("ints must be between {} and {}", i64::MIN, i64::MAX));
+                None
+            }
+        }
+    }
 
-                while (self.curr_tok.tok_type == TokenType::Ident) ||
-                    (self.curr_tok.tok_type == TokenType::Percent) {
+    /// Parse an expression interpolation such as '%{ x * 2 }', once the '%' has been
+    /// consumed. The closing '}' is left as the current token, since in text mode the
+    /// contents carry on directly after it.
+    fn interpolation(&mut self, start: Span) -> Expr {
+        // Consume "{"
+        self.enter_expr();
+        self.get_next_tok();
+        let expr = self.logic_or();
+        self.exit_expr();
+
+        if self.curr_tok.tok_type != TokenType::RightBrace {
+            let err = format!("Expected {:?}, found {:?}",
+                              TokenType::RightBrace,
+                              self.curr_tok.tok_type);
+            self.syntax_err(&err);
+        }
 
-                    if self.peek() == TokenType::LeftParen {
-                        break;
-                    }
+        Expr::Interpolation {
+            expr: Box::new(expr),
+            span: start.to(&self.curr_tok.span)
+        }
+    }
 
-                    let child = match self.curr_tok.tok_type {
-                        TokenType::Ident => Ast::new_from_value(AstType::Ident, &self.curr_tok.val),
-                        TokenType::Percent => {
-                            self.get_next_tok();
-                            Ast::new_from_value(AstType::VariableValue, &self.curr_tok.val)
-                        },
-                        _ => Ast::new(AstType::Eof)
-                    };
+    /// Parse a string literal containing '%{ expr }' interpolations, such as
+    /// "/users/%{ id }". The parts are the literal parts of the string and the
+    /// interpolations, in the order they were written. The lexer splits the string at
+    /// each '%{' into a StringPart token, and the last part is a StringLiteral, which
+    /// is left as the current token.
+    fn interpolated_string(&mut self) -> Expr {
+        let mut parts = Vec::new();
+        let start = self.curr_tok.span;
+
+        loop {
+            let is_string = matches!(self.curr_tok.tok_type,
+                                     TokenType::StringPart | TokenType::StringLiteral);
+
+            if is_string && !self.curr_tok.val.is_empty() {
+                parts.push(Expr::Str {
+                    val: self.curr_tok.val.clone(),
+                    span: self.curr_tok.span
+                });
+            }
 
-                    contents_ast.children.push(Box::new(child));
+            match self.curr_tok.tok_type {
+                TokenType::StringPart => {
+                    // Consume the part, the lexer has left its "{" to be lexed next.
                     self.get_next_tok();
+                    let interp_start = self.curr_tok.span;
+                    parts.push(self.interpolation(interp_start));
+
+                    if self.panicking {
+                        break;
+                    }
+
+                    // Consume "}", and lex the rest of the string.
+                    self.get_next_string_tok();
+                },
+                TokenType::StringLiteral => break,
+                _ => {
+                    let err = self.curr_tok.val.clone();
+                    self.syntax_err(&err);
+                    break;
                 }
+            }
+        }
 
-                Box::new(contents_ast)
-            },
-            TokenType::Ampersand => {
-                // Consume "&"
-                self.get_next_tok();
+        Expr::InterpolatedString {
+            parts,
+            span: start.to(&self.curr_tok.span)
+        }
+    }
 
-                let include_ast = Box::new(Ast::new_from_value(AstType::Include, &self.curr_tok.val));
+    /// Build a binary expression from its operands, spanning from the given start up to
+    /// the end of the right hand side.
+    fn binary(&self, op: BinaryOp, lhs: Expr, rhs: Expr, start: Span) -> Expr {
+        Expr::Binary {
+            op,
+            lhs: Box::new(lhs),
+            rhs: Box::new(rhs),
+            span: self.span_from(start)
+        }
+    }
 
-                // Consume the identifier for the filename now
-                self.get_next_tok();
+    /// Parse a single identifier, such as the name of an element or a variable. If the
+    /// current token isn't an identifier, a syntax error is reported, nothing is consumed
+    /// and the identifier returned is empty.
+    fn ident(&mut self) -> Ident {
+        let span = self.curr_tok.span;
 
-                include_ast
-            },
-            TokenType::Percent => {
-                // Consume "%"
-                self.get_next_tok();
+        if self.curr_tok.tok_type != TokenType::Ident {
+            self.expect(TokenType::Ident);
 
-                let var_ast = Box::new(Ast::new_from_value(AstType::VariableValue, &self.curr_tok.val));
+            return Ident {
+                name: String::new(),
+                span
+            };
+        }
 
-                // Consume identifier
-                self.get_next_tok();
+        let name = self.curr_tok.val.clone();
+        self.get_next_tok();
 
-                var_ast
-            },
-            _ => {
-                Box::new(Ast::new(AstType::Eof))
-            }
+        Ident {
+            name,
+            span
         }
     }
 
     /// Match the current token to an expected one. If the current token does not equal
-    /// the expected one, the parser will panic. Otherwise, we will advance to the next
-    /// token and update the parser internals.
+    /// the expected one, a syntax error is reported and nothing is consumed. Otherwise, we
+    /// will advance to the next token and update the parser internals.
     fn expect(&mut self, token_type: TokenType) {
         if self.curr_tok.tok_type == token_type {
             self.get_next_tok();
@@ -363,8 +1056,61 @@ impl Parser {
             let error_str = format!("Expected {:?}, found {:?}",
                                     token_type,
                                     self.curr_tok.tok_type);
-            self.diagnostic.parse_err(&error_str, &self.curr_tok);
+            self.syntax_err(&error_str);
+        }
+    }
+
+    /// Report a syntax error at the current token, and start skipping ahead to a point
+    /// where parsing can carry on. While the parser is recovering from one error, any
+    /// others are caused by it, so they aren't reported. Returns the report, if there
+    /// is one, so that help can be added to it.
+    fn syntax_err(&mut self, err: &str) -> Option<&mut Report> {
+        if self.panicking {
+            return None;
+        }
+
+        self.panicking = true;
+        Some(self.diagnostic.parse_err(err, &self.curr_tok))
+    }
+
+    /// Skip tokens after a syntax error, until one that parsing can carry on from: a '}'
+    /// or ')' closing a block or list, a keyword starting an element, or a token on a new
+    /// line of the template outside of any block. Braces and parens opened by the skipped
+    /// tokens are skipped up to their matching close. Returns the span of the source
+    /// skipped since start, which is empty if nothing was skipped.
+    fn synchronize(&mut self, start: Span) -> Span {
+        let mut nesting = 0;
+
+        loop {
+            match self.curr_tok.tok_type {
+                TokenType::Eof => break,
+                TokenType::RightBrace | TokenType::RightParen if nesting == 0 => break,
+                TokenType::LeftBrace | TokenType::LeftParen => nesting += 1,
+                TokenType::RightBrace | TokenType::RightParen => nesting -= 1,
+                _ if nesting == 0 && (self.at_keyword() || self.at_line_start()) => break,
+                _ => {}
+            }
+
+            self.get_next_tok();
         }
+
+        if self.prev_span.end > start.start {
+            self.span_from(start)
+        } else {
+            Span::new(self.curr_tok.span.start, self.curr_tok.span.start,
+                      self.curr_tok.span.line, self.curr_tok.span.col)
+        }
+    }
+
+    /// Whether the current token is a keyword that starts an element, such as "if".
+    fn at_keyword(&self) -> bool {
+        self.curr_tok.tok_type == TokenType::Ident
+            && matches!(self.curr_tok.val.as_ref(), "if" | "for" | "let")
+    }
+
+    /// Whether the current token is the first on its line, outside of any block.
+    fn at_line_start(&self) -> bool {
+        self.depth == 0 && self.curr_tok.span.line > self.prev_span.line
     }
 
     /// Retrieve the next available token for parsing. This token is retrieved from the lexer's
@@ -372,12 +1118,68 @@ impl Parser {
     /// indicating EOF. We then update the internal value and type fields of the Parser
     /// struct.
     fn get_next_tok(&mut self) -> &mut Parser {
+        self.consume();
         self.lexer.lex();
         self.curr_tok  = self.lexer.curr_tok.clone().unwrap_or(Token::new_from_empty());
 
         self
     }
 
+    /// Lex the following tokens as part of an expression, where a '-' is never part of
+    /// an identifier. Called before consuming the token the expression follows, such
+    /// as the '=' of a let, since that lexes the first token of the expression.
+    fn enter_expr(&mut self) {
+        self.lexer.enter_expr();
+    }
+
+    /// Stop lexing an expression, once it has been parsed. The token following it may
+    /// be lexed again as a longer identifier, such as an element name containing a '-'.
+    fn exit_expr(&mut self) {
+        self.lexer.exit_expr();
+        self.curr_tok = self.lexer.curr_tok.clone().unwrap_or(Token::new_from_empty());
+    }
+
+    /// Retrieve the next token in text mode, used for the contents of an element.
+    fn get_next_text_tok(&mut self) -> &mut Parser {
+        self.consume();
+        self.lexer.lex_text();
+        self.curr_tok = self.lexer.curr_tok.clone().unwrap_or(Token::new_from_empty());
+
+        self
+    }
+
+    /// Retrieve the body of a raw block, following the "raw" keyword.
+    fn get_next_raw_tok(&mut self) -> &mut Parser {
+        self.consume();
+        self.lexer.lex_raw_block();
+        self.curr_tok = self.lexer.curr_tok.clone().unwrap_or(Token::new_from_empty());
+
+        self
+    }
+
+    /// Retrieve the next part of a string literal, after the '}' closing one of its
+    /// interpolations.
+    fn get_next_string_tok(&mut self) -> &mut Parser {
+        self.consume();
+        self.lexer.lex_string_rest();
+        self.curr_tok = self.lexer.curr_tok.clone().unwrap_or(Token::new_from_empty());
+
+        self
+    }
+
+    /// Record the span of the current token before moving past it. Eof tokens are
+    /// skipped, so that a node ends at the last real token it contains.
+    fn consume(&mut self) {
+        if self.curr_tok.tok_type != TokenType::Eof {
+            self.prev_span = self.curr_tok.span;
+        }
+    }
+
+    /// Returns a span from the given start up to the end of the last consumed token.
+    fn span_from(&self, start: Span) -> Span {
+        start.to(&self.prev_span)
+    }
+
     /// Check the current token but do not consume it.
     fn peek(&self) -> TokenType {
         self.lexer.peek_tok().tok_type
@@ -5,15 +5,24 @@ pub struct Reserved {
     pub words: HashMap<String, String>
 }
 
+impl Default for Reserved {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 // TODO: Better to initialize this hashmap from a constant list (a map is nice
 // to have due to constant lookup)
 impl Reserved {
     pub fn new() -> Reserved {
         let mut w = HashMap::new();
         w.insert(String::from("if"), String::from("if"));
+        w.insert(String::from("else"), String::from("else"));
         w.insert(String::from("let"), String::from("let"));
         w.insert(String::from("for"), String::from("for"));
         w.insert(String::from("in"), String::from("in"));
+        w.insert(String::from("doctype"), String::from("doctype"));
+        w.insert(String::from("raw"), String::from("raw"));
 
         // Add types
         w.insert(String::from("int"), String::from("int"));
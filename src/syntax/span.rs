@@ -0,0 +1,43 @@
+use std::fmt;
+
+/// A range of source text. Tokens and ast nodes carry a span so that diagnostics
+/// from any phase can point back to the exact text they came from.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct Span {
+    /// Byte offset of the first char in the span
+    pub start: usize,
+    /// Byte offset one past the last char in the span
+    pub end: usize,
+    /// Line number of the first char in the span
+    pub line: usize,
+    /// Position of the first char on its line
+    pub col: usize
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize, line: usize, col: usize) -> Span {
+        Span {
+            start,
+            end,
+            line,
+            col
+        }
+    }
+
+    /// Creates a span covering both this span and another one that follows it.
+    /// The line and column are taken from this span, since it starts first.
+    pub fn to(&self, other: &Span) -> Span {
+        Span {
+            start: self.start,
+            end: other.end.max(self.end),
+            line: self.line,
+            col: self.col
+        }
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, pos {}", self.line, self.col)
+    }
+}
@@ -1,20 +1,57 @@
 use std::collections::{HashMap, BTreeMap};
-use syntax::ast::{Ast, AstType};
+use std::fmt;
+use syntax::span::Span;
+use syntax::value::Value;
 
-const GLOBAL_SCOPE: &'static str = "global";
-const FOR_SCOPE: &'static str = "for";
+const GLOBAL_SCOPE: &str = "global";
+const FOR_SCOPE: &str = "for";
+
+/// Name of the helper map bound inside the block of a for loop, such as 'loop.index'.
+pub const LOOP: &str = "loop";
 
 #[derive(Clone)]
 pub struct Symbol {
     pub name: String,
+    /// Declared type of the symbol, or the type of its value if it came from the
+    /// config file
     pub sym_type: String,
-    pub val: String,
-    pub scope: String
+    /// Value of the symbol. Variables declared in a template are Null until their
+    /// declaration is evaluated during generation.
+    pub val: Value,
+    pub scope: String,
+    /// Span of the declaration in the template. None for values from the config file
+    /// and loop variables.
+    pub declared_at: Option<Span>
+}
+
+/// One step of a path after the name of the symbol: a '.field' of a map, or an
+/// '[index]' into a list.
+enum Segment<'a> {
+    Field(&'a str),
+    Index(&'a str)
+}
+
+impl<'a> fmt::Display for Segment<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Segment::Field(key) => write!(f, ".{}", key),
+            Segment::Index(index) => write!(f, "[{}]", index)
+        }
+    }
 }
 
 #[derive(Clone)]
 pub struct SymbolTable {
-    table: HashMap<String, Symbol>
+    table: HashMap<String, Symbol>,
+    /// Stack of local scopes, such as the body of a for loop. Symbols in the innermost
+    /// scope are found first, and are dropped when the scope is popped.
+    scopes: Vec<HashMap<String, Symbol>>
+}
+
+impl Default for SymbolTable {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl SymbolTable {
@@ -22,101 +59,210 @@ impl SymbolTable {
         let symbols = HashMap::new();
 
         SymbolTable {
-            table: symbols
+            table: symbols,
+            scopes: Vec::new()
         }
     }
 
     /// Creates a new symbol table from an existing map. Expects this map
     /// to be serialized from a json input file when tank is run.
-    pub fn from_existing_map(map: &BTreeMap<String, String>) -> SymbolTable {
+    pub fn from_existing_map(map: &BTreeMap<String, Value>) -> SymbolTable {
         let mut symbols = HashMap::new();
 
         for (k, v) in map.iter() {
             let sym = Symbol {
                 name: k.to_owned(),
-                sym_type: "String".to_owned(),
-                val: v.to_owned(),
-                scope: GLOBAL_SCOPE.to_owned()
+                sym_type: v.type_name().to_owned(),
+                val: v.clone(),
+                scope: GLOBAL_SCOPE.to_owned(),
+                declared_at: None
             };
 
             symbols.insert(k.to_owned(), sym);
         }
 
         SymbolTable {
-            table: symbols
+            table: symbols,
+            scopes: Vec::new()
         }
     }
 
-    /// Expects an ast with a type of 'AssignExpr'. We then check the children
-    /// and their types, and then if that identifier already exists. If it does,
-    /// we will panic. If not, we construct a new symbol struct and put it in the
-    /// symbol table.
-    pub fn insert(&mut self, ast: &Box<Ast>) -> &mut SymbolTable {
-        if ast.ast_type != AstType::AssignExpr {
-            panic!("tank: Invalid ast type found in symbol table");
+    /// Declare a variable with the given name and type. If a symbol with that name
+    /// already exists, an error is returned. If not, we construct a new symbol struct
+    /// and put it in the symbol table. The value is only evaluated during generation,
+    /// so it is left as Null here. The span is that of the whole declaration.
+    pub fn insert(&mut self, name: &str, sym_type: &str, span: Span) -> Result<&mut SymbolTable, String> {
+        if let Some(sym) = self.table.get(name) {
+            return Err(format!("Redeclared symbol '{}' found", sym.name));
         }
 
-        if ast.children.len() < 2 {
-            panic!("tank: Invalid ast passed to symbol table");
-        }
+        let sym = Symbol {
+            name: name.to_owned(),
+            sym_type: sym_type.to_owned(),
+            val: Value::Null,
+            scope: GLOBAL_SCOPE.to_owned(),
+            declared_at: Some(span)
+        };
 
-        // Expect the first child to the be the identifier name and the type, and
-        // the second child will be the value of the variable.
-        let ident = ast.children[0].clone().val;
-        let value = ast.children[1].clone().val;
+        self.table.insert(name.to_owned(), sym);
 
-        let ident_type = ast.children[0].clone().var_type.unwrap_or_else(|| {
-            panic!("tank: Variable declared without a type");
-        });
+        Ok(self)
+    }
 
-        match self.table.get(&ident) {
-            Some(sym) => panic!("tank: Redeclared symbol {} found", sym.val),
-            _ => ()
-        };
+    /// Start a new local scope. Symbols bound after this are only visible until
+    /// the matching call to pop_scope().
+    pub fn push_scope(&mut self) -> &mut SymbolTable {
+        self.scopes.push(HashMap::new());
 
-        let sym = Symbol {
-            name: ident.clone(),
-            sym_type: ident_type,
-            val: value,
-            scope: GLOBAL_SCOPE.to_owned()
-        };
+        self
+    }
 
-        self.table.insert(ident, sym);
+    /// End the innermost local scope, dropping all the symbols bound in it.
+    pub fn pop_scope(&mut self) -> &mut SymbolTable {
+        self.scopes.pop();
 
         self
     }
 
-    pub fn insert_for_id(&mut self, ast: &Ast) -> &mut SymbolTable {
-        if ast.ast_type != AstType::Ident {
-            panic!("tank: Invalid ast type {:?} found", ast.ast_type);
-        }
-
-        let ident = ast.clone().val;
+    /// Bind a value to a name in the innermost local scope, such as the variable of
+    /// a for loop. Unlike insert(), the name may shadow a symbol in an outer scope,
+    /// and binding it again in the same scope replaces the value.
+    pub fn bind(&mut self, name: &str, sym_type: &str, val: Value) -> &mut SymbolTable {
+        let sym = Symbol {
+            name: name.to_owned(),
+            sym_type: sym_type.to_owned(),
+            val,
+            scope: FOR_SCOPE.to_owned(),
+            declared_at: None
+        };
 
-        match self.table.get(&ident) {
-            Some(sym) => panic!("tank: Redeclared symbol {} found", sym.val),
-            _ => ()
+        match self.scopes.last_mut() {
+            Some(scope) => scope.insert(name.to_owned(), sym),
+            None => self.table.insert(name.to_owned(), sym)
         };
 
-        let ident_type = ast.var_type.clone().unwrap_or_else(|| {
-            panic!("tank: Variable declared without a type");
-        });
+        self
+    }
 
-        let sym = Symbol {
-            name: ident.clone(),
-            sym_type: ident_type,
-            val: ident.clone(),
-            scope: FOR_SCOPE.to_owned()
+    /// Update the value of a symbol that has already been declared. Used once the value
+    /// of a variable has been computed from its declaration.
+    pub fn set(&mut self, key: &str, val: Value) -> &mut SymbolTable {
+        let scope = self.scopes.iter_mut()
+            .rev()
+            .find(|scope| scope.contains_key(key));
+
+        let sym = match scope {
+            Some(scope) => scope.get_mut(key),
+            None => self.table.get_mut(key)
         };
 
-        self.table.insert(ident, sym);
+        if let Some(sym) = sym {
+            sym.val = val;
+        }
 
         self
     }
 
     /// Wrapper function for getting a Symbol struct from the symbol table. Used for
-    /// convenience and so that the symbol_table struct field is left private.
+    /// convenience and so that the symbol_table struct field is left private. Local
+    /// scopes are searched first, from the innermost out.
     pub fn get(&mut self, key: String) -> Option<&Symbol> {
-        self.table.get(&key)
+        let local = self.scopes.iter()
+            .rev()
+            .find_map(|scope| scope.get(&key));
+
+        match local {
+            Some(sym) => Some(sym),
+            None => self.table.get(&key)
+        }
     }
+
+    /// Checks if a variable can be used at the given byte offset of the template. Every
+    /// declaration in the template is in the table once it is parsed, so only the ones
+    /// that end before the offset count.
+    pub fn is_declared_before(&mut self, key: &str, pos: usize) -> bool {
+        match self.get(key.to_owned()) {
+            Some(&Symbol { declared_at: Some(span), .. }) => span.end <= pos,
+            Some(_) => true,
+            None => false
+        }
+    }
+
+    /// Look up a value by its path, such as 'user.name', 'items[2]' or 'posts[0].author'.
+    /// The first part of the path names a symbol, and each part after it is a field of
+    /// a map or an index into a list. If the path can't be followed, the error names
+    /// the part that couldn't be found.
+    pub fn lookup(&mut self, path: &str) -> Result<&Value, String> {
+        let (name, segments) = split_path(path)?;
+
+        let mut value = match self.get(name.to_owned()) {
+            Some(sym) => &sym.val,
+            None => return Err(format!("'{}' is not defined", name))
+        };
+
+        let mut found = name.to_owned();
+
+        for segment in segments {
+            value = match (&segment, value) {
+                (&Segment::Field(key), Value::Map(map)) => {
+                    map.get(key).ok_or_else(|| format!("'{}' has no field '{}'", found, key))?
+                },
+                (&Segment::Index(index), Value::List(items)) => {
+                    index.parse::<usize>().ok()
+                        .and_then(|i| items.get(i))
+                        .ok_or_else(|| format!("index {} is out of range for '{}', which has {} items",
+                                               index,
+                                               found,
+                                               items.len()))?
+                },
+                (_, other) => {
+                    return Err(format!("'{}' is of type {}, so '{}' can't be looked up in it",
+                                       found,
+                                       other.type_name(),
+                                       segment));
+                }
+            };
+
+            found += &segment.to_string();
+        }
+
+        Ok(value)
+    }
+}
+
+/// Split a path such as 'posts[0].author' into the symbol name, 'posts', and the
+/// segments that follow it. Fails if a segment is empty, an index isn't closed, or
+/// anything other than a '.' or '[' follows an index.
+fn split_path(path: &str) -> Result<(&str, Vec<Segment<'_>>), String> {
+    let is_separator = |c: char| c == '.' || c == '[';
+    let malformed = || format!("'{}' is not a valid path", path);
+
+    let name_end = path.find(is_separator).unwrap_or(path.len());
+    if name_end == 0 {
+        return Err(malformed());
+    }
+
+    let mut rest = &path[name_end..];
+    let mut segments = Vec::new();
+
+    while !rest.is_empty() {
+        let (segment, end) = if let Some(index) = rest.strip_prefix('[') {
+            let end = index.find(']').ok_or_else(malformed)?;
+            (Segment::Index(&index[..end]), end + 2)
+        } else if let Some(field) = rest.strip_prefix('.') {
+            let end = field.find(is_separator).unwrap_or(field.len());
+            (Segment::Field(&field[..end]), end + 1)
+        } else {
+            return Err(malformed());
+        };
+
+        match segment {
+            Segment::Field("") | Segment::Index("") => return Err(malformed()),
+            _ => segments.push(segment)
+        }
+
+        rest = &rest[end..];
+    }
+
+    Ok((&path[..name_end], segments))
 }
@@ -1,3 +1,5 @@
+use syntax::span::Span;
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum TokenType {
     LeftBrace,
@@ -5,20 +7,32 @@ pub enum TokenType {
     LeftParen,
     RightParen,
     Colon,
+    Semicolon,
     Equals,
     Minus,
     Ampersand,
     Percent,
     Arrow,
     Plus,
+    Star,
+    Slash,
     Ident,
     Number,
+    StringLiteral,
+    StringPart,
+    Text,
+    HtmlComment,
+    RawText,
     EqualsEquals,
     Gt,
     Lt,
     GtEquals,
     LtEquals,
     NotEquals,
+    And,
+    Or,
+    Not,
+    Error,
     Eof
 }
 
@@ -30,31 +44,27 @@ pub struct Token {
     pub val: String,
     /// True if the token is a reserved word, false otherwise
     pub is_reserved: bool,
-    /// Character position of the token on the current line.
-    /// If the token is multiple characters, this is the index of the first charaster.
-    pub char_pos: usize,
-    /// Line number of the token
-    pub line_num: usize
+    /// Location of the token in the input. For multi-character tokens, the line
+    /// and column are those of the first character.
+    pub span: Span
 }
 
 impl Token {
-    pub fn new(token_type: TokenType, pos: usize, line: usize) -> Token {
+    pub fn new(token_type: TokenType, span: Span) -> Token {
         Token {
             tok_type: token_type,
             val: "".to_string(),
             is_reserved: false,
-            char_pos: pos,
-            line_num: line
+            span
         }
     }
 
-    pub fn new_from_value(token_type: TokenType, value: &str, pos: usize, line: usize) -> Token {
+    pub fn new_from_value(token_type: TokenType, value: &str, span: Span) -> Token {
         Token {
             tok_type: token_type,
             val: value.to_owned(),
             is_reserved: false,
-            char_pos: pos,
-            line_num: line
+            span
         }
     }
 
@@ -63,8 +73,7 @@ impl Token {
             tok_type: TokenType::Eof,
             val: "".to_string(),
             is_reserved: false,
-            char_pos: 0,
-            line_num: 0
+            span: Span::default()
         }
     }
 
@@ -0,0 +1,338 @@
+use std::collections::HashMap;
+use syntax::ast::{walk_block, walk_node, Attr, BinaryOp, Block, Else, Expr, Ident, Node, Number, Template,
+                  UnaryOp, VarType, Visitor};
+use syntax::span::Span;
+use syntax::symbol_table::{SymbolTable, LOOP};
+use syntax::value::Value;
+use error::type_err::TypeDiagnostic;
+
+/// Checks a parsed template before anything is generated from it. The type of each
+/// expression is worked out from the literals and variables in it, so that values which
+/// don't match their declared types, comparisons between values that can't be compared,
+/// for loops over values that aren't lists and variables that aren't defined are all
+/// reported at once, with the source they were found in.
+///
+/// The type of a value can't always be known before generation, such as a field of a
+/// map declared in the template. Expressions using such values are left to be checked
+/// by the generator.
+pub struct TypeChecker {
+    /// Variables from the config file, or the context of a template rendered in memory
+    globals: SymbolTable,
+    /// Declared types of the variables in the template. The first scope holds the variables
+    /// declared with 'let', and each for loop adds a scope for its variables while its
+    /// block is checked.
+    scopes: Vec<HashMap<String, String>>,
+    /// Error and warning information
+    pub diagnostic: TypeDiagnostic
+}
+
+impl TypeChecker {
+    /// Creates a new TypeChecker. The symbol table should hold only the variables defined
+    /// outside of the template, since the template's own declarations are checked in the
+    /// order they are written.
+    pub fn new(globals: SymbolTable) -> TypeChecker {
+        TypeChecker {
+            globals,
+            scopes: vec![HashMap::new()],
+            diagnostic: TypeDiagnostic::new()
+        }
+    }
+
+    /// Check the whole template, reporting every problem found.
+    pub fn check(&mut self, template: &Template) -> &mut TypeChecker {
+        self.visit_template(template);
+
+        self
+    }
+
+    /// Check the value of a variable declaration against its declared type, and declare
+    /// the variable for the rest of the template.
+    fn check_let(&mut self, var: &Ident, var_type: VarType, value: &Expr) {
+        // The variables declared so far are the ones a bare word could name.
+        let value_type = match value.bare_word(|name| self.is_defined(name)) {
+            Some(_) => Some("string".to_owned()),
+            None => self.expr_type(value)
+        };
+
+        if let Some(value_type) = value_type {
+            if !matches_type(&value_type, var_type) {
+                let err_str = format!("Type mismatch - '{}' is declared as {}, but its value is {}",
+                                      var.name,
+                                      var_type,
+                                      value_type);
+                self.diagnostic.type_err(&err_str, &value.span());
+            }
+        }
+
+        // The variable is declared even if its value is wrong, so that using it later
+        // doesn't cause more errors.
+        self.scopes[0].insert(var.name.clone(), var_type.name().to_owned());
+    }
+
+    /// Check that a for loop is over a list, and that each item in it matches the declared
+    /// type of the loop variable, then check the block with the loop variables declared.
+    fn check_for(&mut self, var: &Ident, var_type: VarType, iter: &Ident, body: &Block) {
+        match self.local_type(&iter.name) {
+            Some(Some(ref iter_type)) if iter_type != "list" => self.not_a_list(iter, iter_type),
+            Some(_) => {},
+            None => {
+                match self.globals.lookup(&iter.name).cloned() {
+                    Ok(Value::List(items)) => {
+                        let mismatch = items.iter()
+                            .enumerate()
+                            .find(|&(_, item)| !item.matches_type(var_type));
+
+                        if let Some((index, item)) = mismatch {
+                            let err_str = format!("Type mismatch - '{}' is declared as {}, but item {} of {} is {}",
+                                                  var.name,
+                                                  var_type,
+                                                  index,
+                                                  iter.name,
+                                                  item.type_name());
+                            self.diagnostic.type_err(&err_str, &var.span);
+                        }
+                    },
+                    Ok(Value::Null) => {},
+                    Ok(value) => self.not_a_list(iter, value.type_name()),
+                    Err(err) => {
+                        self.diagnostic.type_err(&err, &iter.span);
+                    }
+                }
+            }
+        }
+
+        let mut scope = HashMap::new();
+        scope.insert(var.name.clone(), var_type.name().to_owned());
+        scope.insert(LOOP.to_owned(), "map".to_owned());
+
+        self.scopes.push(scope);
+        self.visit_block(body);
+        self.scopes.pop();
+    }
+
+    fn not_a_list(&mut self, iter: &Ident, iter_type: &str) {
+        let err_str = format!("'{}' is not a list", iter.name);
+        let note = format!("its value is of type {}", iter_type);
+        self.diagnostic.type_err(&err_str, &iter.span)
+            .with_note(&note);
+    }
+
+    /// Check a condition, such as the one in an if statement, which must be a bool.
+    fn check_bool(&mut self, expr: &Expr) {
+        if let Some(found) = self.expr_type(expr) {
+            if found != "bool" {
+                let err_str = format!("Expected a bool, found {}", found);
+                self.diagnostic.type_err(&err_str, &expr.span());
+            }
+        }
+    }
+
+    /// Check the value of an attribute. Bare words, numbers and strings are written as
+    /// they are, so only the values of variables and expressions are checked.
+    fn check_attr_value(&mut self, value: &Expr) {
+        match *value {
+            Expr::Ident(..) | Expr::Number { .. } | Expr::Str { .. } => {},
+            Expr::Conditional { ref cond, ref then, ref else_, .. } => {
+                self.check_bool(cond);
+                self.check_attr_value(then);
+
+                if let Some(ref else_) = *else_ {
+                    self.check_attr_value(else_);
+                }
+            },
+            _ => {
+                self.expr_type(value);
+            }
+        }
+    }
+
+    /// Work out the type of an expression, reporting any problems found in it. Returns
+    /// None if the type can't be known before generation.
+    fn expr_type(&mut self, expr: &Expr) -> Option<String> {
+        match *expr {
+            Expr::Number { val: Number::Int(_), .. } => Some("int".to_owned()),
+            Expr::Number { val: Number::Float(_), .. } => Some("float".to_owned()),
+            Expr::Str { .. } => Some("string".to_owned()),
+            Expr::InterpolatedString { ref parts, .. } => {
+                for part in parts {
+                    self.expr_type(part);
+                }
+
+                Some("string".to_owned())
+            },
+            Expr::Ident(ref ident) => {
+                match ident.name.as_ref() {
+                    "true" | "false" => Some("bool".to_owned()),
+                    _ => self.var_type(ident)
+                }
+            },
+            Expr::Var(ref ident) => self.var_type(ident),
+            Expr::Interpolation { expr: ref inner, .. } => self.expr_type(inner),
+            Expr::Raw { ref value, .. } => self.expr_type(value),
+            Expr::Unary { op: UnaryOp::Not, ref operand, .. } => {
+                self.check_bool(operand);
+                Some("bool".to_owned())
+            },
+            Expr::Unary { op: UnaryOp::Negate, ref operand, span } => {
+                match self.expr_type(operand) {
+                    Some(ref found) if !is_number(found) => {
+                        let err_str = format!("Cannot negate {}", found);
+                        self.diagnostic.type_err(&err_str, &span);
+                        None
+                    },
+                    found => found
+                }
+            },
+            Expr::Binary { op: BinaryOp::And, ref lhs, ref rhs, .. }
+            | Expr::Binary { op: BinaryOp::Or, ref lhs, ref rhs, .. } => {
+                self.check_bool(lhs);
+                self.check_bool(rhs);
+                Some("bool".to_owned())
+            },
+            Expr::Binary { op, ref lhs, ref rhs, span } if op.is_comparison() => {
+                self.check_comparison(op, lhs, rhs, span);
+                Some("bool".to_owned())
+            },
+            Expr::Binary { op, ref lhs, ref rhs, span } => {
+                // Both sides are checked, even if the type of one can't be known.
+                let lhs_type = self.expr_type(lhs);
+                let rhs_type = self.expr_type(rhs);
+
+                match (lhs_type?.as_ref(), rhs_type?.as_ref()) {
+                    ("int", "int") => Some("int".to_owned()),
+                    ("string", "string") if op == BinaryOp::Plus => Some("string".to_owned()),
+                    (l, r) if is_number(l) && is_number(r) => Some("float".to_owned()),
+                    (l, r) => {
+                        let err_str = format!("Cannot apply {:?} to {} and {}", op, l, r);
+                        self.diagnostic.type_err(&err_str, &span);
+                        None
+                    }
+                }
+            },
+            Expr::Conditional { ref cond, ref then, ref else_, .. } => {
+                self.check_bool(cond);
+                let then_type = self.expr_type(then);
+
+                match *else_ {
+                    Some(ref else_) if self.expr_type(else_) == then_type => then_type,
+                    _ => None
+                }
+            },
+            Expr::Error { .. } => None
+        }
+    }
+
+    /// Check that both sides of a comparison can be compared. Numbers can be compared with
+    /// each other, and strings with strings. Values of any other types are only ever equal
+    /// to values of the same type.
+    fn check_comparison(&mut self, op: BinaryOp, lhs: &Expr, rhs: &Expr, span: Span) {
+        let lhs_type = self.expr_type(lhs);
+        let rhs_type = self.expr_type(rhs);
+
+        let (lhs_type, rhs_type) = match (lhs_type, rhs_type) {
+            (Some(l), Some(r)) => (l, r),
+            _ => return
+        };
+
+        let is_equality = op == BinaryOp::EqualsEquals || op == BinaryOp::NotEquals;
+        let can_compare = (is_number(&lhs_type) && is_number(&rhs_type)) ||
+            (lhs_type == rhs_type && (is_equality || lhs_type == "string"));
+
+        if !can_compare {
+            let err_str = format!("Cannot compare {} with {}", lhs_type, rhs_type);
+            let report = self.diagnostic.type_err(&err_str, &span);
+
+            if is_equality {
+                report.with_note("values of different types are never equal");
+            }
+        }
+    }
+
+    /// Look up the type of a variable, or of a path into one such as 'user.name'. A
+    /// variable that isn't defined is reported.
+    fn var_type(&mut self, ident: &Ident) -> Option<String> {
+        if let Some(local) = self.local_type(&ident.name) {
+            return local;
+        }
+
+        match self.globals.lookup(&ident.name) {
+            Ok(&Value::Null) => None,
+            Ok(value) => Some(value.type_name().to_owned()),
+            Err(err) => {
+                self.diagnostic.type_err(&err, &ident.span);
+                None
+            }
+        }
+    }
+
+    /// Look up a path in the variables declared in the template. Returns None if the
+    /// variable isn't declared in the template, or Some with the type of the path if it
+    /// is. Only the type of the variable itself is known, not of the values inside it.
+    fn local_type(&self, path: &str) -> Option<Option<String>> {
+        let name_end = path.find(['.', '[']).unwrap_or(path.len());
+        let (name, rest) = path.split_at(name_end);
+
+        self.scopes.iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .map(|found| if rest.is_empty() { Some(found.clone()) } else { None })
+    }
+
+    fn is_defined(&mut self, name: &str) -> bool {
+        self.local_type(name).is_some() || self.globals.get(name.to_owned()).is_some()
+    }
+}
+
+impl Visitor for TypeChecker {
+    fn visit_node(&mut self, node: &Node) {
+        match *node {
+            Node::Let { ref var, var_type, ref value, .. } => self.check_let(var, var_type, value),
+            Node::For { ref var, var_type, ref iter, ref body, .. } => {
+                self.check_for(var, var_type, iter, body)
+            },
+            Node::If { ref cond, ref then, ref else_, .. } => {
+                self.check_bool(cond);
+                self.visit_block(then);
+
+                if let Some(ref else_branch) = *else_ {
+                    self.visit_else(else_branch);
+                }
+            },
+            _ => walk_node(self, node)
+        }
+    }
+
+    fn visit_else(&mut self, else_branch: &Else) {
+        match *else_branch {
+            Else::If { ref cond, ref then, ref else_, .. } => {
+                self.check_bool(cond);
+                self.visit_block(then);
+
+                if let Some(ref else_branch) = *else_ {
+                    self.visit_else(else_branch);
+                }
+            },
+            Else::Block { ref block, .. } => walk_block(self, block)
+        }
+    }
+
+    fn visit_attr(&mut self, attr: &Attr) {
+        if let Attr::Named { value: Some(ref value), .. } = *attr {
+            self.check_attr_value(value);
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        self.expr_type(expr);
+    }
+}
+
+fn is_number(type_name: &str) -> bool {
+    type_name == "int" || type_name == "float"
+}
+
+/// Checks if a value of the given type can be stored in a variable declared with another
+/// type. An int can also be stored as a float.
+fn matches_type(found: &str, declared: VarType) -> bool {
+    found == declared.name() || (found == "int" && declared == VarType::Float)
+}
@@ -0,0 +1,75 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use syntax::ast::VarType;
+
+/// The value of a variable. Values come from evaluating expressions in a template,
+/// or from the json config file.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Value {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Str(String),
+    List(Vec<Value>),
+    Map(BTreeMap<String, Value>),
+    Null
+}
+
+impl Value {
+    /// Name of the type of this value, matching the names used in declarations.
+    pub fn type_name(&self) -> &'static str {
+        match *self {
+            Value::Int(_) => "int",
+            Value::Float(_) => "float",
+            Value::Bool(_) => "bool",
+            Value::Str(_) => "string",
+            Value::List(_) => "list",
+            Value::Map(_) => "map",
+            Value::Null => "null"
+        }
+    }
+
+    /// Checks if this value can be stored in a variable declared with the given type.
+    /// An int can also be stored as a float, and null can be stored as any type.
+    pub fn matches_type(&self, declared: VarType) -> bool {
+        match (self, declared) {
+            (&Value::Null, _) => true,
+            (&Value::Int(_), VarType::Float) => true,
+            _ => self.type_name() == declared.name()
+        }
+    }
+}
+
+/// Values are displayed as they should appear in the generated html. Null is
+/// displayed as nothing at all.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::Int(i) => write!(f, "{}", i),
+            Value::Float(x) => write!(f, "{}", x),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Str(ref s) => write!(f, "{}", s),
+            Value::List(ref items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            },
+            Value::Map(ref map) => {
+                write!(f, "{{")?;
+                for (i, (key, item)) in map.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", key, item)?;
+                }
+                write!(f, "}}")
+            },
+            Value::Null => Ok(())
+        }
+    }
+}
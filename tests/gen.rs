@@ -1,124 +1,671 @@
 extern crate tank;
 
+use std::env;
+use std::fs;
+use std::collections::BTreeMap;
 use std::path::Path;
 use std::fs::File;
-use std::io::Read;
-use std::error::Error;
+use std::io::{Read, Write};
+use tank::syntax::parser::Parser;
 use tank::syntax::symbol_table::SymbolTable;
-use tank::syntax::ast::{AstType, Ast};
+use tank::syntax::value::Value;
+use tank::syntax::ast::{walk_node_mut, Expr, Ident, MutVisitor, Node, Number, Template, VarType};
+use tank::syntax::span::Span;
 use tank::generate::gen::Gen;
+use tank::compile::compiler::Compiler;
 use tank::error::error_traits::Diagnostic;
+use tank::error::tank_err::TankError;
 
-const OUT_FILENAME: &'static str = "tests/gen_test_output";
-
-fn setup_gen(out_filename: &String) -> Gen {
+fn setup_gen() -> Gen<Vec<u8>> {
     let symbol_table = SymbolTable::new();
 
-    Gen::new(out_filename, symbol_table)
+    Gen::from_writer(Vec::new(), symbol_table)
+}
+
+/// Render the given template source in memory, returning the generated html.
+fn render(source: &str) -> String {
+    match Compiler::render_to_string(source, &BTreeMap::new()) {
+        Ok(html) => html,
+        Err(errors) => panic!("Failed to render template: {:?}", errors)
+    }
+}
+
+/// Same as render(), but starting from a symbol table with some variables already
+/// in it, as if they were read from a config file.
+fn render_with_table(source: &str, symbol_table: SymbolTable) -> String {
+    let mut parser = Parser::new(source.to_owned(), symbol_table);
+    parser.parse();
+    assert!(!parser.diagnostic.is_err());
+
+    let mut gen = Gen::from_writer(Vec::new(), parser.symbol_table.clone());
+    gen.output(&parser.root);
+    assert!(!gen.diagnostic.is_err());
+
+    String::from_utf8(gen.into_writer().unwrap()).unwrap()
 }
 
-fn open_gen_output_file() -> String {
-    let full_filename = OUT_FILENAME.to_owned() + ".html";
+/// Write the template and json config to temporary files, and compile them in the same
+/// way as running tank with a config file. Returns the generated html.
+fn render_with_config(source: &str, config: &str, name: &str) -> String {
+    let dir = env::temp_dir();
+    let template_path = dir.join("tank_gen_test_".to_owned() + name + ".tank");
+    let config_path = dir.join("tank_gen_test_".to_owned() + name + ".json");
+
+    File::create(&template_path).unwrap().write_all(source.as_bytes()).unwrap();
+    File::create(&config_path).unwrap().write_all(config.as_bytes()).unwrap();
+
+    let template_filename = template_path.to_str().unwrap().to_owned();
+    {
+        let mut template_file = File::open(&template_path).unwrap();
+        let mut config_file = File::open(&config_path).unwrap();
+        let mut compiler = Compiler::from_config_file(&mut template_file,
+                                                      &template_filename,
+                                                      &mut config_file).unwrap();
+        compiler.compile().unwrap();
+    }
+
+    read_file(&(template_filename + ".html"))
+}
 
-    let path = Path::new(&full_filename);
+fn read_file(full_filename: &str) -> String {
+    let path = Path::new(full_filename);
     let display = path.display();
 
-    let mut file = match File::open(&path) {
-        Err(error) => panic!("Failed to open {}: {}", display, Error::description(&error)),
+    let mut file = match File::open(path) {
+        Err(error) => panic!("Failed to open {}: {}", display, error),
         Ok(file) => file
     };
 
     let mut file_contents = String::new();
-    match file.read_to_string(&mut file_contents) {
-        Err(error) => panic!("Failed to read {}: {}", display, Error::description(&error)),
-        Ok(_) => ()
-    };
+    if let Err(error) = file.read_to_string(&mut file_contents) {
+        panic!("Failed to read {}: {}", display, error);
+    }
 
     file_contents
 }
 
 #[test]
-fn test_output_invalid_ast_type() {
-    let mut gen = setup_gen(&OUT_FILENAME.to_owned());
-    let invalid_ast = Ast::new(AstType::Eof);
+fn test_output_no_children_in_ast() {
+    let mut gen = setup_gen();
 
-    gen.output(&invalid_ast);
+    gen.output(&Template::default());
 
-    assert_eq!(gen.diagnostic.is_err(), true);
+    assert!(gen.diagnostic.is_err());
 }
 
 #[test]
-fn test_output_no_children_in_ast() {
-    let mut gen = setup_gen(&OUT_FILENAME.to_owned());
-    let invalid_ast = Ast::new(AstType::Template);
+fn test_output_should_write_nothing_for_assignment() {
+    let mut symbol_table = SymbolTable::new();
+    symbol_table.insert("x", "int", Span::default()).unwrap();
+
+    let mut gen = Gen::from_writer(Vec::new(), symbol_table);
+    let assignment = Node::Let {
+        var: Ident {
+            name: "x".to_owned(),
+            span: Span::default()
+        },
+        var_type: VarType::Int,
+        value: Expr::Number {
+            val: Number::Int(10),
+            span: Span::default()
+        },
+        span: Span::default()
+    };
+
+    gen.output(&Template {
+        nodes: vec![assignment]
+    });
+
+    assert!(!gen.diagnostic.is_err());
+
+    let output = String::from_utf8(gen.into_writer().unwrap()).unwrap();
+
+    assert!(output.is_empty());
+}
+
+#[test]
+fn test_output_html_comment() {
+    let output = render("// dropped\n/* also /* dropped */ */\n#! kept\ndiv() -> divContents\n");
+
+    assert!(output.starts_with("<!-- kept -->\n<div>\n"));
+    assert!(!output.contains("dropped"));
+}
+
+#[test]
+fn test_output_html_comment_cannot_close_early() {
+    let output = render("#! end --> <script>alert(1)</script>\ndiv() -> divContents\n");
+
+    assert!(output.starts_with("<!-- end - -> <script>alert(1)</script> -->\n<div>\n"), "{}", output);
+}
 
-    gen.output(&invalid_ast);
+#[test]
+fn test_output_contents_text() {
+    let source = "let name: string = tank\np () -> Hello,   world! I'm %name, 100% naïve.\n";
+    let output = render(source);
 
-    assert_eq!(gen.diagnostic.is_err(), true);
+    assert_eq!(output, "<p>\n  Hello,   world! I'm tank, 100% naïve.\n</p>\n");
 }
 
 #[test]
-#[should_panic(expected = "tank: FATAL ERROR - Invalid element found, no children present in ast")]
-fn test_output_invalid_ast_no_element_children() {
-    let mut gen = setup_gen(&OUT_FILENAME.to_owned());
-    let mut invalid_ast = Ast::new(AstType::Template);
-    let first_child = Box::new(Ast::new(AstType::Element));
+fn test_output_source_order() {
+    let output = render("h1 () -> one\n#! two\nlet x: int = 3\nif x > 2 { h2 () -> three }\nh3 () -> four\n");
 
-    invalid_ast.children.push(first_child);
+    assert_eq!(output, "<h1>\n  one\n</h1>\n\
+                        <!-- two -->\n\
+                        <h2>\n  three\n</h2>\n\
+                        <h3>\n  four\n</h3>\n");
+}
 
-    gen.output(&invalid_ast);
+/// Template with every kind of branch, used to check which one is generated.
+fn if_chain_source(x: i64) -> String {
+    format!("let x: int = {}\n\
+             if x > 10 {{ p () -> big }}\n\
+             else if x > 5 {{ p () -> medium }}\n\
+             else {{ p () -> small }}\n", x)
 }
 
 #[test]
-#[should_panic(expected = "tank: FATAL ERROR - Invalid Element ast found, not enough children present")]
-fn test_output_invalid_ast_element_not_enough_children() {
-    let mut gen = setup_gen(&OUT_FILENAME.to_owned());
-    let mut invalid_ast = Ast::new(AstType::Template);
-    let mut first_child = Box::new(Ast::new(AstType::Element));
+fn test_output_if_branch() {
+    let output = render(&if_chain_source(20));
+
+    assert_eq!(output, "<p>\n  big\n</p>\n");
+}
 
-    first_child.children.push(Box::new(Ast::new(AstType::Element)));
-    invalid_ast.children.push(first_child);
+#[test]
+fn test_output_else_if_branch() {
+    let output = render(&if_chain_source(7));
 
-    gen.output(&invalid_ast);
+    assert_eq!(output, "<p>\n  medium\n</p>\n");
 }
 
 #[test]
-fn test_output_should_write_nothing_for_assignment() {
-    let mut gen = setup_gen(&OUT_FILENAME.to_owned());
-    let mut valid_ast = Ast::new(AstType::Template);
-    let mut first_child = Box::new(Ast::new(AstType::Element));
+fn test_output_else_branch() {
+    let output = render(&if_chain_source(1));
+
+    assert_eq!(output, "<p>\n  small\n</p>\n");
+}
+
+#[test]
+fn test_output_no_branch_taken() {
+    let source = "let x: int = 1\nif x > 10 { p () -> big } else if x > 5 { p () -> medium }\n";
+    let output = render(source);
+
+    assert!(output.is_empty());
+}
+
+#[test]
+fn test_output_logical_condition() {
+    let source = "let x: int = 3\nlet shown: bool = true\n\
+                  if !(x > 5) && (shown || x == 0) { p () -> yes } else { p () -> no }\n";
+    let output = render(source);
+
+    assert_eq!(output, "<p>\n  yes\n</p>\n");
+}
+
+#[test]
+fn test_output_logical_short_circuit() {
+    // 'missing' is never declared, so evaluating it would be an error. The type checker
+    // rejects it, so the template is generated without being checked first.
+    let source = "let x: int = 3\nif x > 1 || missing > 1 { p () -> or }\nif x < 1 && missing > 1 { p () -> and }\n";
+    let output = render_with_table(source, SymbolTable::new());
+
+    assert_eq!(output, "<p>\n  or\n</p>\n");
+}
+
+#[test]
+fn test_output_let_arithmetic() {
+    let source = "let x: int = -2 + 3 * (4 - 1) % 5\nlet y: int = x * 10 / 4\np () -> %x %y\n";
+    let output = render(source);
+
+    assert_eq!(output, "<p>\n  2 5\n</p>\n");
+}
+
+#[test]
+fn test_output_minus_without_spaces() {
+    let source = "let x: int = 5\nlet y: int = x-1\np (data-x: %{ x-2 }) -> %y %{ x-1 }\n";
+    let output = render(source);
+
+    assert_eq!(output, "<p data-x=\"3\">\n  4 4\n</p>\n");
+}
+
+#[test]
+fn test_output_interpolation() {
+    let source = "let price: int = 15\n\
+                  p (data-total: %{ price * 3 }) -> Total: %{ price * 3 - 5 }, or %{ price > 10 }\n";
+    let output = render(source);
+
+    assert_eq!(output, "<p data-total=\"45\">\n  Total: 40, or true\n</p>\n");
+}
+
+#[test]
+fn test_output_let_type_mismatch() {
+    let source = "let count: int = \"ten\"\np () -> %count\n";
+    let mut parser = Parser::new(source.to_owned(), SymbolTable::new());
+    parser.parse();
+    assert!(!parser.diagnostic.is_err());
+
+    let mut gen = Gen::from_writer(Vec::new(), parser.symbol_table.clone());
+    gen.output(&parser.root);
+
+    assert!(gen.diagnostic.is_err());
+}
+
+#[test]
+fn test_output_let_typed_values() {
+    let source = "let ratio: float = 2\nlet on: bool = ratio > 1\nlet name: string = \"tank\"\n\
+                  p () -> %{ ratio / 4 } %on %name\n";
+    let output = render(source);
+
+    assert_eq!(output, "<p>\n  0.5 true tank\n</p>\n");
+}
+
+#[test]
+fn test_output_let_bare_word_before_declaration() {
+    // 'later' isn't declared yet where it is used, so it is the string "later".
+    let source = "let name: string = later\nlet later: string = \"x\"\np () -> [%name] [%later]\n";
+    let output = render_with_table(source, SymbolTable::new());
+
+    assert_eq!(output, "<p>\n  [later] [x]\n</p>\n");
+}
+
+#[test]
+fn test_output_float_literals() {
+    let source = "let ratio: float = 1.5\np (data-ratio: %{ ratio + 0.25 }) -> %ratio %{ ratio > 1.25 }\n";
+    let output = render(source);
+
+    assert_eq!(output, "<p data-ratio=\"1.75\">\n  1.5 true\n</p>\n");
+}
+
+fn fruit_table() -> SymbolTable {
+    let mut values = BTreeMap::new();
+    let fruits = vec!["apple", "pear", "plum"].into_iter()
+        .map(|fruit| Value::Str(fruit.to_owned()))
+        .collect();
+    values.insert("fruits".to_owned(), Value::List(fruits));
+
+    SymbolTable::from_existing_map(&values)
+}
+
+#[test]
+fn test_output_text_after_index() {
+    let output = render_with_table("p () -> %fruits[0]\u{e9} %fruits[1]x\n", fruit_table());
+
+    assert_eq!(output, "<p>\n  apple\u{e9} pearx\n</p>\n");
+}
+
+#[test]
+fn test_lookup_malformed_path() {
+    let mut table = fruit_table();
+
+    for path in &["fruits[0]x", "fruits[0]\u{e9}", "fruits[0", "fruits.", "fruits[]", ".fruits"] {
+        assert_eq!(table.lookup(path), Err(format!("'{}' is not a valid path", path)));
+    }
+}
+
+#[test]
+fn test_output_for_loop() {
+    let source = "for fruit: string in fruits {\n    li () -> %fruit\n}\np () -> done\n";
+    let output = render_with_table(source, fruit_table());
+
+    assert_eq!(output, "<li>\n  apple\n</li>\n<li>\n  pear\n</li>\n<li>\n  plum\n</li>\n<p>\n  done\n</p>\n");
+}
+
+#[test]
+fn test_output_for_loop_helpers() {
+    let source = "for fruit: string in fruits {\n    \
+                  li (data-index: %{ loop.index }) -> %fruit %loop.first %loop.last\n}\n";
+    let output = render_with_table(source, fruit_table());
+
+    assert_eq!(output, "<li data-index=\"0\">\n  apple true false\n</li>\n\
+                        <li data-index=\"1\">\n  pear false false\n</li>\n\
+                        <li data-index=\"2\">\n  plum false true\n</li>\n");
+}
+
+#[test]
+fn test_output_for_loop_scope() {
+    let source = "for fruit: string in fruits { li () -> %fruit }\np () -> %fruit\n";
+    let mut parser = Parser::new(source.to_owned(), fruit_table());
+    parser.parse();
+    assert!(!parser.diagnostic.is_err());
+
+    let mut gen = Gen::from_writer(Vec::new(), parser.symbol_table.clone());
+    gen.output(&parser.root);
+
+    // The loop variable can't be used after the loop.
+    assert!(gen.diagnostic.is_err());
+}
+
+#[test]
+fn test_output_for_loop_from_config() {
+    let output = render_with_config("for n: int in numbers { li () -> %{ n * 2 } }\np () -> %title\n",
+                                    "{ \"title\": \"Doubled\", \"numbers\": [1, 2, 3] }",
+                                    "for_config");
+
+    assert_eq!(output, "<li>\n  2\n</li>\n<li>\n  4\n</li>\n<li>\n  6\n</li>\n<p>\n  Doubled\n</p>\n");
+}
+
+#[test]
+fn test_output_typed_config_values() {
+    let output = render_with_config("if enabled { p () -> %{ ratio * 2 } %user %nothing }\n",
+                                    "{ \"enabled\": true, \"ratio\": 1.25, \"user\": { \"name\": \"Ann\" }, \"nothing\": null }",
+                                    "typed_config");
+
+    assert_eq!(output, "<p>\n  2.5 {name: Ann} \n</p>\n");
+}
+
+const NESTED_CONFIG: &str = "{ \"site\": { \"title\": \"Blog\", \"tags\": [\"rust\", \"html\"] }, \
+                             \"posts\": [{ \"author\": \"Ann\", \"draft\": false }, \
+                                       { \"author\": \"Bob\", \"draft\": true }] }";
+
+#[test]
+fn test_output_nested_config_paths() {
+    let source = "h1 (title: %site.title) -> %site.title, by %posts[0].author. Tagged %site.tags[1]\n\
+                  if posts[1].draft && site.tags[0] == \"rust\" { p () -> %{ posts[1].author } }\n";
+    let output = render_with_config(source, NESTED_CONFIG, "nested_config");
+
+    assert_eq!(output, "<h1 title=\"Blog\">\n  Blog, by Ann. Tagged html\n</h1>\n<p>\n  Bob\n</p>\n");
+}
+
+#[test]
+fn test_output_for_loop_over_nested_list() {
+    let source = "for post: map in posts { li () -> %post.author %loop.index }\n";
+    let output = render_with_config(source, NESTED_CONFIG, "nested_for");
+
+    assert_eq!(output, "<li>\n  Ann 0\n</li>\n<li>\n  Bob 1\n</li>\n");
+}
+
+#[test]
+fn test_output_missing_path_segment() {
+    for source in &["p () -> %site.titel\n", "p () -> %posts[2].author\n", "if site.tags.first { p () -> x }\n"] {
+        let mut parser = Parser::new(source.to_string(), nested_table());
+        parser.parse();
+        assert!(!parser.diagnostic.is_err());
+
+        let mut gen = Gen::from_writer(Vec::new(), parser.symbol_table.clone());
+        gen.output(&parser.root);
+
+        assert!(gen.diagnostic.is_err(), "expected an error for {:?}", source);
+    }
+}
 
-    first_child.children.push(Box::new(Ast::new(AstType::AssignExpr)));
-    valid_ast.children.push(first_child);
+fn nested_table() -> SymbolTable {
+    let mut site = BTreeMap::new();
+    site.insert("title".to_owned(), Value::Str("Blog".to_owned()));
+    site.insert("tags".to_owned(), Value::List(vec![Value::Str("rust".to_owned())]));
 
-    gen.output(&valid_ast);
+    let mut post = BTreeMap::new();
+    post.insert("author".to_owned(), Value::Str("Ann".to_owned()));
 
-    let file_contents = open_gen_output_file();
+    let mut values = BTreeMap::new();
+    values.insert("site".to_owned(), Value::Map(site));
+    values.insert("posts".to_owned(), Value::List(vec![Value::Map(post)]));
 
-    assert!(file_contents.is_empty());
+    SymbolTable::from_existing_map(&values)
 }
 
+const UNSAFE_CONFIG: &str = "{ \"bio\": \"<script>alert('hi')</script> & \\\"friends\\\"\", \
+                              \"link\": \"<b>bold</b>\" }";
+
 #[test]
-#[should_panic(expected = "tank: FATAL ERROR - Invalid ast found, no children for if expression")]
-fn test_output_invalid_if_expr() {
-    let mut gen = setup_gen(&OUT_FILENAME.to_owned());
-    let mut invalid_ast = Ast::new(AstType::Template);
-    let first_child = Box::new(Ast::new(AstType::IfExpr));
+fn test_output_escapes_values() {
+    let source = "p (title: %bio data-x: %{ bio }) -> %bio\n";
+    let output = render_with_config(source, UNSAFE_CONFIG, "escapes_values");
+
+    assert_eq!(output, "<p title=\"&lt;script&gt;alert(&#39;hi&#39;)&lt;/script&gt; &amp; &quot;friends&quot;\" \
+                        data-x=\"&lt;script&gt;alert(&#39;hi&#39;)&lt;/script&gt; &amp; &quot;friends&quot;\">\n  \
+                        &lt;script&gt;alert('hi')&lt;/script&gt; &amp; \"friends\"\n</p>\n");
+}
 
-    invalid_ast.children.push(first_child);
+#[test]
+fn test_output_escapes_literal_attributes() {
+    let source = "a (title: 'Say \"hi\" & <bye>' href: \"/?a=%{ 1 }&b='2'\") -> \"Say \\\"hi\\\" & bye\"\n";
+    let output = render(source);
 
-    gen.output(&invalid_ast);
+    // Literal text in contents is still written as it is.
+    assert_eq!(output, "<a title=\"Say &quot;hi&quot; &amp; &lt;bye&gt;\" href=\"/?a=1&amp;b=&#39;2&#39;\">\n  \
+                        Say \"hi\" & bye\n</a>\n");
 }
 
 #[test]
-#[should_panic(expected = "tank: FATAL ERROR - Invalid expression found, not enough children in if expression")]
-fn test_output_invalid_if_expr_not_enough_children() {
-    let mut gen = setup_gen(&OUT_FILENAME.to_owned());
-    let mut invalid_ast = Ast::new(AstType::Template);
-    let mut first_child = Box::new(Ast::new(AstType::IfExpr));
+fn test_output_raw_values() {
+    let source = "p () -> <i>kept</i> %!link %!{ link } %link\n";
+    let output = render_with_config(source, UNSAFE_CONFIG, "raw_values");
+
+    assert_eq!(output, "<p>\n  <i>kept</i> <b>bold</b> <b>bold</b> &lt;b&gt;bold&lt;/b&gt;\n</p>\n");
+}
 
-    first_child.children.push(Box::new(Ast::new(AstType::AssignExpr)));
-    invalid_ast.children.push(first_child);
+/// Renames every element with one name to another.
+struct Rename(&'static str, &'static str);
+
+impl MutVisitor for Rename {
+    fn visit_node_mut(&mut self, node: &mut Node) {
+        if let Node::Element { ref mut name, .. } = *node {
+            if name.name == self.0 {
+                name.name = self.1.to_owned();
+            }
+        }
+
+        walk_node_mut(self, node);
+    }
+}
+
+#[test]
+fn test_output_after_mut_visitor() {
+    let source = "div () -> {\n    b () -> bold\n    if true {\n        b () -> nested\n    }\n}\n";
+    let mut parser = Parser::new(source.to_owned(), SymbolTable::new());
+    parser.parse();
+    assert!(!parser.diagnostic.is_err());
+
+    Rename("b", "strong").visit_template_mut(&mut parser.root);
+
+    let mut gen = Gen::from_writer(Vec::new(), parser.symbol_table.clone());
+    gen.output(&parser.root);
+    assert!(!gen.diagnostic.is_err());
+
+    let output = String::from_utf8(gen.into_writer().unwrap()).unwrap();
+    assert_eq!(output, "<div>\n  <strong>\n    bold\n  </strong>\n  <strong>\n    nested\n  </strong>\n</div>\n");
+}
+
+#[test]
+fn test_output_sibling_children() {
+    let source = "div () -> { p () -> a  p () -> b }\np () -> after\n";
+    let output = render(source);
 
-    gen.output(&invalid_ast);
+    assert_eq!(output, "<div>\n  <p>\n    a\n  </p>\n  <p>\n    b\n  </p>\n</div>\n<p>\n  after\n</p>\n");
+}
+
+#[test]
+fn test_output_mixed_children() {
+    let source = "let n: int = 2\n\
+                  main () -> {\n    \
+                      h1 () -> Title\n    \
+                      if n > 1 { p () -> many  p () -> items }\n    \
+                      #! list\n    \
+                      ul () -> { li () -> one  li () -> two }\n    \
+                      footer () -> end\n\
+                  }\n";
+    let output = render(source);
+
+    assert_eq!(output, "<main>\n  <h1>\n    Title\n  </h1>\n  <p>\n    many\n  </p>\n  <p>\n    items\n  </p>\n  \
+                        <!-- list -->\n  <ul>\n    <li>\n      one\n    </li>\n    <li>\n      two\n    </li>\n  </ul>\n  \
+                        <footer>\n    end\n  </footer>\n</main>\n");
+}
+
+#[test]
+fn test_output_deep_tree() {
+    const DEPTH: usize = 12;
+
+    // Alternate between a nested element and a braced block at each level, with
+    // a sibling after the block so that every level closes at the right point.
+    let mut source = String::new();
+    let mut expected = String::new();
+    for level in 0..DEPTH {
+        let indent = " ".repeat(level * 2);
+        source += &format!("d{} () -> {{\n", level);
+        expected += &format!("{}<d{}>\n", indent, level);
+    }
+    source += "p () -> leaf\n";
+    expected += &format!("{}<p>\n{}  leaf\n{}</p>\n", " ".repeat(DEPTH * 2), " ".repeat(DEPTH * 2), " ".repeat(DEPTH * 2));
+    for level in (0..DEPTH).rev() {
+        let indent = " ".repeat(level * 2);
+        source += &format!("}}\ns{} () -> after\n", level);
+        expected += &format!("{}</d{}>\n", indent, level);
+        if level > 0 {
+            expected += &format!("{}<s{}>\n{}  after\n{}</s{}>\n", indent, level, indent, indent, level);
+        }
+    }
+    expected += "<s0>\n  after\n</s0>\n";
+
+    let output = render(&source);
+
+    assert_eq!(output, expected);
+}
+
+#[test]
+fn test_output_wide_tree() {
+    const WIDTH: usize = 50;
+
+    let mut source = String::from("ul () -> {\n");
+    let mut expected = String::from("<ul>\n");
+    for i in 0..WIDTH {
+        source += &format!("    li () -> item {}\n", i);
+        expected += &format!("  <li>\n    item {}\n  </li>\n", i);
+    }
+    source += "}\np () -> done\n";
+    expected += "</ul>\n<p>\n  done\n</p>\n";
+
+    let output = render(&source);
+
+    assert_eq!(output, expected);
+}
+
+#[test]
+fn test_output_void_elements() {
+    let source = "form () -> {\n    input (type: text name: q);\n    br ()\n    button () -> Go\n}\n\
+                  svg () -> circle (r: 5);\nhr ()\n";
+    let output = render(source);
+
+    assert_eq!(output, "<form>\n  <input type=\"text\" name=\"q\">\n  <br>\n  <button>\n    Go\n  </button>\n</form>\n\
+                        <svg>\n  <circle r=\"5\" />\n</svg>\n<hr>\n");
+}
+
+#[test]
+fn test_output_boolean_attributes() {
+    let source = "let isDefault: bool = true\nlet count: int = 1\n\
+                  input (type: checkbox checked: %isDefault required disabled: %{ count > 2 } defer);\n\
+                  div (hidden: %{ !isDefault } aria-hidden: %isDefault data-open: %{ count > 2 }) -> x\n";
+    let output = render(source);
+
+    assert_eq!(output, "<input type=\"checkbox\" checked required defer>\n\
+                        <div aria-hidden=\"true\" data-open=\"false\">\n  x\n</div>\n");
+}
+
+#[test]
+fn test_output_boolean_attribute_literals() {
+    let source = "let flag: bool = true\n\
+                  input (checked: false required: true disabled: !%flag hidden: !%{ false });\n\
+                  div (aria-hidden: false data-open: !%flag) -> x\n";
+    let output = render(source);
+
+    assert_eq!(output, "<input required hidden>\n<div aria-hidden=\"false\" data-open=\"false\">\n  x\n</div>\n");
+}
+
+#[test]
+fn test_output_attribute_expressions() {
+    let source = "let baseUrl: string = \"/posts?page=1&sort=new\"\nlet id: int = 7\n\
+                  let active: bool = true\nlet name: string = \"<b>\"\n\
+                  a (href: %baseUrl title: \"Post %{ id + 1 } by %{ name }\") -> x\n\
+                  li (class: if %active \"on\" else \"off\" id: if %{ id > 10 } \"big\") -> y\n\
+                  li (class: if %{ !active } \"on\" else \"off\") -> z\n";
+    let output = render(source);
+
+    assert_eq!(output, "<a href=\"/posts?page=1&amp;sort=new\" title=\"Post 8 by &lt;b&gt;\">\n  x\n</a>\n\
+                        <li class=\"on\">\n  y\n</li>\n\
+                        <li class=\"off\">\n  z\n</li>\n");
+}
+
+#[test]
+fn test_output_interpolated_string_contents() {
+    let source = "let count: int = 3\np () -> \"%{ count } items, 100\\%{ off }\"\n";
+    let output = render(source);
+
+    assert_eq!(output, "<p>\n  3 items, 100%{ off }\n</p>\n");
+}
+
+#[test]
+fn test_output_doctype_and_raw_block() {
+    let source = "doctype html\nhtml () -> {\n    raw {\n<svg><path d=\"M0 0\"/></svg>\n    }\n    \
+                  p () -> after\n}\nraw #{ <script>if (a) {</script> }#\n";
+    let output = render(source);
+
+    assert_eq!(output, "<!DOCTYPE html>\n<html>\n<svg><path d=\"M0 0\"/></svg>\n  <p>\n    after\n  </p>\n</html>\n\
+                        \x20<script>if (a) {</script> \n");
+}
+
+#[test]
+fn test_render_to_string_with_context() {
+    let mut context = BTreeMap::new();
+    context.insert("title".to_owned(), Value::Str("Home & away".to_owned()));
+    context.insert("count".to_owned(), Value::Int(2));
+
+    let output = Compiler::render_to_string("h1 () -> %title\np () -> %{ count * 2 }\n", &context);
+
+    assert_eq!(output.unwrap(), "<h1>\n  Home &amp; away\n</h1>\n<p>\n  4\n</p>\n");
+}
+
+#[test]
+fn test_render_to_string_errors() {
+    match Compiler::render_to_string("p (id: ) -> x\n", &BTreeMap::new()) {
+        Err(TankError::Parse(errors)) => {
+            assert_eq!(errors[0].to_string(), "error: Unexpected token \")\" found\n \
+                                               --> <string>:1:8\n  \
+                                               |\n\
+                                               1 | p (id: ) -> x\n  \
+                                               |        ^");
+        },
+        result => panic!("Expected a parse error, found {:?}", result)
+    }
+
+    match Compiler::render_to_string("let count: int = \"ten\"\np () -> %count\n", &BTreeMap::new()) {
+        Err(TankError::Type(errors)) => assert!(errors[0].message.contains("'count' is declared as int")),
+        result => panic!("Expected a type error, found {:?}", result)
+    }
+
+    // Undefined variables are found by the type checker, before anything is generated.
+    match Compiler::render_to_string("p () -> %missing\n", &BTreeMap::new()) {
+        Err(TankError::Type(errors)) => assert!(errors[0].message.contains("'missing' is not defined")),
+        result => panic!("Expected a type error, found {:?}", result)
+    }
+}
+
+#[test]
+fn test_compile_errors() {
+    let template_path = env::temp_dir().join("tank_gen_test_bad_config.tank");
+    let config_path = env::temp_dir().join("tank_gen_test_bad_config.json");
+    File::create(&template_path).unwrap().write_all(b"p () -> x\n").unwrap();
+    File::create(&config_path).unwrap().write_all(b"[1, 2]").unwrap();
+
+    let mut template_file = File::open(&template_path).unwrap();
+    let mut config_file = File::open(&config_path).unwrap();
+    let result = Compiler::from_config_file(&mut template_file,
+                                            &template_path.to_str().unwrap().to_owned(),
+                                            &mut config_file);
+    assert!(matches!(result, Err(TankError::Config(_))));
+
+    // Nothing is written for a template that fails part way through generating.
+    let failing_path = env::temp_dir().join("tank_gen_test_failing.tank");
+    let html_path = env::temp_dir().join("tank_gen_test_failing.tank.html");
+    File::create(&failing_path).unwrap().write_all(b"p () -> before\np () -> %{ 1 / 0 }\n").unwrap();
+    let _ = fs::remove_file(&html_path);
+
+    let mut failing_file = File::open(&failing_path).unwrap();
+    let mut compiler = Compiler::new(&mut failing_file, &failing_path.to_str().unwrap().to_owned()).unwrap();
+    assert!(matches!(compiler.compile(), Err(TankError::Gen(_))));
+    assert!(!html_path.exists());
+
+    // A missing include is reported, rather than stopping the whole process.
+    match Compiler::render_to_string("&tank_gen_test_missing_include\n", &BTreeMap::new()) {
+        Err(TankError::Gen(errors)) => assert!(errors[0].message.contains("Unable to open file")),
+        result => panic!("Expected a generation error, found {:?}", result)
+    }
 }
@@ -0,0 +1,132 @@
+extern crate tank;
+
+use std::time::{Duration, Instant};
+use tank::syntax::lexer::Lexer;
+use tank::syntax::token::TokenType;
+
+const SMALL_SIZE: usize = 1024 * 1024;
+const LARGE_SIZE: usize = 4 * SMALL_SIZE;
+const RUNS: usize = 3;
+
+/// A chunk of template covering most of the lexer: comments, string literals,
+/// operators, nested elements and text with interpolation.
+const CHUNK: &str = "// A line comment
+/* A block /* nested */ comment */
+#! An html comment
+div (id: \"item-list\" class: card data-index: 10) ->
+    p () -> Some text, with punctuation! %name and &amp; more \\% text
+if x >= 10 { span (title: 'caf\\u{e9}') -> big }
+";
+
+/// A single line of text in which every word could start an element, but none
+/// of them does, since the '(' is never closed.
+const LONG_LINE_START: &str = "p () -> ";
+const LONG_LINE_CHUNK: &str = "a (b ";
+
+/// Build a template of at least `size` bytes by repeating a chunk.
+fn build_template(start: &str, chunk: &str, size: usize) -> String {
+    let mut template = String::with_capacity(size + chunk.len());
+    template.push_str(start);
+
+    while template.len() < size {
+        template.push_str(chunk);
+    }
+
+    template
+}
+
+/// Lex every token in the template, switching to text mode after an arrow in
+/// the same way the parser does. Returns the number of tokens lexed.
+fn lex_template(template: String) -> usize {
+    let mut lexer = Lexer::new(template);
+    let mut count = 0;
+
+    loop {
+        lexer.lex();
+        count += 1;
+
+        match lexer.curr_tok.as_ref().unwrap().tok_type {
+            TokenType::Eof => break,
+            TokenType::Error => panic!("Unexpected error token: {:?}", lexer.curr_tok),
+            TokenType::Arrow => {
+                lexer.skip_whitespace();
+                if lexer.at_element_start() {
+                    continue;
+                }
+
+                loop {
+                    lexer.lex_text();
+                    count += 1;
+
+                    match lexer.curr_tok.as_ref().unwrap().tok_type {
+                        TokenType::Eof => break,
+                        TokenType::Percent | TokenType::Ampersand => {
+                            lexer.lex();
+                        },
+                        _ => ()
+                    }
+                }
+            },
+            _ => ()
+        }
+    }
+
+    count
+}
+
+/// Time how long it takes to lex a template of the given size, taking the
+/// fastest of a few runs to reduce noise.
+fn time_lex(start: &str, chunk: &str, size: usize) -> (Duration, usize) {
+    let mut fastest = Duration::MAX;
+    let mut count = 0;
+
+    for _ in 0..RUNS {
+        let template = build_template(start, chunk, size);
+
+        let start = Instant::now();
+        count = lex_template(template);
+        let elapsed = start.elapsed();
+
+        if elapsed < fastest {
+            fastest = elapsed;
+        }
+    }
+
+    (fastest, count)
+}
+
+// Lexing a few MB takes a while in a debug build, so this only runs when asked for,
+// with 'cargo test --release --test lexer_bench -- --ignored --nocapture'. The smaller
+// check below always runs.
+#[test]
+#[ignore]
+fn bench_lex_scales_linearly() {
+    let (small_time, small_count) = time_lex("", CHUNK, SMALL_SIZE);
+    let (large_time, large_count) = time_lex("", CHUNK, LARGE_SIZE);
+
+    println!("lexed {} bytes ({} tokens) in {:?}", SMALL_SIZE, small_count, small_time);
+    println!("lexed {} bytes ({} tokens) in {:?}", LARGE_SIZE, large_count, large_time);
+
+    // Sanity check that all of the larger template was lexed.
+    assert!(large_count > 3 * small_count);
+
+    // Lexing 4x the input should take roughly 4x as long. A quadratic lexer
+    // would take around 16x, so allow plenty of room for timing noise.
+    let ratio = large_time.as_secs_f64() / small_time.as_secs_f64();
+    assert!(ratio < 10.0, "lexing time grew by {:.1}x for 4x the input", ratio);
+}
+
+#[test]
+fn test_lex_long_line_scales_linearly() {
+    // Text checks for an element start after every space. If each check scanned to the
+    // end of the line again, the larger line would take around 16x as long.
+    let (small_time, _) = time_lex(LONG_LINE_START, LONG_LINE_CHUNK, SMALL_SIZE / 64);
+    let (large_time, large_count) = time_lex(LONG_LINE_START, LONG_LINE_CHUNK, SMALL_SIZE / 16);
+
+    // 'p () ->', the whole line as one text token, and the Eofs ending the text and
+    // the template.
+    assert_eq!(large_count, 7);
+
+    let ratio = large_time.as_secs_f64() / small_time.as_secs_f64();
+    assert!(ratio < 10.0, "lexing time grew by {:.1}x for 4x the input", ratio);
+}
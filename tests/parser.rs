@@ -3,27 +3,27 @@ extern crate tank;
 use std::path::Path;
 use std::fs::File;
 use std::io::Read;
-use std::error::Error;
 use tank::syntax::parser::Parser;
 use tank::syntax::symbol_table::SymbolTable;
-use tank::syntax::ast::AstType;
+use tank::syntax::ast::{walk_expr, walk_node, Attr, BinaryOp, Block, Else, Expr, Node, Number, TextPart, UnaryOp,
+                        VarType, Visitor};
+use tank::syntax::span::Span;
 use tank::error::error_traits::Diagnostic;
 
-const DIR: &'static str = "tests/parser_input/";
+const DIR: &str = "tests/parser_input/";
 
 fn setup_parser(filename: String) -> Parser {
     let path = Path::new(&filename);
     let display = path.display();
 
-    let mut file = match File::open(&path) {
-        Err(error) => panic!("Failed to open {}: {}", display, Error::description(&error)),
+    let mut file = match File::open(path) {
+        Err(error) => panic!("Failed to open {}: {}", display, error),
         Ok(file) => file
     };
 
     let mut file_contents = String::new();
-    match file.read_to_string(&mut file_contents) {
-        Err(error) => panic!("Failed to read {}: {}", display, Error::description(&error)),
-        Ok(_) => ()
+    if let Err(error) = file.read_to_string(&mut file_contents) {
+        panic!("Failed to read {}: {}", display, error);
     }
 
     let symbol_table = SymbolTable::new();
@@ -31,6 +31,122 @@ fn setup_parser(filename: String) -> Parser {
     Parser::new(file_contents, symbol_table)
 }
 
+/// Name of the variant of a node, used to check the kinds of a list of nodes at once.
+fn kind(node: &Node) -> &'static str {
+    match *node {
+        Node::Element { .. } => "Element",
+        Node::VoidElement { .. } => "VoidElement",
+        Node::Text { .. } => "Text",
+        Node::Let { .. } => "Let",
+        Node::If { .. } => "If",
+        Node::For { .. } => "For",
+        Node::Block(..) => "Block",
+        Node::Include { .. } => "Include",
+        Node::Comment { .. } => "Comment",
+        Node::Doctype { .. } => "Doctype",
+        Node::Raw { .. } => "Raw",
+        Node::Error { .. } => "Error"
+    }
+}
+
+/// Name of the variant of an expression, or "None" if there is no expression.
+fn expr_kind(expr: Option<&Expr>) -> &'static str {
+    match expr {
+        None => "None",
+        Some(&Expr::Number { .. }) => "Number",
+        Some(&Expr::Str { .. }) => "Str",
+        Some(&Expr::InterpolatedString { .. }) => "InterpolatedString",
+        Some(&Expr::Ident(..)) => "Ident",
+        Some(&Expr::Var(..)) => "Var",
+        Some(&Expr::Interpolation { .. }) => "Interpolation",
+        Some(&Expr::Raw { .. }) => "Raw",
+        Some(&Expr::Unary { .. }) => "Unary",
+        Some(&Expr::Binary { .. }) => "Binary",
+        Some(&Expr::Conditional { .. }) => "Conditional",
+        Some(&Expr::Error { .. }) => "Error"
+    }
+}
+
+/// The name of an element, or an empty string for any other node.
+fn el_name(node: &Node) -> &str {
+    match *node {
+        Node::Element { ref name, .. } | Node::VoidElement { ref name, .. } => &name.name,
+        _ => ""
+    }
+}
+
+fn el_attrs(node: &Node) -> &[Attr] {
+    match *node {
+        Node::Element { ref attrs, .. } | Node::VoidElement { ref attrs, .. } => attrs,
+        _ => panic!("Expected an element, found {:?}", node)
+    }
+}
+
+fn el_body(node: &Node) -> &[Node] {
+    match *node {
+        Node::Element { ref body, .. } => body,
+        _ => panic!("Expected an element, found {:?}", node)
+    }
+}
+
+/// The name and value of each attribute of an element, with the name of an attribute
+/// that couldn't be parsed given as "Error".
+fn attr_pairs(node: &Node) -> Vec<(&str, Option<&Expr>)> {
+    el_attrs(node).iter()
+        .map(|attr| match *attr {
+            Attr::Named { ref name, ref value } => (name.name.as_ref(), value.as_ref()),
+            Attr::Error { .. } => ("Error", None)
+        })
+        .collect()
+}
+
+/// The parts of the text contents of an element.
+fn text_parts(node: &Node) -> &[TextPart] {
+    match el_body(node).first() {
+        Some(Node::Text { parts, .. }) => parts,
+        other => panic!("Expected text contents, found {:?}", other)
+    }
+}
+
+/// The text of the first part of the contents of an element.
+fn contents(node: &Node) -> &str {
+    match text_parts(node).first() {
+        Some(TextPart::Text { text, .. }) => text,
+        other => panic!("Expected text, found {:?}", other)
+    }
+}
+
+/// The name of a variable, or an empty string for any other expression.
+fn var_name(expr: &Expr) -> &str {
+    match *expr {
+        Expr::Var(ref ident) | Expr::Ident(ref ident) => &ident.name,
+        _ => ""
+    }
+}
+
+/// The value of a number or string literal, or an empty string for any other expression.
+fn literal(expr: &Expr) -> String {
+    match *expr {
+        Expr::Number { val, .. } => val.to_string(),
+        Expr::Str { ref val, .. } => val.clone(),
+        _ => String::new()
+    }
+}
+
+fn binary(expr: &Expr) -> (BinaryOp, &Expr, &Expr) {
+    match *expr {
+        Expr::Binary { op, ref lhs, ref rhs, .. } => (op, lhs, rhs),
+        _ => panic!("Expected a binary expression, found {:?}", expr)
+    }
+}
+
+fn if_parts(node: &Node) -> (&Expr, &Block, Option<&Else>) {
+    match *node {
+        Node::If { ref cond, ref then, ref else_, .. } => (cond, then, else_.as_ref().map(|e| &**e)),
+        _ => panic!("Expected an if statement, found {:?}", node)
+    }
+}
+
 #[test]
 fn test_parse_empty_file() {
     let filename = DIR.to_owned() + "empty_file.tank";
@@ -38,7 +154,7 @@ fn test_parse_empty_file() {
 
     parser.parse();
 
-    assert_eq!(parser.diagnostic.is_err(), true);
+    assert!(parser.diagnostic.is_err());
 }
 
 #[test]
@@ -48,7 +164,7 @@ fn test_parse_if_expr_no_left_brace() {
 
     parser.parse();
 
-    assert_eq!(parser.diagnostic.is_err(), true);
+    assert!(parser.diagnostic.is_err());
 }
 
 #[test]
@@ -58,7 +174,7 @@ fn test_parse_if_expr_no_right_brace() {
 
     parser.parse();
 
-    assert_eq!(parser.diagnostic.is_err(), true);
+    assert!(parser.diagnostic.is_err());
 }
 
 #[test]
@@ -68,29 +184,201 @@ fn test_parse_if_valid_expr() {
 
     parser.parse();
 
-    assert_eq!(parser.diagnostic.is_err(), false);
-
-    // Assert that the ast root is of the correcr form.
-    let ast = parser.root;
-    assert_eq!(ast.ast_type, AstType::Template);
-    assert_eq!(ast.children.len(), 2);
+    assert!(!parser.diagnostic.is_err());
 
-    // Assert that the first child is the if expression.
-    let if_ast = &ast.children[0];
-    assert_eq!(if_ast.ast_type, AstType::IfExpr);
-    assert_eq!(if_ast.children.len(), 2);
+    // Assert that the template holds just the if statement.
+    let template = parser.root;
+    assert_eq!(template.nodes.len(), 1);
 
-    // Assert that the ast for the operator contains two terms.
-    let if_expr_ast = &if_ast.children[0];
-    assert_eq!(if_expr_ast.children.len(), 2);
+    let (cond, then, else_) = if_parts(&template.nodes[0]);
+    assert!(then.nodes.is_empty());
+    assert!(else_.is_none());
 
     // Asert that the terms are equal to those found in the test file.
-    let first_term = &if_expr_ast.children[0];
-    let second_term = &if_expr_ast.children[1];
-    assert_eq!(first_term.ast_type, AstType::Ident);
-    assert_eq!(first_term.val, "x".to_owned());
-    assert_eq!(second_term.ast_type, AstType::Number);
-    assert_eq!(second_term.val, "10".to_owned());
+    let (op, first_term, second_term) = binary(cond);
+    assert_eq!(op, BinaryOp::Gt);
+    assert_eq!(expr_kind(Some(first_term)), "Ident");
+    assert_eq!(var_name(first_term), "x");
+    assert_eq!(expr_kind(Some(second_term)), "Number");
+    assert_eq!(literal(second_term), "10");
+}
+
+#[test]
+fn test_parse_if_else() {
+    let filename = DIR.to_owned() + "if_else.tank";
+    let mut parser = setup_parser(filename);
+
+    parser.parse();
+
+    assert!(!parser.diagnostic.is_err());
+
+    let else_block = match if_parts(&parser.root.nodes[0]).2 {
+        Some(Else::Block { block, .. }) => block,
+        other => panic!("Expected an else block, found {:?}", other)
+    };
+
+    let else_element = &else_block.nodes[0];
+    assert_eq!(kind(else_element), "Element");
+    assert_eq!(contents(else_element), "small");
+}
+
+#[test]
+fn test_parse_if_else_if_chain() {
+    let filename = DIR.to_owned() + "if_else_if.tank";
+    let mut parser = setup_parser(filename);
+
+    parser.parse();
+
+    assert!(!parser.diagnostic.is_err());
+
+    // Each else-if holds the next branch.
+    let (cond, else_) = match if_parts(&parser.root.nodes[0]).2 {
+        Some(Else::If { cond, else_, .. }) => (cond, else_),
+        other => panic!("Expected an else-if branch, found {:?}", other)
+    };
+
+    let (op, _, rhs) = binary(cond);
+    assert_eq!(op, BinaryOp::Gt);
+    assert_eq!(literal(rhs), "5");
+
+    let (cond, else_) = match else_.as_ref().map(|e| &**e) {
+        Some(Else::If { cond, else_, .. }) => (cond, else_),
+        other => panic!("Expected an else-if branch, found {:?}", other)
+    };
+    assert_eq!(literal(binary(cond).2), "0");
+
+    assert!(matches!(else_.as_ref().map(|e| &**e), Some(&Else::Block { .. })));
+}
+
+#[test]
+fn test_parse_if_else_if_no_else() {
+    let filename = DIR.to_owned() + "if_else_if_no_else.tank";
+    let mut parser = setup_parser(filename);
+
+    parser.parse();
+
+    assert!(!parser.diagnostic.is_err());
+
+    match if_parts(&parser.root.nodes[0]).2 {
+        Some(Else::If { else_, .. }) => assert!(else_.is_none()),
+        other => panic!("Expected an else-if branch, found {:?}", other)
+    }
+}
+
+#[test]
+fn test_parse_else_without_if() {
+    let filename = DIR.to_owned() + "else_without_if.tank";
+    let mut parser = setup_parser(filename);
+
+    parser.parse();
+
+    assert!(parser.diagnostic.is_err());
+}
+
+#[test]
+fn test_parse_else_no_left_brace() {
+    let filename = DIR.to_owned() + "if_else_no_brace.tank";
+    let mut parser = setup_parser(filename);
+
+    parser.parse();
+
+    assert!(parser.diagnostic.is_err());
+}
+
+#[test]
+fn test_parse_if_logical_precedence() {
+    let filename = DIR.to_owned() + "if_logical.tank";
+    let mut parser = setup_parser(filename);
+
+    parser.parse();
+
+    assert!(!parser.diagnostic.is_err());
+
+    // '&&' binds tighter than '||', so the condition is 'x > 1 || (!y && (...))'
+    let (op, gt, and_expr) = binary(if_parts(&parser.root.nodes[0]).0);
+    assert_eq!(op, BinaryOp::Or);
+    assert_eq!(binary(gt).0, BinaryOp::Gt);
+
+    let (op, not_expr, group) = binary(and_expr);
+    assert_eq!(op, BinaryOp::And);
+
+    match *not_expr {
+        Expr::Unary { op: UnaryOp::Not, ref operand, .. } => assert_eq!(var_name(operand), "y"),
+        _ => panic!("Expected a negated condition, found {:?}", not_expr)
+    }
+
+    // The parens group the inner '||'.
+    let (op, lt, equals) = binary(group);
+    assert_eq!(op, BinaryOp::Or);
+    assert_eq!(binary(lt).0, BinaryOp::Lt);
+    assert_eq!(binary(equals).0, BinaryOp::EqualsEquals);
+}
+
+#[test]
+fn test_parse_for_valid() {
+    let filename = DIR.to_owned() + "for_valid.tank";
+    let mut parser = setup_parser(filename);
+
+    parser.parse();
+
+    assert!(!parser.diagnostic.is_err());
+
+    match parser.root.nodes[0] {
+        Node::For { ref var, ref var_type, ref iter, ref body, .. } => {
+            assert_eq!(var.name, "item".to_owned());
+            assert_eq!(*var_type, VarType::Str);
+            assert_eq!(iter.name, "items".to_owned());
+            assert_eq!(kind(&body.nodes[0]), "Element");
+        },
+        ref other => panic!("Expected a for loop, found {:?}", other)
+    }
+
+    // The loop variable is only bound during generation.
+    assert!(parser.symbol_table.get("item".to_owned()).is_none());
+
+    // Elements after the loop are still parsed.
+    let after = parser.root.nodes.iter()
+        .find(|node| kind(node) == "Element")
+        .unwrap();
+    assert_eq!(contents(after), "after");
+}
+
+#[test]
+fn test_parse_for_no_in() {
+    let filename = DIR.to_owned() + "for_no_in.tank";
+    let mut parser = setup_parser(filename);
+
+    parser.parse();
+
+    assert!(parser.diagnostic.is_err());
+}
+
+#[test]
+fn test_parse_for_typo_in() {
+    let filename = DIR.to_owned() + "for_typo_in.tank";
+    let mut parser = setup_parser(filename);
+
+    parser.parse();
+
+    // The typo is reported once, and the loop is skipped rather than causing more errors.
+    let reports = parser.diagnostic.reports();
+    assert_eq!(reports.len(), 1);
+    assert_eq!(reports[0].message, "Expected 'in' at for loop");
+    assert_eq!(reports[0].help, vec!["write loops as 'for item: type in items'".to_owned()]);
+
+    let root: Vec<&str> = parser.root.nodes.iter().map(kind).collect();
+    assert_eq!(root, vec!["For", "Error", "Element"]);
+}
+
+#[test]
+fn test_parse_lexer_error() {
+    let filename = DIR.to_owned() + "lexer_error.tank";
+    let mut parser = setup_parser(filename);
+
+    parser.parse();
+
+    // The lexer's description of the error is reported as it is.
+    assert_eq!(parser.diagnostic.errors(), vec!["Unexpected character '$'".to_owned()]);
 }
 
 #[test]
@@ -100,7 +388,7 @@ fn test_parse_element_no_left_paren() {
 
     parser.parse();
 
-    assert_eq!(parser.diagnostic.is_err(), true);
+    assert!(parser.diagnostic.is_err());
 }
 
 #[test]
@@ -110,7 +398,7 @@ fn test_parse_element_no_right_paren() {
 
     parser.parse();
 
-    assert_eq!(parser.diagnostic.is_err(), true);
+    assert!(parser.diagnostic.is_err());
 }
 
 #[test]
@@ -120,25 +408,17 @@ fn test_parse_element_no_attribute_list() {
 
     parser.parse();
 
-    let ast = parser.root;
-    assert_eq!(ast.children.len(), 2);
-
-    let el_ast = &ast.children[0];
-    assert_eq!(el_ast.ast_type, AstType::Element);
-    assert_eq!(el_ast.children.len(), 3);
-
-    let el_name_ast = &el_ast.children[0];
-    assert_eq!(el_name_ast.ast_type, AstType::ElementName);
-    assert_eq!(el_name_ast.val, "div".to_owned());
+    let template = parser.root;
+    assert_eq!(template.nodes.len(), 1);
 
-    let attr_list_ast = &el_ast.children[1];
-    assert_eq!(attr_list_ast.ast_type, AstType::AttrList);
-    assert_eq!(attr_list_ast.children.len(), 0);
+    let element = &template.nodes[0];
+    assert_eq!(kind(element), "Element");
+    assert_eq!(el_name(element), "div");
+    assert!(el_attrs(element).is_empty());
 
-    let el_contents_ast = &el_ast.children[2];
-    assert_eq!(el_contents_ast.ast_type, AstType::Contents);
-    assert_eq!(el_contents_ast.children.len(), 1);
-    assert_eq!(el_contents_ast.children[0].val, "divContents".to_owned());
+    assert_eq!(el_body(element).len(), 1);
+    assert_eq!(text_parts(element).len(), 1);
+    assert_eq!(contents(element), "divContents");
 }
 
 #[test]
@@ -148,37 +428,111 @@ fn test_parse_nested_elements() {
 
     parser.parse();
 
-    let ast = parser.root;
-    let el_ast = &ast.children[0];
-    assert_eq!(el_ast.ast_type, AstType::Element);
-    assert_eq!(el_ast.children.len(), 3);
+    let template = parser.root;
+    let element = &template.nodes[0];
+    assert_eq!(kind(element), "Element");
+    assert_eq!(el_name(element), "div");
+    assert_eq!(el_body(element).len(), 1);
+
+    let nested_element = &el_body(element)[0];
+    assert_eq!(kind(nested_element), "Element");
+    assert_eq!(el_name(nested_element), "p");
+
+    assert_eq!(text_parts(nested_element).len(), 1);
+    assert_eq!(contents(nested_element), "pContents");
+}
+
+#[test]
+fn test_parse_element_with_bare_attributes() {
+    let filename = DIR.to_owned() + "el_attr_list_no_colon.tank";
+    let mut parser = setup_parser(filename);
+
+    parser.parse();
+
+    assert!(!parser.diagnostic.is_err());
 
-    let first_element = &el_ast.children[0];
-    assert_eq!(first_element.ast_type, AstType::ElementName);
-    assert_eq!(first_element.val, "div".to_owned());
+    // Attributes without a value have no value expression.
+    let attrs = attr_pairs(&parser.root.nodes[0]);
+    assert_eq!(attrs, vec![("class", None), ("className", None)]);
+}
 
-    let element_contents = &el_ast.children[2];
-    assert_eq!(element_contents.ast_type, AstType::Element);
-    assert_eq!(element_contents.children.len(), 3);
+#[test]
+fn test_parse_element_with_attribute_list_missing_name() {
+    let filename = DIR.to_owned() + "el_attr_list_no_name.tank";
+    let mut parser = setup_parser(filename);
 
-    let nested_element = &element_contents.children[0];
-    assert_eq!(nested_element.ast_type, AstType::ElementName);
-    assert_eq!(nested_element.val, "p".to_owned());
+    parser.parse();
 
-    let nested_element_contents = &element_contents.children[2];
-    assert_eq!(nested_element_contents.ast_type, AstType::Contents);
-    assert_eq!(nested_element_contents.children.len(), 1);
-    assert_eq!(nested_element_contents.children[0].val, "pContents".to_owned());
+    assert!(parser.diagnostic.is_err());
 }
 
 #[test]
-fn test_parse_element_with_attribute_list_missing_colon() {
-    let filename = DIR.to_owned() + "el_attr_list_no_colon.tank";
+fn test_parse_boolean_attributes() {
+    let filename = DIR.to_owned() + "el_attr_boolean.tank";
+    let mut parser = setup_parser(filename);
+
+    parser.parse();
+
+    assert!(!parser.diagnostic.is_err());
+
+    let attrs: Vec<(&str, &str)> = attr_pairs(&parser.root.nodes[0]).into_iter()
+        .map(|(name, value)| (name, expr_kind(value)))
+        .collect();
+    assert_eq!(attrs, vec![("type", "Ident"),
+                           ("checked", "Var"),
+                           ("required", "None"),
+                           ("disabled", "Interpolation"),
+                           ("autofocus", "None")]);
+}
+
+#[test]
+fn test_parse_attribute_expressions() {
+    let filename = DIR.to_owned() + "el_attr_expressions.tank";
     let mut parser = setup_parser(filename);
 
     parser.parse();
 
-    assert_eq!(parser.diagnostic.is_err(), true);
+    assert!(!parser.diagnostic.is_err());
+
+    let attrs = attr_pairs(&parser.root.nodes[0]);
+    let kinds: Vec<(&str, &str)> = attrs.iter()
+        .map(|&(name, value)| (name, expr_kind(value)))
+        .collect();
+    assert_eq!(kinds, vec![("href", "Var"),
+                           ("title", "InterpolatedString"),
+                           ("class", "Conditional"),
+                           ("id", "Conditional")]);
+
+    let parts = match attrs[1].1 {
+        Some(Expr::InterpolatedString { parts, .. }) => parts,
+        other => panic!("Expected an interpolated string, found {:?}", other)
+    };
+    let part_kinds: Vec<&str> = parts.iter().map(|part| expr_kind(Some(part))).collect();
+    assert_eq!(part_kinds, vec!["Str", "Interpolation", "Str"]);
+    assert_eq!(literal(&parts[0]), "Posts by ");
+    match parts[1] {
+        Expr::Interpolation { ref expr, .. } => assert_eq!(var_name(expr), "user.name"),
+        ref other => panic!("Expected an interpolation, found {:?}", other)
+    }
+    assert_eq!(literal(&parts[2]), "!");
+
+    match attrs[2].1 {
+        Some(&Expr::Conditional { ref cond, ref then, else_: Some(ref else_), .. }) => {
+            assert_eq!(expr_kind(Some(cond)), "Var");
+            assert_eq!(literal(then), "on");
+            assert_eq!(literal(else_), "off");
+        },
+        other => panic!("Expected a conditional with an else branch, found {:?}", other)
+    }
+
+    // Without an else branch, the conditional only has the condition and one value.
+    match attrs[3].1 {
+        Some(Expr::Conditional { cond, else_, .. }) => {
+            assert_eq!(expr_kind(Some(cond)), "Interpolation");
+            assert!(else_.is_none());
+        },
+        other => panic!("Expected a conditional, found {:?}", other)
+    }
 }
 
 #[test]
@@ -188,37 +542,187 @@ fn test_parse_element_with_valid_attribute_list() {
 
     parser.parse();
 
-    let ast = parser.root;
-    assert!(ast.children.len() > 0);
+    let template = parser.root;
+    assert!(!template.nodes.is_empty());
 
-    let first_element = &ast.children[0];
-    assert_eq!(first_element.ast_type, AstType::Element);
-    assert!(first_element.children.len() > 1);
+    let first_element = &template.nodes[0];
+    assert_eq!(kind(first_element), "Element");
+    assert_eq!(el_name(first_element), "div");
 
-    let element_name = &first_element.children[0];
-    assert_eq!(element_name.ast_type, AstType::ElementName);
-    assert_eq!(element_name.val, "div".to_owned());
+    let attrs = attr_pairs(first_element);
+    assert_eq!(attrs.len(), 1);
 
-    let attr_list = &first_element.children[1];
-    assert_eq!(attr_list.ast_type, AstType::AttrList);
-    assert_eq!(attr_list.children.len(), 2);
+    let (attr_name, attr_val) = attrs[0];
+    assert_eq!(attr_name, "class");
+    assert_eq!(var_name(attr_val.unwrap()), "className");
+}
 
-    let attr_name = &attr_list.children[0];
-    let attr_val = &attr_list.children[1];
+#[test]
+fn test_parse_element_with_string_literal_attributes() {
+    let filename = DIR.to_owned() + "el_attr_string_literal.tank";
+    let mut parser = setup_parser(filename);
+
+    parser.parse();
+
+    assert!(!parser.diagnostic.is_err());
+
+    let first_element = &parser.root.nodes[0];
+    let attrs = attr_pairs(first_element);
+    assert_eq!(attrs.len(), 2);
 
-    assert_eq!(attr_name.val, "class".to_owned());
-    assert_eq!(attr_val.val, "className".to_owned());
+    let id_val = attrs[0].1.unwrap();
+    assert_eq!(expr_kind(Some(id_val)), "Str");
+    assert_eq!(literal(id_val), "my-div");
+
+    let class_val = attrs[1].1.unwrap();
+    assert_eq!(expr_kind(Some(class_val)), "Str");
+    assert_eq!(literal(class_val), "styled div");
+
+    assert_eq!(contents(first_element), "Hello, world!");
+}
+
+#[test]
+fn test_parse_element_with_unterminated_string_literal() {
+    let filename = DIR.to_owned() + "el_attr_unterminated_string.tank";
+    let mut parser = setup_parser(filename);
+
+    parser.parse();
+
+    assert!(parser.diagnostic.is_err());
+}
+
+#[test]
+fn test_parse_comments() {
+    let filename = DIR.to_owned() + "comments.tank";
+    let mut parser = setup_parser(filename);
+
+    parser.parse();
+
+    assert!(!parser.diagnostic.is_err());
+
+    let template = parser.root;
+    assert_eq!(template.nodes.len(), 2);
+
+    match template.nodes[0] {
+        Node::Comment { ref text, .. } => assert_eq!(text, "Reaches the output"),
+        ref other => panic!("Expected a comment, found {:?}", other)
+    }
+
+    let element = &template.nodes[1];
+    assert_eq!(kind(element), "Element");
+
+    assert_eq!(text_parts(element).len(), 1);
+    assert_eq!(contents(element), "divContents");
+}
+
+#[test]
+fn test_parse_contents_text() {
+    let filename = DIR.to_owned() + "contents_text.tank";
+    let mut parser = setup_parser(filename);
+
+    parser.parse();
+
+    assert!(!parser.diagnostic.is_err());
+
+    let parts = text_parts(&parser.root.nodes[0]);
+    assert_eq!(parts.len(), 3);
+
+    match (&parts[0], &parts[1], &parts[2]) {
+        (TextPart::Text { text: first, .. },
+         TextPart::Value(value),
+         TextPart::Text { text: last, .. }) => {
+            assert_eq!(first, "Hello,   world! Don't panic, naïve café ");
+            assert_eq!(expr_kind(Some(value)), "Var");
+            assert_eq!(var_name(value), "name");
+            assert_eq!(last, "! 100% & more");
+        },
+        other => panic!("Expected text around a variable, found {:?}", other)
+    }
+}
+
+#[test]
+fn test_parse_contents_followed_by_element() {
+    let filename = DIR.to_owned() + "contents_sibling.tank";
+    let mut parser = setup_parser(filename);
+
+    parser.parse();
+
+    assert!(!parser.diagnostic.is_err());
+
+    let first = &parser.root.nodes[0];
+    assert_eq!(el_name(first), "div");
+    assert_eq!(contents(first), "first (not an element)");
+
+    let second = &parser.root.nodes[1];
+    assert_eq!(el_name(second), "p");
+    assert_eq!(contents(second), "second");
+}
+
+#[test]
+fn test_parse_contents_unclosed_paren() {
+    let filename = DIR.to_owned() + "contents_unclosed_paren.tank";
+    let mut parser = setup_parser(filename);
+
+    parser.parse();
+
+    assert!(!parser.diagnostic.is_err());
+
+    // An unclosed '(' in text doesn't make the next line part of an attribute list.
+    let first = &parser.root.nodes[0];
+    assert_eq!(el_name(first), "p");
+    assert_eq!(contents(first), "call me (maybe");
+
+    let second = &parser.root.nodes[1];
+    assert_eq!(el_name(second), "h1");
+    assert_eq!(contents(second), "x");
 }
 
 #[test]
-#[should_panic(expected = "tank: Invalid ast type found in symbol table")]
 fn test_parse_assign_no_type() {
     let filename = DIR.to_owned() + "assign_no_type.tank";
     let mut parser = setup_parser(filename);
 
     parser.parse();
 
-    assert_eq!(parser.diagnostic.is_err(), true);
+    assert!(parser.diagnostic.is_err());
+}
+
+#[test]
+fn test_parse_unknown_type_and_large_int() {
+    let filename = DIR.to_owned() + "unknown_type.tank";
+    let mut parser = setup_parser(filename);
+
+    parser.parse();
+
+    let messages: Vec<&str> = parser.diagnostic.reports().iter().map(|report| report.message.as_ref()).collect();
+    assert_eq!(messages, vec!["Unknown type 'integer' declared for 'x'",
+                              "Unknown type 'number' declared for 'item'",
+                              "Integer 99999999999999999999 is too large"]);
+    assert_eq!(parser.diagnostic.reports()[0].help,
+               vec!["the types are int, float, bool, string, list, map".to_owned()]);
+
+    // Declarations without a valid type are kept as errors, and numbers are parsed.
+    let root: Vec<&str> = parser.root.nodes.iter().map(kind).collect();
+    assert_eq!(root, vec!["Error", "Error", "Let", "Let"]);
+
+    match parser.root.nodes[3] {
+        Node::Let { var_type, value: Expr::Number { val, .. }, .. } => {
+            assert_eq!(var_type, VarType::Float);
+            assert_eq!(val, Number::Float(1.5));
+        },
+        ref other => panic!("Expected a float declaration, found {:?}", other)
+    }
+}
+
+#[test]
+fn test_parse_assign_redeclared() {
+    let filename = DIR.to_owned() + "assign_redeclared.tank";
+    let mut parser = setup_parser(filename);
+
+    parser.parse();
+
+    assert!(parser.diagnostic.is_err());
+    assert!(parser.diagnostic.errors()[0].contains("Redeclared symbol 'count' found"));
 }
 
 #[test]
@@ -228,18 +732,104 @@ fn test_parse_assign_valid() {
 
     parser.parse();
 
-    let element = &parser.root.children[0];
-    assert_eq!(element.ast_type, AstType::Element);
+    match parser.root.nodes[0] {
+        Node::Let { ref var, ref var_type, ref value, .. } => {
+            assert_eq!(var.name, "x".to_owned());
+            assert_eq!(*var_type, VarType::Int);
+            assert_eq!(literal(value), "10");
+        },
+        ref other => panic!("Expected a variable declaration, found {:?}", other)
+    }
+}
+
+#[test]
+fn test_parse_assign_arithmetic_precedence() {
+    let filename = DIR.to_owned() + "assign_arithmetic.tank";
+    let mut parser = setup_parser(filename);
+
+    parser.parse();
+
+    assert!(!parser.diagnostic.is_err());
+
+    // The value is '(-2) + (3 * (4 - 1) % 5)'
+    let value = match parser.root.nodes[0] {
+        Node::Let { ref value, .. } => value,
+        ref other => panic!("Expected a variable declaration, found {:?}", other)
+    };
+
+    let (op, negate, modulo) = binary(value);
+    assert_eq!(op, BinaryOp::Plus);
+    assert!(matches!(*negate, Expr::Unary { op: UnaryOp::Negate, .. }));
+
+    // '*' and '%' have the same precedence, and group to the left.
+    let (op, multiply, five) = binary(modulo);
+    assert_eq!(op, BinaryOp::Modulo);
+    assert_eq!(literal(five), "5");
+
+    let (op, _, minus) = binary(multiply);
+    assert_eq!(op, BinaryOp::Multiply);
+    assert_eq!(binary(minus).0, BinaryOp::Minus);
+}
+
+#[test]
+fn test_parse_minus_without_spaces() {
+    let filename = DIR.to_owned() + "expr_minus.tank";
+    let mut parser = setup_parser(filename);
+
+    parser.parse();
+
+    assert!(!parser.diagnostic.is_err());
+
+    // In an expression, 'x-1' is a subtraction rather than a name containing a '-'.
+    match parser.root.nodes[2] {
+        Node::Let { ref value, .. } => {
+            let (op, lhs, rhs) = binary(value);
+            assert_eq!(op, BinaryOp::Minus);
+            assert_eq!(var_name(lhs), "x");
+            assert_eq!(literal(rhs), "1");
+        },
+        ref other => panic!("Expected a variable declaration, found {:?}", other)
+    }
+
+    match text_parts(&parser.root.nodes[1])[0] {
+        TextPart::Value(Expr::Interpolation { ref expr, .. }) => assert_eq!(binary(expr).0, BinaryOp::Minus),
+        ref other => panic!("Expected an interpolation, found {:?}", other)
+    }
+
+    // Names outside of expressions can still contain a '-', even straight after one.
+    assert_eq!(el_name(&parser.root.nodes[3]), "data-list");
+}
+
+#[test]
+fn test_parse_interpolation() {
+    let filename = DIR.to_owned() + "interpolation.tank";
+    let mut parser = setup_parser(filename);
+
+    parser.parse();
 
-    let assign = &element.children[0];
-    assert_eq!(assign.ast_type, AstType::AssignExpr);
-    assert_eq!(assign.children.len(), 2);
+    assert!(!parser.diagnostic.is_err());
 
-    let ident = &assign.children[0];
-    let val = &assign.children[1];
+    let element = &parser.root.nodes[0];
 
-    assert_eq!(ident.val, "x".to_owned());
-    assert_eq!(val.val, "10".to_owned());
+    match attr_pairs(element)[0].1 {
+        Some(Expr::Interpolation { expr, .. }) => assert_eq!(binary(expr).0, BinaryOp::Multiply),
+        other => panic!("Expected an interpolation, found {:?}", other)
+    }
+
+    let parts = text_parts(element);
+    let kinds: Vec<&str> = parts.iter()
+        .map(|part| match *part {
+            TextPart::Value(ref value) => expr_kind(Some(value)),
+            _ => "Text"
+        })
+        .collect();
+    assert_eq!(kinds, vec!["Text", "Interpolation", "Text", "Interpolation", "Text"]);
+    assert!(matches!(parts[2], TextPart::Text { ref text, .. } if text == ", not "));
+
+    match parts[3] {
+        TextPart::Value(Expr::Interpolation { ref expr, .. }) => assert_eq!(expr_kind(Some(expr)), "Ident"),
+        ref other => panic!("Expected an interpolation, found {:?}", other)
+    }
 }
 
 #[test]
@@ -249,12 +839,16 @@ fn test_parse_variable_value() {
 
     parser.parse();
 
-    let element = &parser.root.children[0];
-    assert_eq!(element.ast_type, AstType::Element);
+    let element = &parser.root.nodes[0];
+    assert_eq!(kind(element), "Element");
 
-    let var = &element.children[2];
-    assert_eq!(var.ast_type, AstType::VariableValue);
-    assert_eq!(var.val, "myVar".to_owned());
+    let parts = text_parts(element);
+    assert_eq!(parts.len(), 1);
+
+    match parts[0] {
+        TextPart::Value(Expr::Var(ref var)) => assert_eq!(var.name, "myVar".to_owned()),
+        ref other => panic!("Expected a variable, found {:?}", other)
+    }
 }
 
 #[test]
@@ -264,9 +858,10 @@ fn test_parse_include_no_contents() {
 
     parser.parse();
 
-    let include = &parser.root.children[0];
-    assert_eq!(include.ast_type, AstType::Include);
-    assert_eq!(include.val, "includedFile".to_owned());
+    match parser.root.nodes[0] {
+        Node::Include { ref path, .. } => assert_eq!(path, "includedFile"),
+        ref other => panic!("Expected an include, found {:?}", other)
+    }
 }
 
 #[test]
@@ -276,10 +871,334 @@ fn test_parse_include_in_contents() {
 
     parser.parse();
 
-    let element = &parser.root.children[0];
-    assert_eq!(element.ast_type, AstType::Element);
+    let element = &parser.root.nodes[0];
+    assert_eq!(kind(element), "Element");
+
+    let parts = text_parts(element);
+    assert_eq!(parts.len(), 1);
+
+    match parts[0] {
+        TextPart::Include { ref path, .. } => assert_eq!(path, "includedFile"),
+        ref other => panic!("Expected an include, found {:?}", other)
+    }
+}
+
+#[test]
+fn test_parse_spans() {
+    let filename = DIR.to_owned() + "spans.tank";
+    let mut parser = setup_parser(filename);
+
+    parser.parse();
+
+    assert!(!parser.diagnostic.is_err());
+
+    let assign = &parser.root.nodes[0];
+    assert_eq!(assign.span(), Span::new(0, 19, 1, 1));
+
+    match *assign {
+        Node::Let { ref var, ref value, .. } => {
+            assert_eq!(var.span, Span::new(4, 9, 1, 5));
+            assert_eq!(value.span(), Span::new(17, 19, 1, 18));
+        },
+        ref other => panic!("Expected a variable declaration, found {:?}", other)
+    }
+
+    let element = &parser.root.nodes[1];
+    assert_eq!(element.span(), Span::new(20, 55, 2, 1));
+
+    match *element {
+        Node::Element { ref name, .. } => assert_eq!(name.span, Span::new(20, 21, 2, 1)),
+        ref other => panic!("Expected an element, found {:?}", other)
+    }
+
+    assert_eq!(attr_pairs(element)[0].1.unwrap().span(), Span::new(30, 37, 2, 11));
+
+    let contents = &el_body(element)[0];
+    assert_eq!(contents.span(), Span::new(42, 55, 2, 23));
+
+    // The variable span covers both the '%' and the name.
+    let var = &text_parts(element)[1];
+    assert!(matches!(*var, TextPart::Value(Expr::Var(..))));
+    assert_eq!(var.span(), Span::new(49, 54, 2, 30));
+}
+
+#[test]
+fn test_parse_variable_path() {
+    let filename = DIR.to_owned() + "variable_path.tank";
+    let mut parser = setup_parser(filename);
+
+    parser.parse();
+
+    assert!(!parser.diagnostic.is_err());
+
+    let element = &parser.root.nodes[0];
+
+    let attr_val = attr_pairs(element)[0].1.unwrap();
+    assert_eq!(expr_kind(Some(attr_val)), "Var");
+    assert_eq!(var_name(attr_val), "posts[0].url");
+
+    let parts: Vec<&str> = text_parts(element).iter()
+        .map(|part| match *part {
+            TextPart::Text { ref text, .. } => text.as_ref(),
+            TextPart::Value(ref value) => var_name(value),
+            TextPart::Include { ref path, .. } => path.as_ref()
+        })
+        .collect();
+
+    // The trailing '.' ends the sentence, so it is left as text.
+    assert_eq!(parts, vec!["posts[0].title", ", by ", "site.author.name", "."]);
+}
+
+#[test]
+fn test_parse_element_children() {
+    let filename = DIR.to_owned() + "el_children.tank";
+    let mut parser = setup_parser(filename);
+
+    parser.parse();
+
+    assert!(!parser.diagnostic.is_err());
+
+    // The div and the span after it.
+    assert_eq!(parser.root.nodes.len(), 2);
+
+    let div = &parser.root.nodes[0];
+    assert_eq!(el_name(div), "div");
+
+    // Each child in the order written.
+    let children: Vec<&str> = el_body(div).iter().map(kind).collect();
+    assert_eq!(children, vec!["Element", "Element", "If", "Element"]);
+
+    assert_eq!(contents(&el_body(div)[0]), "a");
+    assert_eq!(contents(&el_body(div)[1]), "b");
+
+    // A nested element with a block of its own keeps all of its children too.
+    let ul = &el_body(div)[3];
+    assert_eq!(el_name(ul), "ul");
+    assert_eq!(el_body(ul).len(), 2);
+
+    let span = &parser.root.nodes[1];
+    assert_eq!(el_name(span), "span");
+}
+
+#[test]
+fn test_parse_block_unclosed() {
+    let filename = DIR.to_owned() + "el_block_unclosed.tank";
+    let mut parser = setup_parser(filename);
+
+    parser.parse();
+
+    assert!(parser.diagnostic.is_err());
+}
+
+#[test]
+fn test_parse_void_elements() {
+    let filename = DIR.to_owned() + "void_elements.tank";
+    let mut parser = setup_parser(filename);
+
+    parser.parse();
+
+    assert!(!parser.diagnostic.is_err());
+
+    let form = &parser.root.nodes[0];
+    let children: Vec<(&str, &str)> = el_body(form).iter()
+        .map(|node| (kind(node), el_name(node)))
+        .collect();
+    assert_eq!(children, vec![("VoidElement", "input"),
+                              ("VoidElement", "br"),
+                              ("VoidElement", "circle"),
+                              ("Element", "button")]);
+
+    let input = &el_body(form)[0];
+    assert_eq!(el_attrs(input).len(), 2);
+
+    let hr = &parser.root.nodes[1];
+    assert_eq!(kind(hr), "VoidElement");
+}
+
+#[test]
+fn test_parse_void_element_with_contents() {
+    let filename = DIR.to_owned() + "void_with_contents.tank";
+    let mut parser = setup_parser(filename);
+
+    parser.parse();
+
+    assert!(parser.diagnostic.is_err());
+}
+
+#[test]
+fn test_parse_doctype_and_raw_block() {
+    let filename = DIR.to_owned() + "doctype_raw.tank";
+    let mut parser = setup_parser(filename);
+
+    parser.parse();
+
+    assert!(!parser.diagnostic.is_err());
+
+    match parser.root.nodes[0] {
+        Node::Doctype { ref doc_type, .. } => assert_eq!(doc_type, "html"),
+        ref other => panic!("Expected a doctype, found {:?}", other)
+    }
+
+    let html = &parser.root.nodes[1];
+    match el_body(html)[0] {
+        Node::Raw { html: ref raw, .. } => {
+            assert_eq!(raw, "        <svg viewBox=\"0 0 10 10\"><style>.a { fill: red; }</style></svg>\n");
+        },
+        ref other => panic!("Expected a raw block, found {:?}", other)
+    }
+
+    // The element after the raw block is parsed as normal.
+    assert_eq!(el_name(&el_body(html)[1]), "p");
+}
+
+#[test]
+fn test_parse_raw_block_unterminated() {
+    let filename = DIR.to_owned() + "raw_unterminated.tank";
+    let mut parser = setup_parser(filename);
+
+    parser.parse();
+
+    assert!(parser.diagnostic.is_err());
+}
+
+#[test]
+fn test_parse_recovers_from_errors() {
+    let filename = DIR.to_owned() + "recovery.tank";
+    let mut parser = setup_parser(filename);
+
+    parser.parse();
+
+    // Each independent error is reported once, rather than stopping at the first.
+    let lines: Vec<usize> = parser.diagnostic.reports().iter()
+        .map(|report| report.span.unwrap().line)
+        .collect();
+    assert_eq!(lines, vec![1, 2, 4, 6]);
+
+    let root: Vec<&str> = parser.root.nodes.iter().map(kind).collect();
+    assert_eq!(root, vec!["Element",
+                          "Element", "Error",
+                          "Element",
+                          "Error",
+                          "Element",
+                          "Element"]);
+
+    // The error in the attribute list is recovered from at its ')'.
+    let div = &parser.root.nodes[0];
+    assert!(matches!(el_attrs(div)[1], Attr::Error { .. }));
+    assert_eq!(contents(div), "one");
+
+    // The skipped 'two' is covered by the Error node following its element.
+    let error = &parser.root.nodes[2];
+    assert_eq!(error.span(), Span::new(23, 26, 2, 6));
+
+    // Elements following the errors are parsed as normal.
+    assert_eq!(el_name(&parser.root.nodes[3]), "span");
+    assert_eq!(el_name(&parser.root.nodes[6]), "footer");
+
+    let ul = &parser.root.nodes[5];
+    assert!(matches!(el_attrs(&el_body(ul)[0])[0], Attr::Error { .. }));
+    assert_eq!(el_name(&el_body(ul)[1]), "li");
+    assert_eq!(contents(&el_body(ul)[1]), "five");
+}
+
+#[test]
+fn test_parse_recovers_in_attribute_list() {
+    let filename = DIR.to_owned() + "recovery_attr_list.tank";
+    let mut parser = setup_parser(filename);
+
+    parser.parse();
+
+    let errors = parser.diagnostic.errors();
+    assert_eq!(errors, vec!["Expected an attribute name, found Equals".to_owned(),
+                            "Expected an attribute name, found Arrow".to_owned()]);
+
+    // The list carries on with the attribute on the next line.
+    let attrs: Vec<(&str, &str)> = attr_pairs(&parser.root.nodes[0]).into_iter()
+        .map(|(name, value)| (name, expr_kind(value)))
+        .collect();
+    assert_eq!(attrs, vec![("id", "Str"), ("Error", "None"), ("class", "Str")]);
+
+    // The unclosed list ends at the element on the next line.
+    let p = &parser.root.nodes[3];
+    assert_eq!(el_name(p), "p");
+    assert_eq!(contents(p), "three");
+}
+
+#[test]
+fn test_parse_source_order() {
+    let filename = DIR.to_owned() + "source_order.tank";
+    let mut parser = setup_parser(filename);
+
+    parser.parse();
+
+    assert!(!parser.diagnostic.is_err());
+
+    // Top level nodes are in the template in the order they were written.
+    let root: Vec<(&str, usize)> = parser.root.nodes.iter()
+        .map(|node| (kind(node), node.span().line))
+        .collect();
+    assert_eq!(root, vec![("Doctype", 1),
+                          ("Comment", 2),
+                          ("Let", 3),
+                          ("Element", 4),
+                          ("If", 5),
+                          ("For", 9),
+                          ("Include", 12),
+                          ("VoidElement", 13),
+                          ("Raw", 14),
+                          ("Element", 15)]);
+
+    // Nested elements keep their order too.
+    let if_block = if_parts(&parser.root.nodes[4]).1;
+    let paragraphs: Vec<&str> = if_block.nodes.iter().map(contents).collect();
+    assert_eq!(paragraphs, vec!["first", "second"]);
+
+    let section = &parser.root.nodes[9];
+    let headings: Vec<&str> = el_body(section).iter().map(el_name).collect();
+    assert_eq!(headings, vec!["h2", "h3", "h4"]);
+}
+
+/// Collects the elements and variables of a template, in the order they are visited.
+#[derive(Default)]
+struct Collector {
+    elements: Vec<String>,
+    vars: Vec<String>
+}
+
+impl Visitor for Collector {
+    fn visit_node(&mut self, node: &Node) {
+        if !el_name(node).is_empty() {
+            self.elements.push(el_name(node).to_owned());
+        }
+
+        walk_node(self, node);
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        if let Expr::Var(ref var) = *expr {
+            self.vars.push(var.name.clone());
+        }
+
+        walk_expr(self, expr);
+    }
+}
+
+#[test]
+fn test_visitor_walks_template() {
+    let filename = DIR.to_owned() + "source_order.tank";
+    let mut parser = setup_parser(filename);
+
+    parser.parse();
+
+    assert!(!parser.diagnostic.is_err());
+
+    let mut collector = Collector::default();
+    collector.visit_template(&parser.root);
+
+    // Every node is visited in source order, including those nested in blocks.
+    assert_eq!(collector.elements, vec!["h1", "p", "p", "li", "br", "section", "h2", "h3", "h4"]);
 
-    let include = &element.children[2];
-    assert_eq!(include.ast_type, AstType::Include);
-    assert_eq!(include.val, "includedFile".to_owned());
+    // Variables are found in text, conditions and nested expressions. The list of the
+    // for loop is an identifier, so isn't visited as an expression.
+    assert_eq!(collector.vars, vec!["title", "title", "item"]);
 }
@@ -0,0 +1,156 @@
+extern crate tank;
+
+use std::collections::BTreeMap;
+use tank::syntax::parser::Parser;
+use tank::syntax::symbol_table::SymbolTable;
+use tank::syntax::type_check::TypeChecker;
+use tank::syntax::value::Value;
+use tank::generate::gen::Gen;
+use tank::error::error_traits::Diagnostic;
+
+/// Parse and type check the given template source, returning the error messages found.
+fn check(source: &str, globals: SymbolTable) -> Vec<String> {
+    let mut parser = Parser::new(source.to_string(), globals.clone());
+    parser.parse();
+    assert!(!parser.diagnostic.is_err(), "failed to parse {:?}", source);
+
+    let mut checker = TypeChecker::new(globals);
+    checker.check(&parser.root);
+
+    checker.diagnostic.reports().iter().map(|report| report.message.clone()).collect()
+}
+
+/// Parse and generate the given template source without type checking it first, returning
+/// the html and the error messages from generating it.
+fn generate(source: &str) -> (String, Vec<String>) {
+    let mut parser = Parser::new(source.to_string(), SymbolTable::new());
+    parser.parse();
+    assert!(!parser.diagnostic.is_err(), "failed to parse {:?}", source);
+
+    let mut gen = Gen::from_writer(Vec::new(), parser.symbol_table.clone());
+    gen.output(&parser.root);
+
+    let errors = gen.diagnostic.reports().iter().map(|report| report.message.clone()).collect();
+    (String::from_utf8(gen.into_writer().unwrap()).unwrap(), errors)
+}
+
+fn config_table() -> SymbolTable {
+    let mut site = BTreeMap::new();
+    site.insert("title".to_owned(), Value::Str("Blog".to_owned()));
+
+    let mut post = BTreeMap::new();
+    post.insert("author".to_owned(), Value::Str("Ann".to_owned()));
+
+    let mut values = BTreeMap::new();
+    values.insert("site".to_owned(), Value::Map(site));
+    values.insert("posts".to_owned(), Value::List(vec![Value::Map(post)]));
+    values.insert("tags".to_owned(), Value::List(vec![Value::Str("rust".to_owned()),
+                                                      Value::Str("html".to_owned())]));
+    values.insert("count".to_owned(), Value::Int(3));
+    values.insert("ratio".to_owned(), Value::Float(1.5));
+
+    SymbolTable::from_existing_map(&values)
+}
+
+#[test]
+fn test_check_valid_template() {
+    let source = "let limit: int = 2\n\
+                  let scale: float = limit * 2\n\
+                  let name: string = tank\n\
+                  if count > limit && site.title == \"Blog\" { p () -> %site.title %{ scale / ratio } }\n\
+                  for post: map in posts { li (class: if %loop.first \"first\") -> %post.author %loop.index }\n\
+                  for tag: string in tags { a (href: \"/tags/%{ tag }\") -> %tag %name }\n";
+
+    assert_eq!(check(source, config_table()), Vec::<String>::new());
+}
+
+#[test]
+fn test_check_declared_types() {
+    let errors = check("let x: int = hello\nlet y: bool = 1 + 2\nlet z: float = 2\nlet w: int = 2.5\n",
+                       SymbolTable::new());
+
+    assert_eq!(errors, vec!["Type mismatch - 'x' is declared as int, but its value is string",
+                            "Type mismatch - 'y' is declared as bool, but its value is int",
+                            "Type mismatch - 'w' is declared as int, but its value is float"]);
+}
+
+#[test]
+fn test_check_comparisons() {
+    let errors = check("let x: int = 1\nif x > \"a\" { p () -> a }\nif x == ratio { p () -> b }\n",
+                       config_table());
+
+    // An int can be compared with a float, but not with a string.
+    assert_eq!(errors, vec!["Cannot compare int with string"]);
+}
+
+#[test]
+fn test_check_conditions_are_bools() {
+    let errors = check("let x: int = 1\nif x + 1 { p () -> a } else if !x { p () -> b }\nlet y: int = -\"a\"\n",
+                       SymbolTable::new());
+
+    assert_eq!(errors, vec!["Expected a bool, found int", "Expected a bool, found int", "Cannot negate string"]);
+}
+
+#[test]
+fn test_check_for_iterables() {
+    let errors = check("for n: int in count { p () -> %n }\n\
+                        for tag: int in tags { p () -> %tag }\n\
+                        for t: string in nothing { p () -> %t }\n",
+                       config_table());
+
+    assert_eq!(errors.len(), 3);
+    assert_eq!(errors[0], "'count' is not a list");
+    assert_eq!(errors[1], "Type mismatch - 'tag' is declared as int, but item 0 of tags is string");
+    assert!(errors[2].contains("'nothing' is not defined"), "{}", errors[2]);
+}
+
+#[test]
+fn test_check_undefined_variables() {
+    let errors = check("p () -> %missing\n\
+                        let x: int = y + 1\n\
+                        for tag: string in tags { p () -> %tag }\n\
+                        p (title: %tag) -> %site.titel\n",
+                       config_table());
+
+    // The loop variable is only defined inside the loop.
+    assert_eq!(errors.len(), 4);
+    assert!(errors[0].contains("'missing' is not defined"), "{}", errors[0]);
+    assert!(errors[1].contains("'y' is not defined"), "{}", errors[1]);
+    assert!(errors[2].contains("'tag' is not defined"), "{}", errors[2]);
+    assert!(errors[3].contains("titel"), "{}", errors[3]);
+}
+
+#[test]
+fn test_check_reports_spans() {
+    let source = "let x: int = \"one\"\np () -> ok\nif x == true { p () -> a }\n";
+    let mut parser = Parser::new(source.to_string(), SymbolTable::new());
+    parser.parse();
+
+    let mut checker = TypeChecker::new(SymbolTable::new());
+    checker.check(&parser.root);
+
+    // Every error is reported at once, each at the part of the template it refers to.
+    let spans: Vec<(usize, &str)> = checker.diagnostic.reports().iter()
+        .map(|report| report.span.unwrap())
+        .map(|span| (span.line, &source[span.start..span.end]))
+        .collect();
+    assert_eq!(spans, vec![(1, "\"one\""), (3, "x == true")]);
+}
+
+#[test]
+fn test_check_agrees_with_gen_on_bare_words() {
+    // 'later' is only declared after it is used, so both take it as the string "later".
+    let source = "let name: string = later\nlet later: int = 1\np () -> %name\n";
+    assert_eq!(check(source, SymbolTable::new()), Vec::<String>::new());
+    assert_eq!(generate(source), ("<p>\n  later\n</p>\n".to_owned(), Vec::new()));
+
+    let source = "let count: int = later\nlet later: int = 1\n";
+    let expected = vec!["Type mismatch - 'count' is declared as int, but its value is string".to_owned()];
+    assert_eq!(check(source, SymbolTable::new()), expected);
+    assert_eq!(generate(source), (String::new(), expected));
+
+    // Once it is declared, it is a variable to both.
+    let source = "let later: int = 1\nlet count: int = later\np () -> %count\n";
+    assert_eq!(check(source, SymbolTable::new()), Vec::<String>::new());
+    assert_eq!(generate(source), ("<p>\n  1\n</p>\n".to_owned(), Vec::new()));
+}